@@ -0,0 +1,287 @@
+use axum::extract::{Path, Request, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use log::{error, info, warn, LevelFilter};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use songbird::id::GuildId;
+use songbird::Songbird;
+use std::collections::{BTreeMap, BTreeSet};
+use std::num::NonZero;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+use crate::state::guild;
+use crate::state::session::GuildSnapshot;
+use crate::utils::config::{self, CONFIG};
+use crate::worker::drain;
+use crate::worker::types::QueuedTrack;
+
+/// Error body for admin requests.
+struct AdminError {
+    status: StatusCode,
+    message: String,
+}
+
+impl AdminError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        AdminError {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl IntoResponse for AdminError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+#[derive(Clone)]
+struct AdminState {
+    songbird: Option<Arc<Songbird>>,
+}
+
+impl AdminState {
+    async fn channel_id(&self, guild_id: NonZero<u64>) -> Option<NonZero<u64>> {
+        let call = self.songbird.as_ref()?.get(GuildId(guild_id))?;
+        let channel = call.lock().await.current_channel();
+        channel.map(|channel| channel.0)
+    }
+
+    /// Guilds with a voice call or any stored playback state.
+    async fn guild_ids(&self) -> BTreeSet<NonZero<u64>> {
+        let mut guild_ids: BTreeSet<NonZero<u64>> = guild::guild_ids().into_iter().collect();
+        if let Some(songbird) = &self.songbird {
+            guild_ids.extend(songbird.iter().map(|(guild_id, _)| guild_id.0));
+        }
+        guild_ids
+    }
+}
+
+#[derive(Serialize)]
+struct SessionSummary {
+    guild_id: String,
+    channel_id: Option<String>,
+    current_track: Option<String>,
+    queue_length: usize,
+    playing: bool,
+}
+
+#[derive(Serialize)]
+struct QueuedTrackDump {
+    query: String,
+    title: Option<String>,
+    artist: Option<String>,
+    isrc: Option<String>,
+    duration_ms: Option<u64>,
+}
+
+impl From<&QueuedTrack> for QueuedTrackDump {
+    fn from(track: &QueuedTrack) -> Self {
+        QueuedTrackDump {
+            query: track.source.query().to_string(),
+            title: track.title.clone(),
+            artist: track.artist.clone(),
+            isrc: track.isrc.clone(),
+            duration_ms: track.duration.map(|duration| duration.as_millis() as u64),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LogLevelUpdate {
+    level: Option<LevelFilter>,
+    #[serde(default)]
+    modules: BTreeMap<String, LevelFilter>,
+}
+
+#[derive(Deserialize)]
+struct NormalizationUpdate {
+    enabled: bool,
+}
+
+/// Rejects requests without the configured bearer token.
+async fn require_token(request: Request, next: Next) -> Response {
+    let expected = CONFIG.admin.token.as_deref().map(|token| format!("Bearer {}", token));
+    let provided = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if expected.is_none() || provided != expected.as_deref() {
+        return AdminError::new(StatusCode::UNAUTHORIZED, "Invalid admin token").into_response();
+    }
+    next.run(request).await
+}
+
+fn parse_guild_id(guild_id: &str) -> Result<NonZero<u64>, AdminError> {
+    guild_id
+        .parse()
+        .map_err(|_| AdminError::new(StatusCode::BAD_REQUEST, "Invalid guild id"))
+}
+
+async fn list_guilds(State(state): State<AdminState>) -> Json<Vec<SessionSummary>> {
+    let mut sessions = Vec::new();
+    for guild_id in state.guild_ids().await {
+        let snapshot = guild::snapshot(guild_id).await;
+        sessions.push(SessionSummary {
+            guild_id: guild_id.to_string(),
+            channel_id: state.channel_id(guild_id).await.map(|id| id.to_string()),
+            current_track: snapshot
+                .as_ref()
+                .and_then(|s| s.now_playing.as_ref())
+                .map(|track| {
+                    track
+                        .title
+                        .clone()
+                        .or_else(|| track.source_url.clone())
+                        .unwrap_or_else(|| track.stream_url.clone())
+                }),
+            queue_length: snapshot.as_ref().map(|s| s.queue.len()).unwrap_or(0),
+            playing: snapshot.is_some_and(|s| s.track.is_some()),
+        });
+    }
+    Json(sessions)
+}
+
+async fn dump_guild(
+    State(state): State<AdminState>,
+    Path(guild_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AdminError> {
+    let guild_id = parse_guild_id(&guild_id)?;
+    let channel_id = state.channel_id(guild_id).await;
+    let Some(GuildSnapshot {
+        volume,
+        normalization,
+        gain,
+        track,
+        paused,
+        now_playing,
+        queue,
+        is_playing,
+        loop_mode,
+        filters,
+    }) = guild::snapshot(guild_id).await
+    else {
+        if channel_id.is_none() {
+            return Err(AdminError::new(StatusCode::NOT_FOUND, "Unknown guild"));
+        }
+        return Ok(Json(json!({
+            "guild_id": guild_id.to_string(),
+            "channel_id": channel_id.map(|id| id.to_string()),
+        })));
+    };
+
+    let position_ms = match &track {
+        Some(track) => track
+            .get_info()
+            .await
+            .ok()
+            .map(|info| info.position.as_millis() as u64),
+        None => None,
+    };
+
+    Ok(Json(json!({
+        "guild_id": guild_id.to_string(),
+        "channel_id": channel_id.map(|id| id.to_string()),
+        "volume": volume,
+        "normalization": normalization,
+        "gain": gain,
+        "is_playing": is_playing,
+        "paused": paused,
+        "position_ms": position_ms,
+        "loop_mode": loop_mode,
+        "filters": filters,
+        "now_playing": now_playing,
+        "queue": queue.iter().map(QueuedTrackDump::from).collect::<Vec<_>>(),
+    })))
+}
+
+/// Leaves the voice channel and drops the queue without going through the
+/// worker pool, so it works even when the guild's jobs are stuck.
+async fn disconnect_guild(
+    State(state): State<AdminState>,
+    Path(guild_id): Path<String>,
+) -> Result<StatusCode, AdminError> {
+    let guild_id = parse_guild_id(&guild_id)?;
+    guild::clear_playback(guild_id).await;
+    if let Some(songbird) = &state.songbird {
+        if let Err(e) = songbird.remove(GuildId(guild_id)).await {
+            warn!("Admin disconnect of guild {}: {}", guild_id, e);
+        }
+    }
+    info!("Guild {} disconnected by an operator", guild_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Turns loudness normalization on or off for one guild, overriding
+/// `LOUDNESS_NORMALIZATION` from the guild's next track on.
+async fn set_normalization(
+    Path(guild_id): Path<String>,
+    Json(update): Json<NormalizationUpdate>,
+) -> Result<StatusCode, AdminError> {
+    let guild_id = parse_guild_id(&guild_id)?;
+    guild::set_normalization(guild_id, update.enabled)
+        .await
+        .map_err(|e| AdminError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    info!(
+        "Loudness normalization {} for guild {} by an operator",
+        if update.enabled { "enabled" } else { "disabled" },
+        guild_id
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_log_level(Json(update): Json<LogLevelUpdate>) -> Json<serde_json::Value> {
+    let runtime = config::set_log_levels(update.level, update.modules.into_iter().collect());
+    info!("Log level changed by an operator to {}", runtime.log_level);
+    Json(json!({
+        "level": runtime.log_level,
+        "modules": runtime.log_modules.into_iter().collect::<BTreeMap<_, _>>(),
+    }))
+}
+
+async fn start_drain() -> StatusCode {
+    if drain::start() {
+        info!("Drain mode started by an operator");
+    }
+    StatusCode::ACCEPTED
+}
+
+/// Serves the operator interface on `ADMIN_BIND_ADDRESS` when
+/// `ADMIN_ENABLED` is set. It is separate from the bot-facing protocol and
+/// every route needs the `ADMIN_TOKEN` bearer token.
+pub async fn start(songbird: Option<Arc<Songbird>>) {
+    let listener = match TcpListener::bind(&CONFIG.admin.bind_address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(
+                "Failed to bind admin interface to {}: {}",
+                CONFIG.admin.bind_address, e
+            );
+            return;
+        }
+    };
+    info!("Admin interface listening on {}", CONFIG.admin.bind_address);
+
+    let router = Router::new()
+        .route("/admin/guilds", get(list_guilds))
+        .route("/admin/guilds/:guild_id", get(dump_guild))
+        .route("/admin/guilds/:guild_id/disconnect", post(disconnect_guild))
+        .route("/admin/guilds/:guild_id/normalization", put(set_normalization))
+        .route("/admin/log-level", put(set_log_level))
+        .route("/admin/drain", post(start_drain))
+        .layer(middleware::from_fn(require_token))
+        .with_state(AdminState { songbird });
+
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, router).await {
+            error!("Admin interface stopped: {}", e);
+        }
+    });
+}
@@ -0,0 +1,106 @@
+use anyhow::{anyhow, Context, Result};
+use colored::Colorize;
+use serde_derive::Deserialize;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::timeout;
+
+use crate::transport::kafka::topic_partitions;
+use crate::utils::config::{self, Config, TransportKind};
+use crate::utils::constants::CHECK_TIMEOUT_SECONDS;
+
+const DISCORD_CURRENT_USER_URL: &str = "https://discord.com/api/v10/users/@me";
+
+#[derive(Deserialize)]
+struct DiscordUser {
+    id: String,
+    username: String,
+}
+
+async fn check_kafka(config: &Config) -> Result<String> {
+    let topic = &config.kafka.kafka_topic;
+    let wait = Duration::from_secs(CHECK_TIMEOUT_SECONDS);
+    let partitions = topic_partitions(&config.kafka.kafka_uri, topic, wait).await?;
+    Ok(format!("topic {} has {} partition(s)", topic, partitions))
+}
+
+async fn check_redis(redis_url: &str) -> Result<String> {
+    let client = redis::Client::open(redis_url).context("Invalid Redis URL")?;
+    let mut connection = client.get_multiplexed_async_connection().await?;
+    let reply: String = redis::cmd("PING").query_async(&mut connection).await?;
+    Ok(format!("PING answered {}", reply))
+}
+
+async fn check_discord(config: &Config) -> Result<String> {
+    let response = reqwest::Client::new()
+        .get(DISCORD_CURRENT_USER_URL)
+        .header(
+            "Authorization",
+            format!("Bot {}", config.config.discord_bot_token),
+        )
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Discord rejected the bot token ({})", response.status()));
+    }
+
+    let user: DiscordUser = response.json().await?;
+    if user.id != config.config.discord_bot_id.to_string() {
+        return Err(anyhow!(
+            "The token belongs to {} ({}), not DISCORD_BOT_ID {}",
+            user.username,
+            user.id,
+            config.config.discord_bot_id
+        ));
+    }
+    Ok(format!("logged in as {}", user.username))
+}
+
+/// Prints the outcome of one check, returning whether it passed.
+async fn report(name: &str, check: impl Future<Output = Result<String>>) -> bool {
+    let outcome = match timeout(Duration::from_secs(CHECK_TIMEOUT_SECONDS), check).await {
+        Ok(outcome) => outcome,
+        Err(_) => Err(anyhow!("timed out after {}s", CHECK_TIMEOUT_SECONDS)),
+    };
+
+    match outcome {
+        Ok(detail) => {
+            println!("{} {}: {}", "ok".green(), name, detail);
+            true
+        }
+        Err(e) => {
+            println!("{} {}: {:#}", "failed".red(), name, e);
+            false
+        }
+    }
+}
+
+/// Validates the configuration, then checks that every service it points at
+/// can be reached. Fails if anything is wrong.
+pub async fn run() -> Result<()> {
+    let config = match config::load() {
+        Ok(config) => config,
+        Err(errors) => {
+            for error in &errors {
+                println!("{} configuration: {}", "failed".red(), error);
+            }
+            return Err(anyhow!("{} configuration error(s)", errors.len()));
+        }
+    };
+    println!("{} configuration", "ok".green());
+
+    let mut passed = true;
+    if config.transport.kind == TransportKind::Kafka {
+        passed &= report("Kafka", check_kafka(&config)).await;
+    }
+    if let Some(redis_url) = &config.redis_url {
+        passed &= report("Redis", check_redis(redis_url)).await;
+    }
+    passed &= report("Discord", check_discord(&config)).await;
+
+    if passed {
+        Ok(())
+    } else {
+        Err(anyhow!("Some checks failed"))
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::{anyhow, Context, Result};
+use nanoid::nanoid;
+use std::sync::Arc;
+
+use crate::transport::kafka::KafkaTransport;
+use crate::transport::redis::RedisStreamTransport;
+use crate::transport::Transport;
+use crate::utils::config::{TransportKind, CONFIG};
+
+pub mod check;
+pub mod replay;
+pub mod send;
+pub mod tail;
+
+use self::replay::ReplayOptions;
+use self::send::SendOptions;
+use self::tail::TailOptions;
+
+pub const USAGE: &str = "\
+Usage: ravalink [--config <file>] [--set KEY=VALUE]... [command]
+
+Commands:
+  run                    Start the worker (the default)
+  check-config           Validate the configuration and test Kafka, Redis and Discord
+  send [options] <cmd>   Publish a test request to the request topic
+  tail [options]         Print responses and events from the topic as they arrive
+  replay-dead-letters    Push dead-lettered messages back onto their topic
+  help                   Show this message
+
+send options:
+  --guild <id>           Guild the request is for (required)
+  --channel <id>         Voice channel to include in the request
+  --json <message>       Publish a raw protocol message instead of <cmd>
+  <cmd> is one of connect, play <url>, search <query>, stop, pause, resume,
+  skip, shuffle, loop, volume <level>, seek <position>, playlists,
+  playlist-add <id> <track>, playlist-remove <id> <track>, playlist-load <id>,
+  playlist-clear <id>
+
+tail options:
+  --guild <id>           Only print messages for this guild
+  --all                  Also print requests and pings
+
+replay-dead-letters options:
+  --file <path>          Replay records from a JSON-lines file
+  --dry-run              Print what would be replayed
+  --limit <n>            Replay at most n records";
+
+pub enum Cli {
+    Run,
+    CheckConfig,
+    Send(SendOptions),
+    Tail(TailOptions),
+    ReplayDeadLetters(ReplayOptions),
+    Help,
+}
+
+impl Cli {
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut args = args.into_iter();
+        Ok(match args.next().as_deref() {
+            None | Some("run") => Cli::Run,
+            Some("check-config") => Cli::CheckConfig,
+            Some("send") => Cli::Send(SendOptions::from_args(args)?),
+            Some("tail") => Cli::Tail(TailOptions::from_args(args)?),
+            Some("replay-dead-letters") => Cli::ReplayDeadLetters(ReplayOptions::from_args(args)?),
+            Some("help" | "--help" | "-h") => Cli::Help,
+            Some(other) => return Err(anyhow!("Unknown command: {}", other)),
+        })
+    }
+}
+
+/// Parses the value following an option such as `--guild`.
+fn option_value<T: std::str::FromStr>(
+    option: &str,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<T> {
+    args.next()
+        .with_context(|| format!("{} needs a value", option))?
+        .parse()
+        .map_err(|_| anyhow!("{} has an invalid value", option))
+}
+
+/// Opens the configured transport on `topic` in a consumer group of the
+/// tool's own, so it never takes messages from the workers. With
+/// `from_start` it reads the whole topic, otherwise only new messages.
+async fn open_transport(topic: &str, group: &str, from_start: bool) -> Result<Arc<dyn Transport>> {
+    Ok(match CONFIG.transport.kind {
+        TransportKind::Kafka => Arc::new(
+            KafkaTransport::subscribe(
+                &CONFIG.kafka.kafka_uri,
+                group,
+                topic,
+                if from_start { "earliest" } else { "latest" },
+            )
+            .await?,
+        ),
+        TransportKind::Redis => {
+            let redis_url = CONFIG
+                .redis_url
+                .as_deref()
+                .context("REDIS_URL must be set to use the Redis transport")?;
+            Arc::new(
+                RedisStreamTransport::connect(
+                    redis_url,
+                    vec![topic.to_string()],
+                    group,
+                    &nanoid!(),
+                    if from_start { "0" } else { "$" },
+                )
+                .await?,
+            )
+        }
+    })
+}
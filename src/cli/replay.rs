@@ -0,0 +1,138 @@
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use ravalink_interconnect::protocol::Message;
+use std::time::Duration;
+use tokio::time::timeout;
+
+use crate::cli::open_transport;
+use crate::transport::dead_letter::DeadLetter;
+use crate::transport::Transport;
+use crate::utils::config::CONFIG;
+use crate::utils::constants::{DEAD_LETTER_REPLAY_GROUP, DEAD_LETTER_REPLAY_IDLE_SECONDS};
+
+pub struct ReplayOptions {
+    /// Replay records from a JSON-lines file of (possibly edited) dead
+    /// letters instead of reading the dead-letter topic.
+    pub file: Option<String>,
+    pub dry_run: bool,
+    pub limit: Option<usize>,
+}
+
+impl ReplayOptions {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut options = ReplayOptions {
+            file: None,
+            dry_run: false,
+            limit: None,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--file" => options.file = Some(args.next().context("--file needs a path")?),
+                "--dry-run" => options.dry_run = true,
+                "--limit" => {
+                    let limit = args.next().context("--limit needs a number")?;
+                    options.limit = Some(limit.parse().context("--limit must be a number")?);
+                }
+                other => return Err(anyhow!("Unknown replay option: {}", other)),
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn reached_limit(&self, replayed: usize) -> bool {
+        self.limit.is_some_and(|limit| replayed >= limit)
+    }
+}
+
+async fn replay_record(transport: &dyn Transport, record: &DeadLetter, dry_run: bool) -> Result<()> {
+    let payload = record.raw_payload()?;
+    if dry_run {
+        info!(
+            "Would replay {}[{}]@{} ({} bytes), which failed with: {}",
+            record.topic,
+            record.partition,
+            record.offset,
+            payload.len(),
+            record.error
+        );
+        return Ok(());
+    }
+
+    match serde_json::from_slice(&payload) {
+        Ok(Message::Request(request)) => {
+            transport
+                .send_keyed(&record.topic, &request.guild_id.to_string(), &payload)
+                .await?
+        }
+        _ => transport.send(&record.topic, &payload).await?,
+    }
+    info!(
+        "Replayed {}[{}]@{} to {}",
+        record.topic, record.partition, record.offset, record.topic
+    );
+    Ok(())
+}
+
+/// Pushes dead-lettered messages back onto the topic they were read from.
+/// Records read from the dead-letter topic are acknowledged once replayed,
+/// so each one is replayed once; reading stops when the topic is drained.
+/// Dry runs on Redis still claim the entries they read for the replay group.
+pub async fn run(options: ReplayOptions) -> Result<()> {
+    let topic = CONFIG
+        .transport
+        .dead_letter_topic
+        .as_deref()
+        .context("DEAD_LETTER_TOPIC must be set")?;
+    let transport = open_transport(topic, DEAD_LETTER_REPLAY_GROUP, true).await?;
+    let mut replayed = 0;
+
+    if let Some(path) = &options.file {
+        let contents = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Failed to read {}", path))?;
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            if options.reached_limit(replayed) {
+                break;
+            }
+            let record: DeadLetter =
+                serde_json::from_str(line).context("Failed to parse dead letter")?;
+            replay_record(transport.as_ref(), &record, options.dry_run).await?;
+            replayed += 1;
+        }
+    } else {
+        while !options.reached_limit(replayed) {
+            let idle = Duration::from_secs(DEAD_LETTER_REPLAY_IDLE_SECONDS);
+            let message = match timeout(idle, transport.recv()).await {
+                Ok(received) => match received? {
+                    Some(message) => message,
+                    None => break,
+                },
+                Err(_) => break,
+            };
+
+            match message
+                .payload
+                .as_deref()
+                .map(serde_json::from_slice::<DeadLetter>)
+            {
+                Some(Ok(record)) => {
+                    replay_record(transport.as_ref(), &record, options.dry_run).await?;
+                    replayed += 1;
+                }
+                _ => warn!(
+                    "Skipping malformed dead letter {}[{}]@{}",
+                    message.topic, message.partition, message.offset
+                ),
+            }
+
+            if !options.dry_run {
+                transport.ack(&message).await?;
+            }
+        }
+    }
+
+    info!("Replayed {} dead-lettered messages", replayed);
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use anyhow::{anyhow, Context, Result};
+use nanoid::nanoid;
+use ravalink_interconnect::protocol::{Command, Message, Request};
+use std::num::NonZero;
+
+use crate::cli::{open_transport, option_value};
+use crate::transport::redis::request_stream;
+use crate::utils::config::{TransportKind, CONFIG};
+use crate::utils::constants::CLI_CONSUMER_GROUP;
+use crate::utils::helpers::get_timestamp;
+
+pub struct SendOptions {
+    pub guild_id: Option<NonZero<u64>>,
+    pub voice_channel_id: Option<NonZero<u64>>,
+    /// A raw protocol message, sent as-is instead of building a request.
+    pub json: Option<String>,
+    pub command: Vec<String>,
+}
+
+impl SendOptions {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut options = SendOptions {
+            guild_id: None,
+            voice_channel_id: None,
+            json: None,
+            command: Vec::new(),
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--guild" => options.guild_id = Some(option_value("--guild", &mut args)?),
+                "--channel" => {
+                    options.voice_channel_id = Some(option_value("--channel", &mut args)?)
+                }
+                "--json" => options.json = Some(args.next().context("--json needs a message")?),
+                _ => options.command.push(arg),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn to_command(words: &[String]) -> Result<Command> {
+    let argument = |index: usize| {
+        words
+            .get(index)
+            .cloned()
+            .with_context(|| format!("{} is missing an argument", words[0]))
+    };
+
+    let name = words.first().context("No command given")?;
+    Ok(match name.as_str() {
+        "connect" => Command::Connect,
+        "play" => Command::Play { url: argument(1)? },
+        "search" => Command::Search {
+            query: words[1..].join(" "),
+        },
+        "stop" => Command::Stop,
+        "pause" => Command::Pause,
+        "resume" => Command::Resume,
+        "skip" => Command::Skip,
+        "shuffle" => Command::ShuffleQueue,
+        "loop" => Command::Loop,
+        "volume" => Command::SetVolume {
+            volume: argument(1)?.parse().context("volume must be a number")?,
+        },
+        "seek" => Command::SeekToPosition {
+            position: argument(1)?.parse().context("position must be a number")?,
+        },
+        "playlists" => Command::GetPlaylists,
+        "playlist-add" => Command::AddToPlaylist {
+            playlist_id: argument(1)?,
+            track: argument(2)?,
+        },
+        "playlist-remove" => Command::RemoveFromPlaylist {
+            playlist_id: argument(1)?,
+            track: argument(2)?,
+        },
+        "playlist-load" => Command::LoadPlaylist {
+            playlist_id: argument(1)?,
+        },
+        "playlist-clear" => Command::ClearPlaylist {
+            playlist_id: argument(1)?,
+        },
+        other => return Err(anyhow!("Unknown command: {}", other)),
+    })
+}
+
+/// Publishes a single request to the request topic, the same way a bot
+/// would, and prints its job id so the response can be found with `tail`.
+pub async fn run(options: SendOptions) -> Result<()> {
+    let (payload, guild_id) = match &options.json {
+        Some(json) => {
+            let message: Message =
+                serde_json::from_str(json).context("--json is not a valid protocol message")?;
+            let guild_id = match &message {
+                Message::Request(request) => {
+                    println!("Sending job {}", request.job_id);
+                    Some(request.guild_id)
+                }
+                _ => options.guild_id,
+            };
+            (json.clone().into_bytes(), guild_id)
+        }
+        None => {
+            let guild_id = options.guild_id.context("--guild is required")?;
+            let job_id = nanoid!();
+            let message = Message::Request(Request {
+                job_id: job_id.clone(),
+                guild_id,
+                voice_channel_id: options.voice_channel_id,
+                command: to_command(&options.command)?,
+                timestamp: get_timestamp(),
+            });
+            println!("Sending job {}", job_id);
+            (serde_json::to_vec(&message)?, Some(guild_id))
+        }
+    };
+
+    let topic = &CONFIG.kafka.kafka_topic;
+    let destination = match (CONFIG.transport.kind, guild_id) {
+        (TransportKind::Redis, Some(guild_id)) => request_stream(topic, guild_id.get()),
+        _ => topic.clone(),
+    };
+
+    let transport = open_transport(topic, CLI_CONSUMER_GROUP, false).await?;
+    match guild_id {
+        Some(guild_id) => transport.send_keyed(&destination, &guild_id.to_string(), &payload).await?,
+        None => transport.send(&destination, &payload).await?,
+    }
+    println!("Sent to {}", destination);
+    Ok(())
+}
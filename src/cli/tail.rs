@@ -0,0 +1,101 @@
+use anyhow::Result;
+use nanoid::nanoid;
+use ravalink_interconnect::protocol::Message;
+use std::num::NonZero;
+
+use crate::cli::{open_transport, option_value};
+use crate::utils::config::CONFIG;
+use crate::utils::constants::CLI_CONSUMER_GROUP;
+
+pub struct TailOptions {
+    pub guild_id: Option<NonZero<u64>>,
+    /// Also print requests and pings, not just what the workers produce.
+    pub all: bool,
+}
+
+impl TailOptions {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut options = TailOptions {
+            guild_id: None,
+            all: false,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--guild" => options.guild_id = Some(option_value("--guild", &mut args)?),
+                "--all" => options.all = true,
+                other => return Err(anyhow::anyhow!("Unknown tail option: {}", other)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// A one-line description of `message`, or `None` if it is filtered out.
+fn describe(message: &Message, options: &TailOptions) -> Option<String> {
+    let (guild_id, line) = match message {
+        Message::Response(response) => (
+            Some(response.guild_id),
+            format!(
+                "[{}] response {} guild {}: {:?}",
+                response.timestamp, response.job_id, response.guild_id, response.response_type
+            ),
+        ),
+        Message::Event(event) => (
+            Some(event.guild_id),
+            format!(
+                "[{}] event {} guild {}: {:?}",
+                event.timestamp, event.job_id, event.guild_id, event.event_type
+            ),
+        ),
+        Message::Request(request) if options.all => (
+            Some(request.guild_id),
+            format!(
+                "[{}] request {} guild {}: {:?}",
+                request.timestamp, request.job_id, request.guild_id, request.command
+            ),
+        ),
+        Message::Ping { id } if options.all => (None, format!("ping {}", id)),
+        Message::Pong { id } if options.all => (None, format!("pong {}", id)),
+        _ => return None,
+    };
+
+    match (options.guild_id, guild_id) {
+        (Some(wanted), Some(guild_id)) if wanted != guild_id => None,
+        (Some(_), None) => None,
+        _ => Some(line),
+    }
+}
+
+/// Prints messages from the topic as they arrive, until interrupted. Each
+/// run reads through a consumer group of its own, so it sees every message
+/// without taking any from the workers.
+pub async fn run(options: TailOptions) -> Result<()> {
+    let topic = &CONFIG.kafka.kafka_topic;
+    let group = format!("{}-tail-{}", CLI_CONSUMER_GROUP, nanoid!());
+    let transport = open_transport(topic, &group, false).await?;
+    println!("Tailing {}", topic);
+
+    while let Some(message) = transport.recv().await? {
+        match message
+            .payload
+            .as_deref()
+            .map(serde_json::from_slice::<Message>)
+        {
+            Some(Ok(parsed)) => {
+                if let Some(line) = describe(&parsed, &options) {
+                    println!("{}", line);
+                }
+            }
+            Some(Err(e)) => println!(
+                "undecodable message {}[{}]@{}: {}",
+                message.topic, message.partition, message.offset, e
+            ),
+            None => {}
+        }
+        transport.ack(&message).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use log::{error, info};
+use ravalink_interconnect::protocol::{self, Command, ResponseType};
+use std::net::SocketAddr;
+use std::num::NonZero;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+use crate::utils::config::CONFIG;
+use crate::utils::helpers::get_timestamp;
+use crate::worker::dispatcher::Dispatcher;
+use crate::worker::events::{EventBus, Subscriber};
+use crate::worker::types::{ServerEventType, ServerIPCData, ServerMessage};
+
+use self::pb::ravalink_server::{Ravalink, RavalinkServer};
+
+pub mod pb {
+    tonic::include_proto!("ravalink");
+}
+
+fn to_command(command: pb::command_request::Command) -> Command {
+    use pb::command_request::Command as Pb;
+
+    match command {
+        Pb::Connect(_) => Command::Connect,
+        Pb::Search(search) => Command::Search { query: search.query },
+        Pb::Play(play) => Command::Play { url: play.url },
+        Pb::Stop(_) => Command::Stop,
+        Pb::Pause(_) => Command::Pause,
+        Pb::Resume(_) => Command::Resume,
+        Pb::SeekToPosition(seek) => Command::SeekToPosition {
+            position: seek.position,
+        },
+        Pb::SetVolume(volume) => Command::SetVolume {
+            volume: volume.volume,
+        },
+        Pb::GetPlaylists(_) => Command::GetPlaylists,
+        Pb::AddToPlaylist(entry) => Command::AddToPlaylist {
+            playlist_id: entry.playlist_id,
+            track: entry.track,
+        },
+        Pb::RemoveFromPlaylist(entry) => Command::RemoveFromPlaylist {
+            playlist_id: entry.playlist_id,
+            track: entry.track,
+        },
+        Pb::LoadPlaylist(playlist) => Command::LoadPlaylist {
+            playlist_id: playlist.playlist_id,
+        },
+        Pb::ClearPlaylist(playlist) => Command::ClearPlaylist {
+            playlist_id: playlist.playlist_id,
+        },
+        Pb::ShuffleQueue(_) => Command::ShuffleQueue,
+        Pb::Skip(_) => Command::Skip,
+        Pb::Loop(_) => Command::Loop,
+    }
+}
+
+fn to_response(response: protocol::Response) -> pb::CommandResponse {
+    use pb::command_response::Result as Pb;
+
+    let result = match response.response_type {
+        ResponseType::Success => Pb::Success(pb::Empty {}),
+        ResponseType::Failure { reason } => Pb::Failure(pb::Failure { reason }),
+        ResponseType::SearchResults { tracks } => Pb::SearchResults(pb::SearchResults {
+            tracks_json: serde_json::to_string(&tracks).unwrap_or_default(),
+        }),
+        ResponseType::TracksQueued {
+            count,
+            total_duration,
+        } => Pb::TracksQueued(pb::TracksQueued {
+            count: count as u64,
+            total_duration,
+        }),
+    };
+
+    pb::CommandResponse {
+        job_id: response.job_id,
+        guild_id: response.guild_id.get(),
+        timestamp: response.timestamp,
+        result: Some(result),
+    }
+}
+
+fn to_event(event: ServerIPCData) -> pb::Event {
+    use pb::event::Event as Pb;
+
+    let ServerMessage::Event(event_type) = event.message;
+    let kind = match event_type {
+        ServerEventType::TrackStarted { track } => Pb::TrackStarted(pb::TrackStarted {
+            title: track.title.unwrap_or_default(),
+            artist: track.artist.unwrap_or_default(),
+            url: track.source_url.unwrap_or_default(),
+            duration: track.duration.unwrap_or_default(),
+        }),
+        ServerEventType::TrackEnded => Pb::TrackEnded(pb::Empty {}),
+        ServerEventType::TrackError { error } => Pb::TrackError(pb::TrackError { error }),
+        ServerEventType::ChannelMoved { from, to } => Pb::ChannelMoved(pb::ChannelMoved {
+            from_channel_id: from.get(),
+            to_channel_id: to.get(),
+        }),
+    };
+
+    pb::Event {
+        job_id: event.job_id,
+        guild_id: event.guild_id.get(),
+        timestamp: get_timestamp(),
+        event: Some(kind),
+    }
+}
+
+pub struct RavalinkService {
+    dispatcher: Arc<Dispatcher>,
+    ipc: Arc<EventBus>,
+}
+
+#[tonic::async_trait]
+impl Ravalink for RavalinkService {
+    async fn execute(
+        &self,
+        request: Request<pb::CommandRequest>,
+    ) -> Result<Response<pb::CommandResponse>, Status> {
+        let request = request.into_inner();
+        let guild_id = NonZero::new(request.guild_id)
+            .ok_or_else(|| Status::invalid_argument("guild_id must be set"))?;
+        let voice_channel_id = request.voice_channel_id.and_then(NonZero::new);
+        let command = request
+            .command
+            .ok_or_else(|| Status::invalid_argument("command must be set"))?;
+
+        let response = self
+            .dispatcher
+            .dispatch(guild_id, voice_channel_id, to_command(command))
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+
+        Ok(Response::new(to_response(response)))
+    }
+
+    type SubscribeEventsStream = Pin<Box<dyn Stream<Item = Result<pb::Event, Status>> + Send>>;
+
+    async fn subscribe_events(
+        &self,
+        request: Request<pb::SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+        let guild_filter = request.into_inner().guild_id;
+
+        // A subscriber that reads slowly holds up the other subscribers
+        // rather than missing events.
+        let events = ReceiverStream::new(self.ipc.subscribe(Subscriber::Grpc)).filter_map(move |event| {
+            if guild_filter.is_some_and(|guild_id| guild_id != event.guild_id.get()) {
+                return None;
+            }
+            Some(Ok(to_event(event)))
+        });
+
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+/// Rejects calls without the configured bearer token.
+fn require_token(request: Request<()>) -> Result<Request<()>, Status> {
+    let expected = CONFIG.grpc.token.as_deref().map(|token| format!("Bearer {}", token));
+    let provided = request
+        .metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok());
+    if expected.is_none() || provided != expected.as_deref() {
+        return Err(Status::unauthenticated("Invalid token"));
+    }
+    Ok(request)
+}
+
+/// Starts the gRPC control plane when `GRPC_ENABLED` is set.
+pub async fn start(dispatcher: Arc<Dispatcher>, ipc: Arc<EventBus>) {
+    let address: SocketAddr = match CONFIG.grpc.bind_address.parse() {
+        Ok(address) => address,
+        Err(e) => {
+            error!(
+                "Invalid gRPC bind address {}: {}",
+                CONFIG.grpc.bind_address, e
+            );
+            return;
+        }
+    };
+    info!("gRPC control plane listening on {}", address);
+
+    let service = RavalinkService { dispatcher, ipc };
+    tokio::spawn(async move {
+        if let Err(e) = Server::builder()
+            .add_service(RavalinkServer::with_interceptor(service, require_token))
+            .serve(address)
+            .await
+        {
+            error!("gRPC control plane stopped: {}", e);
+        }
+    });
+}
@@ -1,6 +1,10 @@
 use log::info;
 use serenity::client::Context;
 use serenity::{async_trait, client::EventHandler, model::gateway::Ready};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set once the Discord gateway has sent `ready`.
+pub static DISCORD_READY: AtomicBool = AtomicBool::new(false);
 
 pub struct Handler;
 
@@ -8,5 +12,6 @@ pub struct Handler;
 impl EventHandler for Handler {
     async fn ready(&self, _: Context, ready: Ready) {
         info!("{} is connected!", ready.user.name);
+        DISCORD_READY.store(true, Ordering::Relaxed);
     }
 }
\ No newline at end of file
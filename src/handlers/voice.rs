@@ -1,27 +1,37 @@
 use std::num::NonZero;
 use std::sync::Arc;
-use rdkafka::producer::FutureProducer;
 use songbird::events::EventHandler as VoiceEventHandler;
 use songbird::{Event, EventContext};
 use serenity::async_trait;
-use tokio::sync::broadcast::Sender;
-use log::error;
-use tokio::sync::Mutex;
+use log::{debug, error};
+use songbird::Songbird;
+use reqwest::Client;
 
+use crate::state::guild;
+use crate::utils::logger::LogContext;
+use crate::worker::commands::play;
+use crate::worker::events::EventBus;
 use crate::worker::types::{ServerIPCData, ServerEventType, ServerMessage};
 
 pub struct TrackErrorNotifier {
     pub job_id: String,
     pub guild_id: NonZero<u64>,
-    pub ipc: Arc<Sender<ServerIPCData>>,
-    pub producer : Arc<Mutex<FutureProducer>>,
+    pub ipc: Arc<EventBus>,
+    pub publish: bool,
+}
+
+pub struct TrackStartNotifier {
+    pub job_id: String,
+    pub guild_id: NonZero<u64>,
+    pub ipc: Arc<EventBus>,
+    pub publish: bool,
 }
 
 pub struct TrackEndNotifier {
     pub job_id: String,
     pub guild_id: NonZero<u64>,
-    pub ipc: Arc<Sender<ServerIPCData>>,
-    pub producer : Arc<Mutex<FutureProducer>>,
+    pub ipc: Arc<EventBus>,
+    pub publish: bool,
 }
 
 #[async_trait]
@@ -35,21 +45,14 @@ impl VoiceEventHandler for TrackErrorNotifier {
                     state.playing
                 );
 
-                let notification = self.ipc.send(ServerIPCData {
+                self.ipc.publish(ServerIPCData {
                     message: ServerMessage::Event(ServerEventType::TrackError {
                         error: error_message,
                     }),
                     guild_id: self.guild_id,
                     job_id: self.job_id.clone(),
-                    producer : Some(self.producer.clone()),
+                    publish: self.publish,
                 });
-
-                if let Err(e) = notification {
-                    error!(
-                        "Failed to notify job: {} about track error. Error: {}",
-                        self.job_id, e
-                    );
-                }
             }
         }
 
@@ -57,31 +60,70 @@ impl VoiceEventHandler for TrackErrorNotifier {
     }
 }
 
+#[async_trait]
+impl VoiceEventHandler for TrackStartNotifier {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let Some((track, _)) = guild::now_playing(self.guild_id).await else {
+            return None;
+        };
+
+        self.ipc.publish(ServerIPCData {
+            message: ServerMessage::Event(ServerEventType::TrackStarted { track }),
+            guild_id: self.guild_id,
+            job_id: self.job_id.clone(),
+            publish: self.publish,
+        });
+
+        None
+    }
+}
+
 #[async_trait]
 impl VoiceEventHandler for TrackEndNotifier {
     async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
-        let notification = self.ipc.send(ServerIPCData {
+        self.ipc.publish(ServerIPCData {
             message: ServerMessage::Event(ServerEventType::TrackEnded),
             guild_id: self.guild_id,
             job_id: self.job_id.clone(),
-            producer : Some(self.producer.clone()),
+            publish: self.publish,
         });
 
+        debug!(
+            job_id = self.job_id.as_str(), guild_id = self.guild_id.get();
+            "Notified job: {} that track has ended.",
+            self.job_id
+        );
 
-        match notification {
-            Ok(_) => {
-                println!(
-                    "Notified job: {} that track has ended.",
-                    self.job_id.to_string()
-                );
-            }
-            Err(e) => {
+        None
+    }
+}
+
+
+/// Moves the guild queue forward once the track it is attached to ends or
+/// fails. The advance resolves and analyses the next track, so it runs in its
+/// own task rather than holding up songbird's event loop.
+#[derive(Clone)]
+pub struct QueueAdvancer {
+    pub guild_id: NonZero<u64>,
+    pub manager: Option<Arc<Songbird>>,
+    pub client: Client,
+}
+
+#[async_trait]
+impl VoiceEventHandler for QueueAdvancer {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let guild_id = self.guild_id;
+        let mut manager = self.manager.clone();
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            let advance = play::play_next(guild_id, &mut manager, client);
+            if let Err(e) = LogContext::guild(guild_id).scope(advance).await {
                 error!(
-                    "Failed to notify job: {} that track has ended. Error: {}",
-                    self.job_id.to_string(), e
+                    "Failed to advance queue for guild: {}. Error: {}",
+                    guild_id, e
                 );
             }
-        }
+        });
 
         None
     }
@@ -0,0 +1,422 @@
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use nanoid::nanoid;
+use ravalink_interconnect::protocol::{Command, ResponseType};
+use serde_json::Value;
+use songbird::id::GuildId;
+use songbird::Songbird;
+use std::collections::HashMap;
+use std::num::NonZero;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::Mutex;
+use tokio::time::interval;
+
+use crate::monitoring::metrics;
+use crate::state::guild;
+use crate::utils::config::CONFIG;
+use crate::utils::constants::LAVALINK_STATS_INTERVAL_SECONDS;
+use crate::utils::helpers::get_unix_timestamp;
+use crate::worker::dispatcher::Dispatcher;
+use crate::worker::events::{EventBus, Subscriber};
+use crate::worker::types::{ServerEventType, ServerIPCData, ServerMessage};
+
+use self::protocol::{
+    Cpu, Exception, Memory, OutgoingMessage, Player, PlayerEvent, PlayerState, Stats, Track,
+    VoiceState,
+};
+
+pub mod protocol;
+mod routes;
+mod tracks;
+mod websocket;
+
+#[derive(Default)]
+struct PlayerRecord {
+    track: Option<Track>,
+    voice: VoiceState,
+}
+
+/// A connected Lavalink client. Players are owned by the session that created
+/// them, and their events are only delivered to that session.
+struct Session {
+    sender: mpsc::Sender<OutgoingMessage>,
+    players: HashMap<NonZero<u64>, PlayerRecord>,
+}
+
+impl Session {
+    /// Queues a message for the socket. Returns false when the client has
+    /// fallen so far behind that its buffer is full, and should be dropped.
+    fn send(&self, message: OutgoingMessage) -> bool {
+        match self.sender.try_send(message) {
+            Err(TrySendError::Full(_)) => {
+                metrics::EVENT_BUS_DROPS
+                    .with_label_values(&[Subscriber::Lavalink.label()])
+                    .inc();
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+pub struct LavalinkState {
+    dispatcher: Arc<Dispatcher>,
+    songbird: Option<Arc<Songbird>>,
+    sessions: Mutex<HashMap<String, Session>>,
+    started_at: Instant,
+}
+
+impl LavalinkState {
+    /// Runs a command through the worker pool and turns a failure response
+    /// into an error.
+    pub async fn dispatch(
+        &self,
+        guild_id: NonZero<u64>,
+        voice_channel_id: Option<NonZero<u64>>,
+        command: Command,
+    ) -> Result<()> {
+        let response = self
+            .dispatcher
+            .dispatch(guild_id, voice_channel_id, command)
+            .await?;
+        match response.response_type {
+            ResponseType::Failure { reason } => Err(anyhow!(reason)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Registers a session and queues its `ready` message. The session holds
+    /// the only sender, so the socket closes once the session is dropped.
+    async fn open_session(&self, sender: mpsc::Sender<OutgoingMessage>) -> String {
+        let session_id = nanoid!();
+        let _ = sender.try_send(OutgoingMessage::Ready {
+            resumed: false,
+            session_id: session_id.clone(),
+        });
+        self.sessions.lock().await.insert(
+            session_id.clone(),
+            Session {
+                sender,
+                players: HashMap::new(),
+            },
+        );
+        session_id
+    }
+
+    /// Closes the sessions whose clients could not keep up with their
+    /// messages.
+    async fn close_lagging(&self, session_ids: Vec<String>) {
+        for session_id in session_ids {
+            warn!("Closing Lavalink session {}, which fell behind", session_id);
+            self.close_session(&session_id).await;
+        }
+    }
+
+    /// Drops the session and leaves the voice channels of its players.
+    async fn close_session(&self, session_id: &str) {
+        let Some(session) = self.sessions.lock().await.remove(session_id) else {
+            return;
+        };
+        for guild_id in session.players.into_keys() {
+            if let Err(e) = self.dispatch(guild_id, None, Command::Stop).await {
+                warn!("Failed to destroy player for guild {}: {}", guild_id, e);
+            }
+        }
+    }
+
+    pub async fn has_session(&self, session_id: &str) -> bool {
+        self.sessions.lock().await.contains_key(session_id)
+    }
+
+    /// Creates the session's player for the guild if needed and stores the
+    /// voice state the client sent.
+    pub async fn register_player(
+        &self,
+        session_id: &str,
+        guild_id: NonZero<u64>,
+        voice: Option<VoiceState>,
+    ) {
+        let mut sessions = self.sessions.lock().await;
+        let Some(session) = sessions.get_mut(session_id) else {
+            return;
+        };
+        let record = session.players.entry(guild_id).or_default();
+        if let Some(voice) = voice {
+            record.voice = voice;
+        }
+    }
+
+    pub async fn remove_player(&self, session_id: &str, guild_id: NonZero<u64>) -> bool {
+        let mut sessions = self.sessions.lock().await;
+        sessions
+            .get_mut(session_id)
+            .map(|session| session.players.remove(&guild_id).is_some())
+            .unwrap_or(false)
+    }
+
+    async fn player_state(&self, guild_id: NonZero<u64>) -> PlayerState {
+        let position = match guild::now_playing(guild_id).await {
+            Some((_, Some(handle))) => handle
+                .get_info()
+                .await
+                .map(|info| info.position.as_millis() as u64)
+                .unwrap_or(0),
+            _ => 0,
+        };
+
+        let connected = match self
+            .songbird
+            .as_ref()
+            .and_then(|s| s.get(GuildId(guild_id)))
+        {
+            Some(call) => call.lock().await.current_connection().is_some(),
+            None => false,
+        };
+
+        PlayerState {
+            time: get_unix_timestamp().as_millis() as u64,
+            position,
+            connected,
+            ping: -1,
+        }
+    }
+
+    pub async fn player(&self, session_id: &str, guild_id: NonZero<u64>) -> Option<Player> {
+        let (track, voice) = {
+            let sessions = self.sessions.lock().await;
+            let record = sessions.get(session_id)?.players.get(&guild_id)?;
+            (record.track.clone(), record.voice.clone())
+        };
+
+        let snapshot = guild::snapshot(guild_id).await;
+        Some(Player {
+            guild_id: guild_id.to_string(),
+            track,
+            volume: (guild::volume(guild_id).await * 100.0).round() as u32,
+            paused: snapshot.as_ref().is_some_and(|s| s.paused),
+            state: self.player_state(guild_id).await,
+            voice,
+            filters: snapshot
+                .map(|s| s.filters)
+                .unwrap_or_else(|| Value::Object(Default::default())),
+        })
+    }
+
+    pub async fn players(&self, session_id: &str) -> Option<Vec<Player>> {
+        let guild_ids: Vec<NonZero<u64>> = {
+            let sessions = self.sessions.lock().await;
+            sessions.get(session_id)?.players.keys().copied().collect()
+        };
+
+        let mut players = Vec::with_capacity(guild_ids.len());
+        for guild_id in guild_ids {
+            if let Some(player) = self.player(session_id, guild_id).await {
+                players.push(player);
+            }
+        }
+        Some(players)
+    }
+
+    pub async fn stats(&self) -> Stats {
+        let guild_ids: Vec<NonZero<u64>> = {
+            let sessions = self.sessions.lock().await;
+            sessions
+                .values()
+                .flat_map(|session| session.players.keys().copied())
+                .collect()
+        };
+
+        let mut playing_players = 0;
+        for guild_id in &guild_ids {
+            if guild::now_playing(*guild_id).await.is_some() {
+                playing_players += 1;
+            }
+        }
+
+        Stats {
+            players: guild_ids.len(),
+            playing_players,
+            uptime: self.started_at.elapsed().as_millis() as u64,
+            // Memory and load are not sampled; clients only use them to
+            // weigh nodes against each other.
+            memory: Memory {
+                free: 0,
+                used: 0,
+                allocated: 0,
+                reservable: 0,
+            },
+            cpu: Cpu {
+                cores: std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(1),
+                system_load: 0.0,
+                lavalink_load: 0.0,
+            },
+            frame_stats: None,
+        }
+    }
+
+    /// Translates a worker event into a Lavalink player event for the session
+    /// owning the guild's player.
+    async fn handle_event(&self, event: ServerIPCData) {
+        let guild_id = event.guild_id;
+        let mut sessions = self.sessions.lock().await;
+        let Some((session_id, session)) = sessions
+            .iter_mut()
+            .find(|(_, session)| session.players.contains_key(&guild_id))
+        else {
+            return;
+        };
+        let session_id = session_id.clone();
+        let Some(record) = session.players.get_mut(&guild_id) else {
+            return;
+        };
+
+        let ServerMessage::Event(event_type) = event.message;
+        let events = match event_type {
+            ServerEventType::TrackStarted { track } => {
+                let query = track
+                    .source_url
+                    .clone()
+                    .unwrap_or_else(|| track.stream_url.clone());
+                let track = Track::from_resolved(&query, &track);
+                record.track = Some(track.clone());
+                vec![PlayerEvent::TrackStartEvent {
+                    guild_id: guild_id.to_string(),
+                    track,
+                }]
+            }
+            ServerEventType::TrackEnded => match record.track.take() {
+                Some(track) => vec![PlayerEvent::TrackEndEvent {
+                    guild_id: guild_id.to_string(),
+                    track,
+                    reason: "finished".to_string(),
+                }],
+                None => vec![],
+            },
+            ServerEventType::TrackError { error } => match record.track.take() {
+                Some(track) => vec![
+                    PlayerEvent::TrackExceptionEvent {
+                        guild_id: guild_id.to_string(),
+                        track: track.clone(),
+                        exception: Exception::fault(error),
+                    },
+                    PlayerEvent::TrackEndEvent {
+                        guild_id: guild_id.to_string(),
+                        track,
+                        reason: "loadFailed".to_string(),
+                    },
+                ],
+                None => vec![],
+            },
+            // Lavalink clients move the bot themselves through voice updates.
+            ServerEventType::ChannelMoved { .. } => vec![],
+        };
+
+        let delivered = events
+            .into_iter()
+            .all(|event| session.send(OutgoingMessage::Event(event)));
+        drop(sessions);
+        if !delivered {
+            self.close_lagging(vec![session_id]).await;
+        }
+    }
+
+    /// Sends `playerUpdate` for every player at the configured interval and
+    /// `stats` to every session once a minute.
+    async fn send_updates(&self) {
+        let mut player_updates = interval(Duration::from_secs(
+            CONFIG.lavalink.player_update_interval_seconds.max(1),
+        ));
+        let mut stats_updates = interval(Duration::from_secs(LAVALINK_STATS_INTERVAL_SECONDS));
+
+        loop {
+            tokio::select! {
+                _ = player_updates.tick() => {
+                    let players: Vec<(String, NonZero<u64>)> = {
+                        let sessions = self.sessions.lock().await;
+                        sessions
+                            .iter()
+                            .flat_map(|(session_id, session)| {
+                                session.players.keys().map(|guild_id| (session_id.clone(), *guild_id))
+                            })
+                            .collect()
+                    };
+                    let mut lagging = Vec::new();
+                    for (session_id, guild_id) in players {
+                        let update = OutgoingMessage::PlayerUpdate {
+                            guild_id: guild_id.to_string(),
+                            state: self.player_state(guild_id).await,
+                        };
+                        let sessions = self.sessions.lock().await;
+                        if let Some(session) = sessions.get(&session_id) {
+                            if !session.send(update) && !lagging.contains(&session_id) {
+                                lagging.push(session_id);
+                            }
+                        }
+                    }
+                    self.close_lagging(lagging).await;
+                }
+                _ = stats_updates.tick() => {
+                    let stats = self.stats().await;
+                    let lagging: Vec<String> = self
+                        .sessions
+                        .lock()
+                        .await
+                        .iter()
+                        .filter(|(_, session)| !session.send(OutgoingMessage::Stats(stats.clone())))
+                        .map(|(session_id, _)| session_id.clone())
+                        .collect();
+                    self.close_lagging(lagging).await;
+                }
+            }
+        }
+    }
+}
+
+async fn forward_events(state: Arc<LavalinkState>, mut events: mpsc::Receiver<ServerIPCData>) {
+    while let Some(event) = events.recv().await {
+        state.handle_event(event).await;
+    }
+}
+
+/// Starts the Lavalink v4 compatible API when `LAVALINK_ENABLED` is set.
+/// Player updates become regular worker pool jobs, and track events are
+/// picked up from the event bus.
+pub async fn start(
+    dispatcher: Arc<Dispatcher>,
+    songbird: Option<Arc<Songbird>>,
+    ipc: Arc<EventBus>,
+) {
+    let state = Arc::new(LavalinkState {
+        dispatcher,
+        songbird,
+        sessions: Mutex::new(HashMap::new()),
+        started_at: Instant::now(),
+    });
+
+    let listener = match TcpListener::bind(&CONFIG.lavalink.bind_address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(
+                "Failed to bind Lavalink API to {}: {}",
+                CONFIG.lavalink.bind_address, e
+            );
+            return;
+        }
+    };
+    info!("Lavalink API listening on {}", CONFIG.lavalink.bind_address);
+
+    tokio::spawn(forward_events(state.clone(), ipc.subscribe(Subscriber::Lavalink)));
+    let updates = state.clone();
+    tokio::spawn(async move { updates.send_updates().await });
+
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, routes::router(state)).await {
+            error!("Lavalink API stopped: {}", e);
+        }
+    });
+}
@@ -0,0 +1,271 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize as _, Deserializer};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::worker::track_cache::ResolvedTrack;
+use crate::worker::types::QueuedTrack;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackInfo {
+    pub identifier: String,
+    pub is_seekable: bool,
+    pub author: String,
+    pub length: u64,
+    pub is_stream: bool,
+    pub position: u64,
+    pub title: String,
+    pub uri: Option<String>,
+    pub artwork_url: Option<String>,
+    pub isrc: Option<String>,
+    pub source_name: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Track {
+    pub encoded: String,
+    pub info: TrackInfo,
+    pub plugin_info: Value,
+    pub user_data: Value,
+}
+
+impl Track {
+    /// Lavalink clients treat `encoded` as opaque, so it carries the track
+    /// info as base64 JSON instead of Lavaplayer's binary format. The
+    /// identifier is the yt-dlp query the track is played from.
+    pub fn new(info: TrackInfo) -> Self {
+        let encoded = serde_json::to_vec(&info)
+            .map(|bytes| STANDARD.encode(bytes))
+            .unwrap_or_default();
+        Track {
+            encoded,
+            info,
+            plugin_info: Value::Object(Default::default()),
+            user_data: Value::Object(Default::default()),
+        }
+    }
+
+    pub fn decode(encoded: &str) -> Result<Self> {
+        let bytes = STANDARD
+            .decode(encoded)
+            .context("Track is not valid base64")?;
+        let info: TrackInfo =
+            serde_json::from_slice(&bytes).context("Track was not encoded by ravalink")?;
+        Ok(Track::new(info))
+    }
+
+    pub fn from_queued(track: &QueuedTrack) -> Self {
+        let query = track.source.query().to_string();
+        Track::new(TrackInfo {
+            identifier: query.clone(),
+            is_seekable: false,
+            author: track.artist.clone().unwrap_or_default(),
+            length: track.duration.map(|d| d.as_millis() as u64).unwrap_or(0),
+            is_stream: track.duration.is_none(),
+            position: 0,
+            title: track.title.clone().unwrap_or_else(|| query.clone()),
+            uri: Some(query),
+            artwork_url: None,
+            isrc: track.isrc.clone(),
+            source_name: "ravalink".to_string(),
+        })
+    }
+
+    pub fn from_resolved(query: &str, track: &ResolvedTrack) -> Self {
+        Track::new(TrackInfo {
+            identifier: query.to_string(),
+            is_seekable: false,
+            author: track.artist.clone().unwrap_or_default(),
+            length: track.duration.map(|d| (d * 1000.0) as u64).unwrap_or(0),
+            is_stream: track.duration.is_none(),
+            position: 0,
+            title: track.title.clone().unwrap_or_else(|| query.to_string()),
+            uri: track.source_url.clone().or_else(|| Some(query.to_string())),
+            artwork_url: track.thumbnail.clone(),
+            isrc: None,
+            source_name: track
+                .track_id
+                .as_deref()
+                .and_then(|id| id.split('-').next())
+                .unwrap_or("ravalink")
+                .to_lowercase(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistInfo {
+    pub name: String,
+    pub selected_track: i32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistData {
+    pub info: PlaylistInfo,
+    pub plugin_info: Value,
+    pub tracks: Vec<Track>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Exception {
+    pub message: Option<String>,
+    pub severity: String,
+    pub cause: String,
+}
+
+impl Exception {
+    pub fn fault(message: String) -> Self {
+        Exception {
+            message: Some(message.clone()),
+            severity: "fault".to_string(),
+            cause: message,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "loadType", content = "data", rename_all = "camelCase")]
+pub enum LoadResult {
+    Track(Track),
+    Playlist(PlaylistData),
+    Search(Vec<Track>),
+    Empty {},
+    Error(Exception),
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceState {
+    #[serde(default)]
+    pub token: String,
+    #[serde(default)]
+    pub endpoint: String,
+    #[serde(default)]
+    pub session_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_id: Option<String>,
+}
+
+/// Tells a field that was left out (`None`) from one set to `null`
+/// (`Some(None)`), which Lavalink uses to stop the player.
+fn explicit_null<'de, D>(deserializer: D) -> Result<Option<Option<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatePlayerTrack {
+    #[serde(default, deserialize_with = "explicit_null")]
+    pub encoded: Option<Option<String>>,
+    pub identifier: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatePlayer {
+    pub track: Option<UpdatePlayerTrack>,
+    #[serde(default, deserialize_with = "explicit_null")]
+    pub encoded_track: Option<Option<String>>,
+    pub identifier: Option<String>,
+    pub position: Option<u64>,
+    pub volume: Option<u32>,
+    pub paused: Option<bool>,
+    pub filters: Option<Value>,
+    pub voice: Option<VoiceState>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerState {
+    pub time: u64,
+    pub position: u64,
+    pub connected: bool,
+    pub ping: i64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Player {
+    pub guild_id: String,
+    pub track: Option<Track>,
+    pub volume: u32,
+    pub paused: bool,
+    pub state: PlayerState,
+    pub voice: VoiceState,
+    pub filters: Value,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Memory {
+    pub free: u64,
+    pub used: u64,
+    pub allocated: u64,
+    pub reservable: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Cpu {
+    pub cores: usize,
+    pub system_load: f64,
+    pub lavalink_load: f64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    pub players: usize,
+    pub playing_players: usize,
+    pub uptime: u64,
+    pub memory: Memory,
+    pub cpu: Cpu,
+    pub frame_stats: Option<Value>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum PlayerEvent {
+    #[serde(rename_all = "camelCase")]
+    TrackStartEvent { guild_id: String, track: Track },
+    #[serde(rename_all = "camelCase")]
+    TrackEndEvent {
+        guild_id: String,
+        track: Track,
+        reason: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    TrackExceptionEvent {
+        guild_id: String,
+        track: Track,
+        exception: Exception,
+    },
+}
+
+/// Messages sent to clients over the WebSocket, tagged by `op`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum OutgoingMessage {
+    #[serde(rename_all = "camelCase")]
+    Ready {
+        resumed: bool,
+        session_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    PlayerUpdate {
+        guild_id: String,
+        state: PlayerState,
+    },
+    Stats(Stats),
+    Event(PlayerEvent),
+}
@@ -0,0 +1,310 @@
+use axum::extract::{Path, Query, Request, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use log::debug;
+use ravalink_interconnect::protocol::Command;
+use serde_derive::Deserialize;
+use serde_json::json;
+use std::num::NonZero;
+use std::sync::Arc;
+
+use crate::state::guild;
+use crate::utils::config::CONFIG;
+use crate::utils::helpers::get_unix_timestamp;
+
+use super::protocol::{LoadResult, Player, Stats, Track, UpdatePlayer};
+use super::{tracks, websocket, LavalinkState};
+
+/// Error body in the shape Lavalink clients expect.
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = json!({
+            "timestamp": get_unix_timestamp().as_millis() as u64,
+            "status": self.status.as_u16(),
+            "error": self.status.canonical_reason().unwrap_or_default(),
+            "message": self.message,
+        });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// Configuration validation makes `LAVALINK_PASSWORD` mandatory; an empty
+/// one still never lets a request through.
+async fn require_password(request: Request, next: Next) -> Response {
+    let password = CONFIG.lavalink.password.as_deref().unwrap_or_default();
+    let provided = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if password.is_empty() || provided != Some(password) {
+        return ApiError::new(StatusCode::UNAUTHORIZED, "Invalid password").into_response();
+    }
+    next.run(request).await
+}
+
+fn parse_guild_id(guild_id: &str) -> Result<NonZero<u64>, ApiError> {
+    guild_id
+        .parse()
+        .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, "Invalid guild id"))
+}
+
+#[derive(Deserialize)]
+struct LoadTracksParams {
+    identifier: String,
+}
+
+#[derive(Deserialize)]
+struct UpdatePlayerParams {
+    #[serde(rename = "noReplace", default)]
+    no_replace: bool,
+}
+
+#[derive(Deserialize)]
+struct DecodeTrackParams {
+    #[serde(rename = "encodedTrack")]
+    encoded_track: String,
+}
+
+async fn version() -> &'static str {
+    "4.0.0"
+}
+
+async fn info() -> Json<serde_json::Value> {
+    Json(json!({
+        "version": { "semver": "4.0.0", "major": 4, "minor": 0, "patch": 0, "preRelease": null, "build": null },
+        "buildTime": 0,
+        "git": { "branch": "", "commit": "", "commitTime": 0 },
+        "jvm": "",
+        "lavaplayer": "",
+        "sourceManagers": ["youtube", "soundcloud", "spotify", "applemusic", "deezer", "http"],
+        "filters": [],
+        "plugins": [],
+    }))
+}
+
+async fn stats(State(state): State<Arc<LavalinkState>>) -> Json<Stats> {
+    Json(state.stats().await)
+}
+
+async fn load_tracks(Query(params): Query<LoadTracksParams>) -> Json<LoadResult> {
+    Json(tracks::load(&params.identifier).await)
+}
+
+async fn decode_track(Query(params): Query<DecodeTrackParams>) -> Result<Json<Track>, ApiError> {
+    Track::decode(&params.encoded_track)
+        .map(Json)
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+async fn get_players(
+    State(state): State<Arc<LavalinkState>>,
+    Path(session_id): Path<String>,
+) -> Result<Json<Vec<Player>>, ApiError> {
+    state
+        .players(&session_id)
+        .await
+        .map(Json)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Session not found"))
+}
+
+async fn get_player(
+    State(state): State<Arc<LavalinkState>>,
+    Path((session_id, guild_id)): Path<(String, String)>,
+) -> Result<Json<Player>, ApiError> {
+    let guild_id = parse_guild_id(&guild_id)?;
+    state
+        .player(&session_id, guild_id)
+        .await
+        .map(Json)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Player not found"))
+}
+
+/// Applies a player update as worker pool jobs: a voice channel id becomes
+/// Connect, a track becomes Play and a volume becomes SetVolume. A new track
+/// replaces the current one unless `noReplace` is set, in which case it is
+/// ignored while something plays, and a `null` track stops the player.
+/// `paused` and `position` become Pause, Resume and SeekToPosition. Filters
+/// are stored and reported back but not applied to the audio yet.
+///
+/// Unlike Lavalink, Ravalink joins voice through its own gateway connection.
+/// The client's token, endpoint and session id are accepted but only echoed
+/// back, and the bot only joins a channel when the voice state carries the
+/// non-standard `channelId`. Clients that omit it must have the bot joined
+/// some other way, such as a Connect sent over the transport.
+async fn update_player(
+    State(state): State<Arc<LavalinkState>>,
+    Path((session_id, guild_id)): Path<(String, String)>,
+    Query(params): Query<UpdatePlayerParams>,
+    Json(update): Json<UpdatePlayer>,
+) -> Result<Json<Player>, ApiError> {
+    let guild_id = parse_guild_id(&guild_id)?;
+    if !state.has_session(&session_id).await {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "Session not found"));
+    }
+    let job_failed =
+        |e: anyhow::Error| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+    // Register the player first so track events raised by the jobs below
+    // already reach this session.
+    state
+        .register_player(&session_id, guild_id, update.voice.clone())
+        .await;
+
+    if let Some(voice) = &update.voice {
+        if let Some(channel_id) = voice.channel_id.as_deref() {
+            let channel_id: NonZero<u64> = channel_id
+                .parse()
+                .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, "Invalid channel id"))?;
+            state
+                .dispatch(guild_id, Some(channel_id), Command::Connect)
+                .await
+                .map_err(job_failed)?;
+        }
+    }
+
+    // `Some(None)` is an explicit `null`: stop the player.
+    let encoded = update
+        .track
+        .as_ref()
+        .and_then(|track| track.encoded.clone())
+        .or(update.encoded_track.clone());
+    let identifier = update
+        .track
+        .as_ref()
+        .and_then(|track| track.identifier.clone())
+        .or(update.identifier.clone());
+
+    let track = match (encoded, identifier) {
+        (Some(Some(encoded)), _) => Some(
+            Track::decode(&encoded)
+                .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e.to_string()))?,
+        ),
+        (Some(None), _) => {
+            guild::stop_playback(guild_id).await.map_err(job_failed)?;
+            None
+        }
+        (None, Some(identifier)) => match tracks::load(&identifier).await {
+            LoadResult::Track(track) => Some(track),
+            LoadResult::Search(tracks) => tracks.into_iter().next(),
+            _ => {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "Identifier is not a single track",
+                ))
+            }
+        },
+        (None, None) => None,
+    };
+
+    if let Some(track) = &track {
+        let playing = guild::is_playing(guild_id).await;
+        if playing && params.no_replace {
+            debug!("Ignoring track for guild {}, noReplace is set", guild_id);
+        } else {
+            // Whichever of the stopped track's advancer and this Play runs
+            // first, the new track is started exactly once.
+            if playing {
+                guild::stop_playback(guild_id).await.map_err(job_failed)?;
+            }
+            state
+                .dispatch(
+                    guild_id,
+                    None,
+                    Command::Play {
+                        url: track.info.identifier.clone(),
+                    },
+                )
+                .await
+                .map_err(job_failed)?;
+        }
+    }
+
+    if let Some(volume) = update.volume {
+        state
+            .dispatch(
+                guild_id,
+                None,
+                Command::SetVolume {
+                    volume: volume as f32 / 100.0,
+                },
+            )
+            .await
+            .map_err(job_failed)?;
+    }
+
+    if let Some(paused) = update.paused {
+        let command = if paused { Command::Pause } else { Command::Resume };
+        state
+            .dispatch(guild_id, None, command)
+            .await
+            .map_err(job_failed)?;
+    }
+
+    if let Some(position) = update.position {
+        state
+            .dispatch(guild_id, None, Command::SeekToPosition { position })
+            .await
+            .map_err(job_failed)?;
+    }
+
+    if let Some(filters) = update.filters {
+        debug!("Storing filters for guild {}; they are not applied yet", guild_id);
+        guild::set_filters(guild_id, filters).await;
+    }
+
+    state
+        .player(&session_id, guild_id)
+        .await
+        .map(Json)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Player not found"))
+}
+
+async fn destroy_player(
+    State(state): State<Arc<LavalinkState>>,
+    Path((session_id, guild_id)): Path<(String, String)>,
+) -> Result<StatusCode, ApiError> {
+    let guild_id = parse_guild_id(&guild_id)?;
+    if !state.remove_player(&session_id, guild_id).await {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "Player not found"));
+    }
+    state
+        .dispatch(guild_id, None, Command::Stop)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn router(state: Arc<LavalinkState>) -> Router {
+    Router::new()
+        .route("/version", get(version))
+        .route("/v4/info", get(info))
+        .route("/v4/stats", get(stats))
+        .route("/v4/loadtracks", get(load_tracks))
+        .route("/v4/decodetrack", get(decode_track))
+        .route("/v4/websocket", get(websocket::upgrade))
+        .route("/v4/sessions/:session_id/players", get(get_players))
+        .route(
+            "/v4/sessions/:session_id/players/:guild_id",
+            get(get_player).patch(update_player).delete(destroy_player),
+        )
+        .layer(middleware::from_fn(require_password))
+        .with_state(state)
+}
@@ -0,0 +1,80 @@
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::resolver::{parse_link, LinkKind, RESOLVER};
+use crate::utils::config::CONFIG;
+use crate::utils::constants::LAVALINK_SEARCH_RESULTS;
+use crate::worker::playlist;
+use crate::worker::track_cache::TRACK_CACHE;
+use crate::worker::types::QueuedTrack;
+
+use super::protocol::{Exception, LoadResult, PlaylistData, PlaylistInfo, Track};
+
+/// Maps Lavalink search prefixes onto the equivalent yt-dlp search.
+fn search_query(identifier: &str) -> Option<String> {
+    let (prefix, query) = identifier.split_once(':')?;
+    let extractor = match prefix {
+        "ytsearch" | "ytmsearch" => "ytsearch",
+        "scsearch" => "scsearch",
+        _ => return None,
+    };
+    Some(format!(
+        "{}{}:{}",
+        extractor, LAVALINK_SEARCH_RESULTS, query
+    ))
+}
+
+fn playlist(name: &str, tracks: &[QueuedTrack]) -> LoadResult {
+    LoadResult::Playlist(PlaylistData {
+        info: PlaylistInfo {
+            name: name.to_string(),
+            selected_track: -1,
+        },
+        plugin_info: Value::Object(Default::default()),
+        tracks: tracks.iter().map(Track::from_queued).collect(),
+    })
+}
+
+async fn try_load(identifier: &str) -> Result<LoadResult> {
+    if let Some(query) = search_query(identifier) {
+        let tracks = playlist::expand(&query).await?;
+        if tracks.is_empty() {
+            return Ok(LoadResult::Empty {});
+        }
+        return Ok(LoadResult::Search(
+            tracks.iter().map(Track::from_queued).collect(),
+        ));
+    }
+
+    if playlist::is_playlist_url(identifier) {
+        let tracks = playlist::expand(identifier).await?;
+        return Ok(playlist(identifier, &tracks));
+    }
+
+    if let Some(entries) = RESOLVER.resolve(identifier).await? {
+        let tracks: Vec<QueuedTrack> = entries
+            .into_iter()
+            .take(CONFIG.config.playlist_max_entries)
+            .map(QueuedTrack::from)
+            .collect();
+        let is_track = matches!(parse_link(identifier), Some(link) if link.kind == LinkKind::Track);
+        return Ok(match tracks.first() {
+            Some(track) if is_track => LoadResult::Track(Track::from_queued(track)),
+            _ => playlist(identifier, &tracks),
+        });
+    }
+
+    let resolved = TRACK_CACHE.resolve(identifier).await?;
+    Ok(LoadResult::Track(Track::from_resolved(
+        identifier, &resolved,
+    )))
+}
+
+/// Resolves a `loadtracks` identifier with the same building blocks the Play
+/// command uses: playlist expansion, provider metadata and the track cache.
+pub async fn load(identifier: &str) -> LoadResult {
+    match try_load(identifier).await {
+        Ok(result) => result,
+        Err(e) => LoadResult::Error(Exception::fault(e.to_string())),
+    }
+}
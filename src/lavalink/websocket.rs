@@ -0,0 +1,53 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use log::{debug, error, info};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use super::LavalinkState;
+use crate::utils::config::CONFIG;
+
+pub async fn upgrade(State(state): State<Arc<LavalinkState>>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(state, socket))
+}
+
+/// Lavalink v4 clients only listen on the socket; every command goes over
+/// REST. The socket lives as long as the session, and closing it destroys the
+/// session's players. A client that stops reading is disconnected once its
+/// buffer of `EVENT_BUS_CAPACITY` messages is full.
+async fn handle_socket(state: Arc<LavalinkState>, mut socket: WebSocket) {
+    let (sender, mut outgoing) = mpsc::channel(CONFIG.events.bus_capacity.max(1));
+    let session_id = state.open_session(sender).await;
+    info!("Lavalink client connected with session {}", session_id);
+
+    loop {
+        tokio::select! {
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Ok(message)) => debug!("Ignoring client message: {:?}", message),
+                Some(Err(e)) => {
+                    error!("Lavalink socket error for session {}: {}", session_id, e);
+                    break;
+                }
+            },
+            message = outgoing.recv() => {
+                // The session was closed for falling behind.
+                let Some(message) = message else { break };
+                let text = match serde_json::to_string(&message) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        error!("Failed to serialize Lavalink message: {}", e);
+                        continue;
+                    }
+                };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    info!("Lavalink session {} closed", session_id);
+    state.close_session(&session_id).await;
+}
@@ -3,9 +3,59 @@ mod worker;
 mod handlers;
 mod startup;
 mod state;
+mod resolver;
+mod transport;
+mod lavalink;
+mod grpc;
+mod monitoring;
+mod cli;
+mod admin;
+use crate::cli::{Cli, USAGE};
 use crate::startup::start_rusty_server;
+use crate::utils::config::{self, ConfigArgs};
+use crate::utils::logger::loggers;
 
 #[tokio::main]
 async fn main() {
-    start_rusty_server().await;
+    let (config_args, args) = ConfigArgs::extract(std::env::args().skip(1));
+    config::set_args(config_args);
+
+    let command = match Cli::from_args(args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let result = match command {
+        Cli::Run => {
+            start_rusty_server().await;
+            Ok(())
+        }
+        Cli::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        // Reports configuration errors itself, so it runs before anything
+        // loads `CONFIG`.
+        Cli::CheckConfig => cli::check::run().await,
+        Cli::Send(options) => {
+            loggers().await;
+            cli::send::run(options).await
+        }
+        Cli::Tail(options) => {
+            loggers().await;
+            cli::tail::run(options).await
+        }
+        Cli::ReplayDeadLetters(options) => {
+            loggers().await;
+            cli::replay::run(options).await
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("{:#}", e);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,101 @@
+use anyhow::{anyhow, Context, Result};
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::time::timeout;
+
+use crate::handlers::default::DISCORD_READY;
+use crate::transport::Transport;
+use crate::utils::config::CONFIG;
+use crate::utils::constants::READINESS_TIMEOUT_SECONDS;
+use crate::utils::helpers::is_program_in_path;
+use crate::worker::drain;
+
+#[derive(Serialize)]
+pub struct CheckResult {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: BTreeMap<&'static str, CheckResult>,
+}
+
+async fn check(future: impl Future<Output = Result<()>>) -> CheckResult {
+    let outcome = match timeout(Duration::from_secs(READINESS_TIMEOUT_SECONDS), future).await {
+        Ok(outcome) => outcome,
+        Err(_) => Err(anyhow!("timed out")),
+    };
+    CheckResult {
+        ok: outcome.is_ok(),
+        error: outcome.err().map(|e| format!("{:#}", e)),
+    }
+}
+
+async fn check_discord() -> Result<()> {
+    if DISCORD_READY.load(Ordering::Relaxed) {
+        Ok(())
+    } else {
+        Err(anyhow!("Gateway has not sent ready yet"))
+    }
+}
+
+async fn check_not_draining() -> Result<()> {
+    if drain::is_draining() {
+        Err(anyhow!("Worker is draining"))
+    } else {
+        Ok(())
+    }
+}
+
+async fn check_redis(redis_url: &str) -> Result<()> {
+    let client = redis::Client::open(redis_url).context("Invalid Redis URL")?;
+    let mut connection = client.get_multiplexed_async_connection().await?;
+    let _: String = redis::cmd("PING").query_async(&mut connection).await?;
+    Ok(())
+}
+
+async fn check_ytdlp() -> Result<()> {
+    if is_program_in_path("yt-dlp") {
+        Ok(())
+    } else {
+        Err(anyhow!("yt-dlp is not installed"))
+    }
+}
+
+/// Runs every readiness check concurrently. Redis is only checked when
+/// `REDIS_URL` is configured.
+pub async fn readiness(transport: &dyn Transport) -> Readiness {
+    let redis = async {
+        match CONFIG.redis_url.as_deref() {
+            Some(redis_url) => Some(check(check_redis(redis_url)).await),
+            None => None,
+        }
+    };
+    let (draining, discord, transport, redis, ytdlp) = tokio::join!(
+        check(check_not_draining()),
+        check(check_discord()),
+        check(transport.check_ready()),
+        redis,
+        check(check_ytdlp()),
+    );
+
+    let mut checks = BTreeMap::new();
+    checks.insert("discord", discord);
+    checks.insert("transport", transport);
+    if let Some(redis) = redis {
+        checks.insert("redis", redis);
+    }
+    checks.insert("yt_dlp", ytdlp);
+    checks.insert("drain", draining);
+
+    Readiness {
+        ready: checks.values().all(|check| check.ok),
+        checks,
+    }
+}
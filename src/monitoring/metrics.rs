@@ -0,0 +1,223 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    exponential_buckets, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge, register_int_gauge_vec, Encoder, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+use ravalink_interconnect::protocol::{Command, ResponseType};
+use songbird::Songbird;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+use crate::state::guild;
+use crate::utils::helpers::get_unix_timestamp;
+use crate::worker::types::ServerIPCData;
+
+pub static JOBS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "ravalink_jobs_total",
+        "Jobs handled by the worker pool, by command and outcome",
+        &["command", "outcome"]
+    )
+    .unwrap()
+});
+
+pub static JOB_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "ravalink_job_latency_seconds",
+        "Time from the request timestamp to the response being produced",
+        &["command"],
+        exponential_buckets(0.25, 2.0, 10).unwrap()
+    )
+    .unwrap()
+});
+
+pub static KAFKA_CONSUMER_LAG: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "ravalink_kafka_consumer_lag",
+        "How many messages the consumer is behind the end of each partition",
+        &["topic", "partition"]
+    )
+    .unwrap()
+});
+
+pub static KAFKA_PRODUCER_ERRORS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "ravalink_kafka_producer_errors_total",
+        "Messages the Kafka producer failed to deliver"
+    )
+    .unwrap()
+});
+
+pub static VOICE_CONNECTIONS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "ravalink_voice_connections",
+        "Guilds with an active voice connection"
+    )
+    .unwrap()
+});
+
+pub static ACTIVE_TRACKS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!("ravalink_active_tracks", "Guilds currently playing a track").unwrap()
+});
+
+pub static QUEUE_LENGTH: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "ravalink_queue_length",
+        "Tracks waiting in each guild's queue",
+        &["guild_id"]
+    )
+    .unwrap()
+});
+
+pub static YTDLP_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "ravalink_ytdlp_duration_seconds",
+        "Duration of yt-dlp invocations",
+        &["operation"],
+        exponential_buckets(0.25, 2.0, 8).unwrap()
+    )
+    .unwrap()
+});
+
+pub static YTDLP_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "ravalink_ytdlp_failures_total",
+        "yt-dlp invocations that failed",
+        &["operation"]
+    )
+    .unwrap()
+});
+
+pub static EVENTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "ravalink_events_total",
+        "Worker events published on the event bus, by event",
+        &["event"]
+    )
+    .unwrap()
+});
+
+pub static EVENT_BUS_WAITS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "ravalink_event_bus_waits_total",
+        "Events that had to wait for room because a subscriber's buffer was full",
+        &["subscriber"]
+    )
+    .unwrap()
+});
+
+pub static EVENT_BUS_DROPS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "ravalink_event_bus_drops_total",
+        "Events an API client lost because its buffer was full",
+        &["subscriber"]
+    )
+    .unwrap()
+});
+
+/// Registers every metric up front so each one is exported from the first
+/// scrape, not only after it is first updated.
+pub fn init() {
+    Lazy::force(&JOBS);
+    Lazy::force(&JOB_LATENCY);
+    Lazy::force(&KAFKA_CONSUMER_LAG);
+    Lazy::force(&KAFKA_PRODUCER_ERRORS);
+    Lazy::force(&VOICE_CONNECTIONS);
+    Lazy::force(&ACTIVE_TRACKS);
+    Lazy::force(&QUEUE_LENGTH);
+    Lazy::force(&YTDLP_LATENCY);
+    Lazy::force(&YTDLP_FAILURES);
+    Lazy::force(&EVENTS);
+    Lazy::force(&EVENT_BUS_WAITS);
+    Lazy::force(&EVENT_BUS_DROPS);
+}
+
+pub fn command_name(command: &Command) -> &'static str {
+    match command {
+        Command::Connect => "connect",
+        Command::Search { .. } => "search",
+        Command::Play { .. } => "play",
+        Command::Stop => "stop",
+        Command::Pause => "pause",
+        Command::Resume => "resume",
+        Command::SeekToPosition { .. } => "seek_to_position",
+        Command::SetVolume { .. } => "set_volume",
+        Command::GetPlaylists => "get_playlists",
+        Command::AddToPlaylist { .. } => "add_to_playlist",
+        Command::RemoveFromPlaylist { .. } => "remove_from_playlist",
+        Command::LoadPlaylist { .. } => "load_playlist",
+        Command::ClearPlaylist { .. } => "clear_playlist",
+        Command::ShuffleQueue => "shuffle_queue",
+        Command::Skip => "skip",
+        Command::Loop => "loop",
+    }
+}
+
+/// Records a job's outcome and its latency from `request_timestamp`, which
+/// is in whole seconds.
+pub fn record_job(command: &str, response_type: &ResponseType, request_timestamp: u64) {
+    let outcome = match response_type {
+        ResponseType::Failure { .. } => "failed",
+        _ => "processed",
+    };
+    JOBS.with_label_values(&[command, outcome]).inc();
+
+    let latency = get_unix_timestamp().as_secs_f64() - request_timestamp as f64;
+    JOB_LATENCY
+        .with_label_values(&[command])
+        .observe(latency.max(0.0));
+}
+
+/// Records how long a yt-dlp invocation started at `started` took.
+pub fn record_ytdlp(operation: &str, started: Instant, succeeded: bool) {
+    YTDLP_LATENCY
+        .with_label_values(&[operation])
+        .observe(started.elapsed().as_secs_f64());
+    if !succeeded {
+        YTDLP_FAILURES.with_label_values(&[operation]).inc();
+    }
+}
+
+/// Counts the worker events published on the event bus.
+pub async fn record_events(mut events: mpsc::Receiver<ServerIPCData>) {
+    while let Some(event) = events.recv().await {
+        EVENTS.with_label_values(&[event.name()]).inc();
+    }
+}
+
+/// Refreshes the gauges that are sampled rather than updated as they change.
+async fn sample_playback(songbird: Option<&Arc<Songbird>>) {
+    if let Some(songbird) = songbird {
+        let connected = songbird
+            .iter()
+            .filter(|(_, call)| {
+                call.try_lock()
+                    .map(|call| call.current_connection().is_some())
+                    .unwrap_or(true)
+            })
+            .count();
+        VOICE_CONNECTIONS.set(connected as i64);
+    }
+
+    let summary = guild::playback_summary().await;
+    ACTIVE_TRACKS.set(summary.iter().filter(|(_, _, playing)| *playing).count() as i64);
+    QUEUE_LENGTH.reset();
+    for (guild_id, queued, _) in summary {
+        QUEUE_LENGTH
+            .with_label_values(&[&guild_id.to_string()])
+            .set(queued as i64);
+    }
+}
+
+/// Renders every registered metric in the Prometheus text format.
+pub async fn render(songbird: Option<&Arc<Songbird>>) -> String {
+    sample_playback(songbird).await;
+
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        log::error!("Failed to encode metrics: {}", e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
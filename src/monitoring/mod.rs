@@ -0,0 +1,78 @@
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use log::{error, info};
+use songbird::Songbird;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+use crate::transport::Transport;
+use crate::utils::config::CONFIG;
+
+pub mod health;
+pub mod metrics;
+pub mod telemetry;
+
+#[derive(Clone)]
+struct MonitoringState {
+    songbird: Option<Arc<Songbird>>,
+    transport: Arc<dyn Transport>,
+}
+
+async fn render_metrics(State(state): State<MonitoringState>) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        metrics::render(state.songbird.as_ref()).await,
+    )
+}
+
+/// Liveness only says the process is up and serving requests.
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+async fn readyz(State(state): State<MonitoringState>) -> impl IntoResponse {
+    let readiness = health::readiness(state.transport.as_ref()).await;
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+/// Serves `/metrics` for Prometheus and the `/healthz` and `/readyz` probes
+/// when `MONITORING_ENABLED` is set.
+pub async fn start(songbird: Option<Arc<Songbird>>, transport: Arc<dyn Transport>) {
+    metrics::init();
+
+    let listener = match TcpListener::bind(&CONFIG.monitoring.bind_address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(
+                "Failed to bind monitoring endpoint to {}: {}",
+                CONFIG.monitoring.bind_address, e
+            );
+            return;
+        }
+    };
+    info!("Monitoring endpoint listening on {}", CONFIG.monitoring.bind_address);
+
+    let router = Router::new()
+        .route("/metrics", get(render_metrics))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(MonitoringState {
+            songbird,
+            transport,
+        });
+
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, router).await {
+            error!("Monitoring endpoint stopped: {}", e);
+        }
+    });
+}
@@ -0,0 +1,116 @@
+use anyhow::Result;
+use log::{error, info};
+use once_cell::sync::OnceCell;
+use opentelemetry::global::{self, BoxedSpan};
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::{FutureExt, SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+
+use crate::utils::config::CONFIG;
+
+const TRACER_NAME: &str = "ravalink";
+
+static PROVIDER: OnceCell<TracerProvider> = OnceCell::new();
+
+/// Installs the W3C trace context propagator and, when an OTLP endpoint is
+/// configured, a tracer provider exporting spans to it. Without an endpoint
+/// spans are still created and propagated but never exported.
+pub fn init() -> Result<()> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let Some(endpoint) = &CONFIG.tracing.otlp_endpoint else {
+        return Ok(());
+    };
+    let exporter = SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            CONFIG.tracing.service_name.clone(),
+        )]))
+        .build();
+
+    global::set_tracer_provider(provider.clone());
+    let _ = PROVIDER.set(provider);
+    info!("Exporting traces to {}", endpoint);
+    Ok(())
+}
+
+/// Flushes spans that have not been exported yet.
+pub fn shutdown() {
+    if let Some(provider) = PROVIDER.get() {
+        if let Err(e) = provider.shutdown() {
+            error!("Failed to shut down the trace exporter: {}", e);
+        }
+    }
+}
+
+/// The trace context carried in a message's headers, or an empty context if
+/// the producer did not send one.
+pub fn extract(headers: &HashMap<String, String>) -> Context {
+    TraceContextPropagator::new().extract(headers)
+}
+
+/// Headers carrying the trace context `cx` to the consumer of a message.
+pub fn inject(cx: &Context) -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    TraceContextPropagator::new().inject_context(cx, &mut headers);
+    headers
+}
+
+/// Starts a span that is a child of `parent`.
+pub fn start_span(
+    name: impl Into<Cow<'static, str>>,
+    kind: SpanKind,
+    attributes: Vec<KeyValue>,
+    parent: &Context,
+) -> BoxedSpan {
+    let tracer = global::tracer(TRACER_NAME);
+    tracer
+        .span_builder(name)
+        .with_kind(kind)
+        .with_attributes(attributes)
+        .start_with_context(&tracer, parent)
+}
+
+/// A context whose active span is a new child of the current one.
+pub fn child_context(name: impl Into<Cow<'static, str>>, kind: SpanKind, attributes: Vec<KeyValue>) -> Context {
+    let parent = Context::current();
+    let span = start_span(name, kind, attributes, &parent);
+    parent.with_span(span)
+}
+
+/// Runs `future` inside an internal span, marking the span as failed if the
+/// future returns an error.
+pub async fn in_span<T, F>(
+    name: impl Into<Cow<'static, str>>,
+    attributes: Vec<KeyValue>,
+    future: F,
+) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let cx = child_context(name, SpanKind::Internal, attributes);
+    let result = future.with_context(cx.clone()).await;
+    record_result(&cx, &result);
+    result
+}
+
+/// Ends the active span of `cx`, with an error status if `result` failed.
+pub fn record_result<T>(cx: &Context, result: &Result<T>) {
+    let span = cx.span();
+    if let Err(e) = result {
+        span.set_status(Status::error(e.to_string()));
+    }
+    span.end();
+}
@@ -0,0 +1,188 @@
+use anyhow::{bail, Result};
+use reqwest::Url;
+use serde_derive::Deserialize;
+use std::time::Duration;
+
+use crate::resolver::{LinkKind, MetadataHttp, ParsedLink, Provider, ResolvedEntry};
+use crate::utils::config::CONFIG;
+
+const API_URL: &str = "https://api.music.apple.com";
+const LOOKUP_URL: &str = "https://itunes.apple.com/lookup";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AppleSongAttributes {
+    name: String,
+    artist_name: String,
+    isrc: Option<String>,
+    duration_in_millis: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct AppleSong {
+    attributes: AppleSongAttributes,
+}
+
+#[derive(Deserialize)]
+struct AppleSongs {
+    data: Vec<AppleSong>,
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LookupItem {
+    wrapper_type: String,
+    track_name: Option<String>,
+    artist_name: Option<String>,
+    track_time_millis: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct LookupResults {
+    results: Vec<LookupItem>,
+}
+
+impl From<AppleSong> for ResolvedEntry {
+    fn from(song: AppleSong) -> Self {
+        ResolvedEntry {
+            title: song.attributes.name,
+            artist: song.attributes.artist_name,
+            isrc: song.attributes.isrc,
+            duration: song.attributes.duration_in_millis.map(Duration::from_millis),
+        }
+    }
+}
+
+/// Parses `https://music.apple.com/<storefront>/<kind>/<slug>/<id>` links. An
+/// album link carrying `?i=<id>` points at a single track of that album.
+pub fn parse_url(url: &Url) -> Option<ParsedLink> {
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    let storefront = segments.first()?.to_string();
+    let id = segments.last()?.to_string();
+
+    let track_id = url
+        .query_pairs()
+        .find(|(key, _)| key == "i")
+        .map(|(_, value)| value.into_owned());
+
+    let (kind, id) = match (*segments.get(1)?, track_id) {
+        ("album", Some(track_id)) => (LinkKind::Track, track_id),
+        ("album", None) => (LinkKind::Album, id),
+        ("song", _) => (LinkKind::Track, id),
+        ("playlist", _) => (LinkKind::Playlist, id),
+        _ => return None,
+    };
+
+    Some(ParsedLink {
+        provider: Provider::AppleMusic,
+        kind,
+        id,
+        storefront: Some(storefront),
+    })
+}
+
+async fn fetch_catalog(http: &dyn MetadataHttp, token: &str, path: String) -> Result<Vec<ResolvedEntry>> {
+    let mut entries = Vec::new();
+    let mut next = Some(path);
+    while let Some(path) = next {
+        let body = http.get(&format!("{}{}", API_URL, path), Some(token)).await?;
+        let page: AppleSongs = serde_json::from_str(&body)?;
+        entries.extend(page.data.into_iter().map(ResolvedEntry::from));
+        next = page.next;
+    }
+    Ok(entries)
+}
+
+/// Falls back to the public iTunes lookup API, which needs no token but
+/// cannot see playlists and does not expose ISRCs.
+async fn fetch_lookup(http: &dyn MetadataHttp, link: &ParsedLink, storefront: &str) -> Result<Vec<ResolvedEntry>> {
+    let body = http
+        .get(
+            &format!("{}?id={}&entity=song&country={}", LOOKUP_URL, link.id, storefront),
+            None,
+        )
+        .await?;
+    let lookup: LookupResults = serde_json::from_str(&body)?;
+
+    Ok(lookup
+        .results
+        .into_iter()
+        .filter(|item| item.wrapper_type == "track")
+        .filter_map(|item| {
+            Some(ResolvedEntry {
+                title: item.track_name?,
+                artist: item.artist_name?,
+                isrc: None,
+                duration: item.track_time_millis.map(Duration::from_millis),
+            })
+        })
+        .collect())
+}
+
+pub async fn fetch(http: &dyn MetadataHttp, link: &ParsedLink) -> Result<Vec<ResolvedEntry>> {
+    let storefront = link
+        .storefront
+        .as_deref()
+        .unwrap_or(&CONFIG.resolver.apple_music_storefront);
+
+    let Some(token) = CONFIG.resolver.apple_music_token.as_deref() else {
+        if link.kind == LinkKind::Playlist {
+            bail!("APPLE_MUSIC_TOKEN must be set to resolve Apple Music playlists");
+        }
+        return fetch_lookup(http, link, storefront).await;
+    };
+
+    let path = match link.kind {
+        LinkKind::Track => format!("/v1/catalog/{}/songs/{}", storefront, link.id),
+        LinkKind::Album => format!("/v1/catalog/{}/albums/{}/tracks", storefront, link.id),
+        LinkKind::Playlist => format!("/v1/catalog/{}/playlists/{}/tracks", storefront, link.id),
+    };
+
+    fetch_catalog(http, token, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::fixtures::FixtureHttp;
+
+    fn link(kind: LinkKind, id: &str) -> ParsedLink {
+        ParsedLink {
+            provider: Provider::AppleMusic,
+            kind,
+            id: id.to_string(),
+            storefront: Some("us".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn maps_catalog_songs() {
+        let http = FixtureHttp::new(&[(
+            "https://api.music.apple.com/v1/catalog/us/songs/1440857781",
+            include_str!("fixtures/apple_catalog_songs.json"),
+        )]);
+        let entries = fetch_catalog(&http, "token", "/v1/catalog/us/songs/1440857781".to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].search_query(), "Fleetwood Mac - Dreams");
+        assert_eq!(entries[0].isrc.as_deref(), Some("USWB10700022"));
+    }
+
+    #[tokio::test]
+    async fn lookup_keeps_only_tracks() {
+        let http = FixtureHttp::new(&[(
+            "https://itunes.apple.com/lookup?id=594061854&entity=song&country=us",
+            include_str!("fixtures/apple_lookup.json"),
+        )]);
+        let entries = fetch_lookup(&http, &link(LinkKind::Album, "594061854"), "us")
+            .await
+            .unwrap();
+
+        let queries: Vec<String> = entries.iter().map(ResolvedEntry::search_query).collect();
+        assert_eq!(queries, ["Fleetwood Mac - Second Hand News", "Fleetwood Mac - Dreams"]);
+        assert!(entries.iter().all(|entry| entry.isrc.is_none()));
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::Result;
+use reqwest::Url;
+use serde_derive::Deserialize;
+use std::time::Duration;
+
+use crate::resolver::{LinkKind, MetadataHttp, ParsedLink, Provider, ResolvedEntry};
+use crate::utils::constants::DEEZER_PAGE_LIMIT;
+
+const API_URL: &str = "https://api.deezer.com";
+
+#[derive(Deserialize)]
+struct DeezerArtist {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct DeezerTrack {
+    title: String,
+    artist: DeezerArtist,
+    duration: u64,
+    isrc: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DeezerTracks {
+    data: Vec<DeezerTrack>,
+    next: Option<String>,
+}
+
+impl From<DeezerTrack> for ResolvedEntry {
+    fn from(track: DeezerTrack) -> Self {
+        ResolvedEntry {
+            title: track.title,
+            artist: track.artist.name,
+            isrc: track.isrc,
+            duration: Some(Duration::from_secs(track.duration)),
+        }
+    }
+}
+
+/// Parses `https://www.deezer.com/[lang/]<kind>/<id>` links.
+pub fn parse_url(url: &Url) -> Option<ParsedLink> {
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    let position = segments
+        .iter()
+        .position(|s| matches!(*s, "track" | "album" | "playlist"))?;
+
+    let kind = match segments[position] {
+        "track" => LinkKind::Track,
+        "album" => LinkKind::Album,
+        _ => LinkKind::Playlist,
+    };
+
+    Some(ParsedLink {
+        provider: Provider::Deezer,
+        kind,
+        id: segments.get(position + 1)?.to_string(),
+        storefront: None,
+    })
+}
+
+pub async fn fetch(http: &dyn MetadataHttp, link: &ParsedLink) -> Result<Vec<ResolvedEntry>> {
+    let collection = match link.kind {
+        LinkKind::Track => {
+            let body = http.get(&format!("{}/track/{}", API_URL, link.id), None).await?;
+            let track: DeezerTrack = serde_json::from_str(&body)?;
+            return Ok(vec![track.into()]);
+        }
+        LinkKind::Album => "album",
+        LinkKind::Playlist => "playlist",
+    };
+
+    let mut entries = Vec::new();
+    let mut next = Some(format!(
+        "{}/{}/{}/tracks?limit={}",
+        API_URL, collection, link.id, DEEZER_PAGE_LIMIT
+    ));
+    while let Some(url) = next {
+        let body = http.get(&url, None).await?;
+        let page: DeezerTracks = serde_json::from_str(&body)?;
+        entries.extend(page.data.into_iter().map(ResolvedEntry::from));
+        next = page.next;
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::fixtures::FixtureHttp;
+
+    #[tokio::test]
+    async fn maps_an_album() {
+        let http = FixtureHttp::new(&[(
+            "https://api.deezer.com/album/302127/tracks?limit=100",
+            include_str!("fixtures/deezer_album.json"),
+        )]);
+        let link = ParsedLink {
+            provider: Provider::Deezer,
+            kind: LinkKind::Album,
+            id: "302127".to_string(),
+            storefront: None,
+        };
+        let entries = fetch(&http, &link).await.unwrap();
+
+        let queries: Vec<String> = entries.iter().map(ResolvedEntry::search_query).collect();
+        assert_eq!(queries, ["Daft Punk - One More Time", "Daft Punk - Aerodynamic"]);
+        assert_eq!(entries[0].isrc.as_deref(), Some("GBDUW0000053"));
+        assert_eq!(entries[1].duration, Some(Duration::from_secs(212)));
+    }
+}
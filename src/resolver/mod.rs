@@ -0,0 +1,267 @@
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use reqwest::{Client, Url};
+use serenity::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod apple_music;
+pub mod deezer;
+pub mod spotify;
+
+/// HTTP access used by the metadata providers. Kept behind a trait so the
+/// providers can be driven from local fixtures instead of the live APIs.
+#[async_trait]
+pub trait MetadataHttp: Send + Sync {
+    async fn get(&self, url: &str, bearer: Option<&str>) -> Result<String>;
+    async fn post_form(&self, url: &str, form: &[(&str, &str)]) -> Result<String>;
+}
+
+pub struct ReqwestMetadataHttp {
+    client: Client,
+}
+
+impl ReqwestMetadataHttp {
+    pub fn new(client: Client) -> Self {
+        ReqwestMetadataHttp { client }
+    }
+}
+
+#[async_trait]
+impl MetadataHttp for ReqwestMetadataHttp {
+    async fn get(&self, url: &str, bearer: Option<&str>) -> Result<String> {
+        let mut request = self.client.get(url);
+        if let Some(token) = bearer {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?.error_for_status()?;
+        Ok(response.text().await?)
+    }
+
+    async fn post_form(&self, url: &str, form: &[(&str, &str)]) -> Result<String> {
+        let response = self.client.post(url).form(form).send().await?.error_for_status()?;
+        Ok(response.text().await?)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Provider {
+    Spotify,
+    AppleMusic,
+    Deezer,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkKind {
+    Track,
+    Album,
+    Playlist,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParsedLink {
+    pub provider: Provider,
+    pub kind: LinkKind,
+    pub id: String,
+    pub storefront: Option<String>,
+}
+
+/// A track described by a metadata provider, which still has to be mapped to
+/// a playable source.
+#[derive(Clone, Debug)]
+pub struct ResolvedEntry {
+    pub title: String,
+    pub artist: String,
+    pub isrc: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+impl ResolvedEntry {
+    pub fn search_query(&self) -> String {
+        format!("{} - {}", self.artist, self.title)
+    }
+}
+
+/// A yt-dlp search for the exact recording, used when searching by artist
+/// and title finds nothing playable.
+pub fn isrc_query(isrc: &str) -> String {
+    format!("ytsearch1:\"{}\"", isrc)
+}
+
+/// Recognises a Spotify, Apple Music or Deezer link. Returns `None` for
+/// anything else so the caller can hand it to yt-dlp unchanged.
+pub fn parse_link(link: &str) -> Option<ParsedLink> {
+    if let Some(uri) = link.strip_prefix("spotify:") {
+        return spotify::parse_uri(uri);
+    }
+
+    let url = Url::parse(link).ok()?;
+    match url.host_str()? {
+        "open.spotify.com" => spotify::parse_url(&url),
+        "music.apple.com" | "itunes.apple.com" => apple_music::parse_url(&url),
+        "www.deezer.com" | "deezer.com" => deezer::parse_url(&url),
+        _ => None,
+    }
+}
+
+pub struct MetadataResolver {
+    http: Arc<dyn MetadataHttp>,
+    spotify: spotify::SpotifyClient,
+}
+
+impl MetadataResolver {
+    pub fn new(http: Arc<dyn MetadataHttp>) -> Self {
+        MetadataResolver {
+            http,
+            spotify: spotify::SpotifyClient::new(),
+        }
+    }
+
+    /// Resolves a provider link into its tracks, in provider order. Returns
+    /// `Ok(None)` when the link does not belong to a supported provider.
+    pub async fn resolve(&self, link: &str) -> Result<Option<Vec<ResolvedEntry>>> {
+        let Some(parsed) = parse_link(link) else {
+            return Ok(None);
+        };
+
+        let entries = match parsed.provider {
+            Provider::Spotify => self.spotify.fetch(self.http.as_ref(), &parsed).await?,
+            Provider::AppleMusic => apple_music::fetch(self.http.as_ref(), &parsed).await?,
+            Provider::Deezer => deezer::fetch(self.http.as_ref(), &parsed).await?,
+        };
+
+        if entries.is_empty() {
+            return Err(anyhow!("No tracks found for {:?} link", parsed.provider));
+        }
+
+        Ok(Some(entries))
+    }
+}
+
+pub static RESOLVER: Lazy<MetadataResolver> =
+    Lazy::new(|| MetadataResolver::new(Arc::new(ReqwestMetadataHttp::new(Client::new()))));
+
+/// Serves recorded provider responses in place of the live APIs.
+#[cfg(test)]
+pub mod fixtures {
+    use super::MetadataHttp;
+    use anyhow::{anyhow, Result};
+    use serenity::async_trait;
+    use std::collections::HashMap;
+
+    pub struct FixtureHttp {
+        responses: HashMap<String, &'static str>,
+    }
+
+    impl FixtureHttp {
+        pub fn new(responses: &[(&str, &'static str)]) -> Self {
+            FixtureHttp {
+                responses: responses
+                    .iter()
+                    .map(|(url, body)| (url.to_string(), *body))
+                    .collect(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl MetadataHttp for FixtureHttp {
+        async fn get(&self, url: &str, _bearer: Option<&str>) -> Result<String> {
+            self.responses
+                .get(url)
+                .map(|body| body.to_string())
+                .ok_or_else(|| anyhow!("No fixture for {}", url))
+        }
+
+        async fn post_form(&self, url: &str, _form: &[(&str, &str)]) -> Result<String> {
+            Err(anyhow!("No fixture for {}", url))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::types::QueuedTrack;
+
+    fn link(provider: Provider, kind: LinkKind, id: &str, storefront: Option<&str>) -> ParsedLink {
+        ParsedLink {
+            provider,
+            kind,
+            id: id.to_string(),
+            storefront: storefront.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn parses_spotify_links() {
+        assert_eq!(
+            parse_link("https://open.spotify.com/track/69kOkLUCkxIZYexIgSG8rq?si=abc"),
+            Some(link(Provider::Spotify, LinkKind::Track, "69kOkLUCkxIZYexIgSG8rq", None))
+        );
+        assert_eq!(
+            parse_link("https://open.spotify.com/intl-de/album/4m2880jivSbbyEGAKfITCa"),
+            Some(link(Provider::Spotify, LinkKind::Album, "4m2880jivSbbyEGAKfITCa", None))
+        );
+        assert_eq!(
+            parse_link("spotify:playlist:37i9dQZF1DXcBWIGoYBM5M"),
+            Some(link(Provider::Spotify, LinkKind::Playlist, "37i9dQZF1DXcBWIGoYBM5M", None))
+        );
+        assert_eq!(parse_link("https://open.spotify.com/artist/4tZwfgrHOc3mvqYlEYSvVi"), None);
+    }
+
+    #[test]
+    fn parses_apple_music_links() {
+        assert_eq!(
+            parse_link("https://music.apple.com/us/album/rumours/594061854?i=1440857781"),
+            Some(link(Provider::AppleMusic, LinkKind::Track, "1440857781", Some("us")))
+        );
+        assert_eq!(
+            parse_link("https://music.apple.com/gb/album/rumours/594061854"),
+            Some(link(Provider::AppleMusic, LinkKind::Album, "594061854", Some("gb")))
+        );
+        assert_eq!(
+            parse_link("https://music.apple.com/us/playlist/todays-hits/pl.f4d106fed2bd41149aaacabb233eb5eb"),
+            Some(link(
+                Provider::AppleMusic,
+                LinkKind::Playlist,
+                "pl.f4d106fed2bd41149aaacabb233eb5eb",
+                Some("us")
+            ))
+        );
+    }
+
+    #[test]
+    fn parses_deezer_links() {
+        assert_eq!(
+            parse_link("https://www.deezer.com/fr/album/302127"),
+            Some(link(Provider::Deezer, LinkKind::Album, "302127", None))
+        );
+        assert_eq!(
+            parse_link("https://deezer.com/track/3135556"),
+            Some(link(Provider::Deezer, LinkKind::Track, "3135556", None))
+        );
+    }
+
+    #[test]
+    fn leaves_other_links_to_yt_dlp() {
+        assert_eq!(parse_link("https://www.youtube.com/watch?v=dQw4w9WgXcQ"), None);
+        assert_eq!(parse_link("never gonna give you up"), None);
+    }
+
+    #[test]
+    fn maps_entries_to_search_queries() {
+        let entry = ResolvedEntry {
+            title: "Dreams".to_string(),
+            artist: "Fleetwood Mac".to_string(),
+            isrc: Some("USWB10700022".to_string()),
+            duration: None,
+        };
+        let queued = QueuedTrack::from(entry);
+        assert_eq!(queued.source.query(), "ytsearch1:Fleetwood Mac - Dreams");
+        assert_eq!(
+            isrc_query(queued.isrc.as_deref().unwrap()),
+            "ytsearch1:\"USWB10700022\""
+        );
+    }
+}
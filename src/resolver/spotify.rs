@@ -0,0 +1,260 @@
+use anyhow::{Context, Result};
+use reqwest::Url;
+use serde_derive::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::resolver::{LinkKind, MetadataHttp, ParsedLink, Provider, ResolvedEntry};
+use crate::utils::config::CONFIG;
+use crate::utils::constants::{SPOTIFY_ALBUM_PAGE_LIMIT, SPOTIFY_PLAYLIST_PAGE_LIMIT};
+
+const TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+const API_URL: &str = "https://api.spotify.com/v1";
+
+#[derive(Deserialize)]
+struct SpotifyToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Deserialize)]
+struct SpotifyArtist {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct SpotifyExternalIds {
+    isrc: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SpotifyTrack {
+    name: String,
+    #[serde(default)]
+    artists: Vec<SpotifyArtist>,
+    duration_ms: u64,
+    external_ids: Option<SpotifyExternalIds>,
+}
+
+#[derive(Deserialize)]
+struct SpotifyPlaylistItem {
+    track: Option<SpotifyTrack>,
+}
+
+#[derive(Deserialize)]
+struct SpotifyPage<T> {
+    items: Vec<T>,
+    next: Option<String>,
+}
+
+impl From<SpotifyTrack> for ResolvedEntry {
+    fn from(track: SpotifyTrack) -> Self {
+        ResolvedEntry {
+            title: track.name,
+            artist: track
+                .artists
+                .into_iter()
+                .map(|a| a.name)
+                .collect::<Vec<_>>()
+                .join(", "),
+            isrc: track.external_ids.and_then(|ids| ids.isrc),
+            duration: Some(Duration::from_millis(track.duration_ms)),
+        }
+    }
+}
+
+fn kind_from_segment(segment: &str) -> Option<LinkKind> {
+    match segment {
+        "track" => Some(LinkKind::Track),
+        "album" => Some(LinkKind::Album),
+        "playlist" => Some(LinkKind::Playlist),
+        _ => None,
+    }
+}
+
+/// Parses `spotify:<kind>:<id>` URIs, with the `spotify:` prefix already stripped.
+pub fn parse_uri(uri: &str) -> Option<ParsedLink> {
+    let (kind, id) = uri.split_once(':')?;
+    Some(ParsedLink {
+        provider: Provider::Spotify,
+        kind: kind_from_segment(kind)?,
+        id: id.to_string(),
+        storefront: None,
+    })
+}
+
+/// Parses `https://open.spotify.com/[intl-xx/]<kind>/<id>` links.
+pub fn parse_url(url: &Url) -> Option<ParsedLink> {
+    let mut segments = url.path_segments()?.filter(|s| !s.is_empty());
+    let mut kind = segments.next()?;
+    if kind.starts_with("intl-") {
+        kind = segments.next()?;
+    }
+    Some(ParsedLink {
+        provider: Provider::Spotify,
+        kind: kind_from_segment(kind)?,
+        id: segments.next()?.to_string(),
+        storefront: None,
+    })
+}
+
+pub struct SpotifyClient {
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+impl SpotifyClient {
+    pub fn new() -> Self {
+        SpotifyClient {
+            token: Mutex::new(None),
+        }
+    }
+
+    async fn access_token(&self, http: &dyn MetadataHttp) -> Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some((value, expires_at)) = token.as_ref() {
+            if Instant::now() < *expires_at {
+                return Ok(value.clone());
+            }
+        }
+
+        let config = &CONFIG.resolver;
+        let client_id = config
+            .spotify_client_id
+            .as_deref()
+            .context("SPOTIFY_CLIENT_ID must be set to resolve Spotify links")?;
+        let client_secret = config
+            .spotify_client_secret
+            .as_deref()
+            .context("SPOTIFY_CLIENT_SECRET must be set to resolve Spotify links")?;
+
+        let body = http
+            .post_form(
+                TOKEN_URL,
+                &[
+                    ("grant_type", "client_credentials"),
+                    ("client_id", client_id),
+                    ("client_secret", client_secret),
+                ],
+            )
+            .await
+            .context("Failed to obtain Spotify access token")?;
+        let fresh: SpotifyToken = serde_json::from_str(&body)?;
+
+        // Refresh a minute early so a token never expires mid-pagination.
+        let expires_at = Instant::now() + Duration::from_secs(fresh.expires_in.saturating_sub(60));
+        *token = Some((fresh.access_token.clone(), expires_at));
+        Ok(fresh.access_token)
+    }
+
+    pub async fn fetch(&self, http: &dyn MetadataHttp, link: &ParsedLink) -> Result<Vec<ResolvedEntry>> {
+        let token = self.access_token(http).await?;
+
+        match link.kind {
+            LinkKind::Track => {
+                let body = http.get(&format!("{}/tracks/{}", API_URL, link.id), Some(&token)).await?;
+                let track: SpotifyTrack = serde_json::from_str(&body)?;
+                Ok(vec![track.into()])
+            }
+            LinkKind::Album => {
+                let mut entries = Vec::new();
+                let mut next = Some(format!(
+                    "{}/albums/{}/tracks?limit={}",
+                    API_URL, link.id, SPOTIFY_ALBUM_PAGE_LIMIT
+                ));
+                while let Some(url) = next {
+                    let body = http.get(&url, Some(&token)).await?;
+                    let page: SpotifyPage<SpotifyTrack> = serde_json::from_str(&body)?;
+                    entries.extend(page.items.into_iter().map(ResolvedEntry::from));
+                    next = page.next;
+                }
+                Ok(entries)
+            }
+            LinkKind::Playlist => {
+                let mut entries = Vec::new();
+                let mut next = Some(format!(
+                    "{}/playlists/{}/tracks?limit={}",
+                    API_URL, link.id, SPOTIFY_PLAYLIST_PAGE_LIMIT
+                ));
+                while let Some(url) = next {
+                    let body = http.get(&url, Some(&token)).await?;
+                    let page: SpotifyPage<SpotifyPlaylistItem> = serde_json::from_str(&body)?;
+                    entries.extend(
+                        page.items
+                            .into_iter()
+                            .filter_map(|item| item.track)
+                            .filter(|track| !track.artists.is_empty())
+                            .map(ResolvedEntry::from),
+                    );
+                    next = page.next;
+                }
+                Ok(entries)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::fixtures::FixtureHttp;
+
+    /// A client holding a valid token, so no credentials are needed.
+    fn client() -> SpotifyClient {
+        SpotifyClient {
+            token: Mutex::new(Some((
+                "token".to_string(),
+                Instant::now() + Duration::from_secs(3600),
+            ))),
+        }
+    }
+
+    fn link(kind: LinkKind, id: &str) -> ParsedLink {
+        ParsedLink {
+            provider: Provider::Spotify,
+            kind,
+            id: id.to_string(),
+            storefront: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn maps_a_track() {
+        let http = FixtureHttp::new(&[(
+            "https://api.spotify.com/v1/tracks/69kOkLUCkxIZYexIgSG8rq",
+            include_str!("fixtures/spotify_track.json"),
+        )]);
+        let entries = client()
+            .fetch(&http, &link(LinkKind::Track, "69kOkLUCkxIZYexIgSG8rq"))
+            .await
+            .unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].search_query(),
+            "Daft Punk, Pharrell Williams - Get Lucky (feat. Pharrell Williams and Nile Rodgers)"
+        );
+        assert_eq!(entries[0].isrc.as_deref(), Some("USQX91300108"));
+        assert_eq!(entries[0].duration, Some(Duration::from_millis(369626)));
+    }
+
+    #[tokio::test]
+    async fn follows_playlist_pages_and_skips_unplayable_items() {
+        let http = FixtureHttp::new(&[
+            (
+                "https://api.spotify.com/v1/playlists/37i9dQZF1DXcBWIGoYBM5M/tracks?limit=100",
+                include_str!("fixtures/spotify_playlist_1.json"),
+            ),
+            (
+                "https://api.spotify.com/v1/playlists/37i9dQZF1DXcBWIGoYBM5M/tracks?offset=100&limit=100",
+                include_str!("fixtures/spotify_playlist_2.json"),
+            ),
+        ]);
+        let entries = client()
+            .fetch(&http, &link(LinkKind::Playlist, "37i9dQZF1DXcBWIGoYBM5M"))
+            .await
+            .unwrap();
+
+        let queries: Vec<String> = entries.iter().map(ResolvedEntry::search_query).collect();
+        assert_eq!(queries, ["Dua Lipa - Levitating", "The Weeknd - Blinding Lights"]);
+    }
+}
@@ -1,18 +1,17 @@
 use crate::handlers::default::Handler;
-use crate::utils::config::CONFIG;
-use log::info;
+use crate::monitoring::telemetry;
+use crate::utils::config::{self, CONFIG};
+use log::{error, info};
 use serenity::prelude::GatewayIntents;
 use songbird::Config as SongbirdConfig;
 use songbird::SerenityInit;
 use std::sync::Arc;
 use songbird::Songbird;
 use crate::utils::helpers::initialize;
-use nanoid::nanoid;
-use crate::state::{initializer::StateClient, manager::State};
+use crate::state::{initializer::StateClient, manager::State, session};
 use crate::worker::connector::initialize_api;
-use crate::worker::types::{ServerIPCData, ServerIPC};
-use tokio::sync::broadcast::{Sender, Receiver};
-use tokio::sync::broadcast;
+use crate::worker::events::EventBus;
+use crate::worker::types::ServerIPC;
 
 // pub async fn initialize_state() -> State {
 //     let redis_url = &CONFIG.redis_url;
@@ -41,7 +40,7 @@ pub async fn initialize_songbird(
         let _ = client
             .start_autosharded()
             .await
-            .map_err(|why| println!("Client ended: {:?}", why));
+            .map_err(|why| error!("Client ended: {:?}", why));
     });
 
     let manager = {
@@ -53,6 +52,7 @@ pub async fn initialize_songbird(
         let mut config = SongbirdConfig::default();
         config.use_softclip = false;
         manager.set_config(config);
+        session::set_songbird(manager.clone());
         Some(manager)
     } else {
         None
@@ -62,27 +62,61 @@ pub async fn initialize_songbird(
 pub async fn initialize_worker_pool (ipc: &mut ServerIPC) {
     info!("Worker Pool Initialized");
     let songbird = initialize_songbird(ipc).await;
-    initialize_api(ipc, songbird, &nanoid!()).await;
+    initialize_api(ipc, songbird).await;
 }
 
 pub async fn initialize_ipc() -> ServerIPC {
-    let (tx_processor, _) = broadcast::channel(16);
-    let worker_rx = tx_processor.subscribe();
-    let tx_main = Arc::new(tx_processor);
     let worker_ipc = ServerIPC {
-        sender: tx_main.clone(),
-        receiver: worker_rx,
+        bus: EventBus::new(CONFIG.events.bus_capacity),
     };
 
     return worker_ipc;
 }
 
 
+/// Reloads the non-critical settings whenever the process receives SIGHUP.
+#[cfg(unix)]
+fn spawn_config_reload() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!("Failed to listen for SIGHUP, configuration reload is disabled: {}", e);
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            match config::reload() {
+                Ok(runtime) => info!(
+                    "Configuration reloaded: log level {}, idle time {}s, default volume {}",
+                    runtime.log_level, runtime.bot_idle_time_seconds, runtime.default_volume
+                ),
+                Err(errors) => {
+                    for e in errors {
+                        error!("Configuration not reloaded: {}", e);
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn spawn_config_reload() {}
+
 pub async fn start_rusty_server() {
     initialize().await;
+    spawn_config_reload();
+    if let Err(e) = telemetry::init() {
+        error!("Failed to start exporting traces: {:#}", e);
+    }
     // initialize_state().await;
     let mut ipc = initialize_ipc().await;
     initialize_worker_pool(&mut ipc).await;
+    telemetry::shutdown();
 
     log::info!("Server Starting...");
 }
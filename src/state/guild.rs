@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use songbird::tracks::TrackHandle;
+use std::num::NonZero;
+use std::time::Duration;
+
+use crate::state::session::{self, GuildSnapshot, LoopMode, SessionCommand};
+use crate::utils::config::{runtime, CONFIG};
+use crate::worker::track_cache::ResolvedTrack;
+use crate::worker::types::QueuedTrack;
+
+/// Starts the guild's session if it is not running yet, so its idle timer
+/// covers a voice channel joined without playing anything.
+pub fn open(guild_id: NonZero<u64>) {
+    session::open(guild_id);
+}
+
+pub async fn is_normalization_enabled(guild_id: NonZero<u64>) -> bool {
+    match snapshot(guild_id).await {
+        Some(snapshot) => snapshot.normalization,
+        None => CONFIG.audio.loudness_normalization,
+    }
+}
+
+pub async fn set_normalization(guild_id: NonZero<u64>, enabled: bool) -> Result<()> {
+    session::request(guild_id, |reply| SessionCommand::SetNormalization(enabled, reply)).await?
+}
+
+pub async fn set_volume(guild_id: NonZero<u64>, volume: f32) -> Result<()> {
+    session::request(guild_id, |reply| SessionCommand::SetVolume(volume, reply)).await?
+}
+
+/// Registers the track now playing in the guild and applies the guild volume
+/// combined with the track's loudness gain.
+pub async fn set_current_track(guild_id: NonZero<u64>, track: TrackHandle, gain: f32) -> Result<()> {
+    session::request(guild_id, |reply| SessionCommand::SetCurrentTrack(track, gain, reply)).await?
+}
+
+/// Records the metadata of the track about to start. Set before the track is
+/// handed to the driver so track start handlers can already read it.
+pub async fn set_now_playing(guild_id: NonZero<u64>, track: ResolvedTrack) {
+    session::send(guild_id, SessionCommand::SetNowPlaying(track)).await;
+}
+
+pub async fn now_playing(guild_id: NonZero<u64>) -> Option<(ResolvedTrack, Option<TrackHandle>)> {
+    let snapshot = snapshot(guild_id).await?;
+    Some((snapshot.now_playing?, snapshot.track))
+}
+
+pub async fn volume(guild_id: NonZero<u64>) -> f32 {
+    match snapshot(guild_id).await {
+        Some(snapshot) => snapshot.volume,
+        None => runtime().default_volume,
+    }
+}
+
+/// Appends tracks to the guild queue in order. Returns `true` when nothing is
+/// playing, meaning the caller has to start playback. Fails without queueing
+/// anything when the tracks do not fit within `MAX_QUEUE_LENGTH`.
+pub async fn enqueue(guild_id: NonZero<u64>, tracks: Vec<QueuedTrack>) -> Result<bool> {
+    session::request(guild_id, |reply| SessionCommand::Enqueue(tracks, reply)).await?
+}
+
+pub async fn upcoming(guild_id: NonZero<u64>, count: usize) -> Vec<QueuedTrack> {
+    session::query(guild_id, |reply| SessionCommand::Upcoming(count, reply))
+        .await
+        .unwrap_or_default()
+}
+
+/// Pops the next queued track, honouring the loop mode. When the queue is
+/// exhausted the guild is marked as idle so the next enqueue starts playback
+/// again.
+pub async fn next_track(guild_id: NonZero<u64>) -> Option<QueuedTrack> {
+    session::query(guild_id, SessionCommand::NextTrack).await.flatten()
+}
+
+pub async fn pause(guild_id: NonZero<u64>) -> Result<()> {
+    session::request(guild_id, |reply| SessionCommand::SetPaused(true, reply)).await?
+}
+
+pub async fn resume(guild_id: NonZero<u64>) -> Result<()> {
+    session::request(guild_id, |reply| SessionCommand::SetPaused(false, reply)).await?
+}
+
+/// Ends the current track so the queue moves on, even if it is on repeat.
+pub async fn skip(guild_id: NonZero<u64>) -> Result<()> {
+    session::request(guild_id, SessionCommand::Skip).await?
+}
+
+/// Ends the current track and drops the queue, staying in the voice channel.
+pub async fn stop_playback(guild_id: NonZero<u64>) -> Result<()> {
+    session::query(guild_id, SessionCommand::StopPlayback)
+        .await
+        .unwrap_or(Ok(()))
+}
+
+/// Whether a track is playing or being started in the guild.
+pub async fn is_playing(guild_id: NonZero<u64>) -> bool {
+    snapshot(guild_id).await.is_some_and(|snapshot| snapshot.is_playing)
+}
+
+/// Switches to the next loop mode: off, then the current track, then the
+/// whole queue.
+pub async fn cycle_loop(guild_id: NonZero<u64>) -> Result<LoopMode> {
+    session::request(guild_id, SessionCommand::CycleLoop).await
+}
+
+/// Shuffles the upcoming entries, returning how many there are.
+pub async fn shuffle(guild_id: NonZero<u64>) -> Result<usize> {
+    session::request(guild_id, SessionCommand::Shuffle).await
+}
+
+/// Seeks the current track. The seek runs outside the session so a slow
+/// source does not hold up the guild's other commands.
+pub async fn seek(guild_id: NonZero<u64>, position: Duration) -> Result<()> {
+    let track = snapshot(guild_id)
+        .await
+        .and_then(|snapshot| snapshot.track)
+        .with_context(|| format!("Nothing is playing in guild {}", guild_id))?;
+    track.seek_async(position).await?;
+    Ok(())
+}
+
+/// Stores the player filters a client asked for. They are reported back but
+/// not applied to the audio yet.
+pub async fn set_filters(guild_id: NonZero<u64>, filters: Value) {
+    session::send(guild_id, SessionCommand::SetFilters(filters)).await;
+}
+
+pub async fn snapshot(guild_id: NonZero<u64>) -> Option<GuildSnapshot> {
+    session::query(guild_id, SessionCommand::Snapshot).await
+}
+
+pub fn guild_ids() -> Vec<NonZero<u64>> {
+    session::guild_ids()
+}
+
+/// Queue length and whether a track is playing, for every guild session.
+pub async fn playback_summary() -> Vec<(NonZero<u64>, usize, bool)> {
+    let mut summary = Vec::new();
+    for guild_id in guild_ids() {
+        if let Some(snapshot) = snapshot(guild_id).await {
+            summary.push((guild_id, snapshot.queue.len(), snapshot.track.is_some()));
+        }
+    }
+    summary
+}
+
+pub async fn clear_playback(guild_id: NonZero<u64>) {
+    session::notify(guild_id, SessionCommand::ClearPlayback).await;
+}
@@ -1,2 +1,5 @@
 pub mod initializer;
-pub mod manager;
\ No newline at end of file
+pub mod manager;
+pub mod guild;
+pub mod session;
+pub mod persistence;
\ No newline at end of file
@@ -0,0 +1,55 @@
+use log::{error, warn};
+use serde_json::json;
+use tokio::sync::mpsc;
+
+use crate::state::guild;
+use crate::state::initializer::StateClient;
+use crate::state::manager::State;
+use crate::utils::config::CONFIG;
+use crate::utils::constants::{SESSION_KEY_PREFIX, SESSION_TTL_SECONDS};
+use crate::utils::helpers::get_timestamp;
+use crate::worker::types::ServerIPCData;
+
+/// Writes a guild's playback state to Redis after each of its worker events,
+/// and removes it once the guild has no session left.
+pub async fn run(mut events: mpsc::Receiver<ServerIPCData>) {
+    let Some(redis_url) = CONFIG.redis_url.as_deref() else {
+        return;
+    };
+    let state = match StateClient::new(redis_url) {
+        Ok(client) => State::new(client),
+        Err(e) => {
+            error!("Failed to initialize Redis, sessions are not persisted: {}", e);
+            return;
+        }
+    };
+
+    while let Some(event) = events.recv().await {
+        let key = format!("{}{}", SESSION_KEY_PREFIX, event.guild_id);
+        let written = match guild::snapshot(event.guild_id).await {
+            Some(snapshot) => {
+                let record = json!({
+                    "job_id": event.job_id,
+                    "last_event": event.name(),
+                    "volume": snapshot.volume,
+                    "paused": snapshot.paused,
+                    "loop_mode": snapshot.loop_mode,
+                    "now_playing": snapshot.now_playing,
+                    "queue": snapshot
+                        .queue
+                        .iter()
+                        .map(|track| track.source.query())
+                        .collect::<Vec<_>>(),
+                    "updated_at": get_timestamp(),
+                });
+                state
+                    .set_with_expiry(&key, &record.to_string(), SESSION_TTL_SECONDS)
+                    .await
+            }
+            None => state.delete(&key).await,
+        };
+        if let Err(e) = written {
+            warn!("Failed to persist the session of guild {}: {}", event.guild_id, e);
+        }
+    }
+}
@@ -0,0 +1,439 @@
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use once_cell::sync::{Lazy, OnceCell};
+use rand::seq::SliceRandom;
+use serde_derive::Serialize;
+use serde_json::Value;
+use songbird::id::GuildId;
+use songbird::tracks::TrackHandle;
+use songbird::Songbird;
+use std::collections::HashMap;
+use std::future::pending;
+use std::num::NonZero;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{sleep_until, Instant};
+
+use crate::utils::config::{runtime, CONFIG};
+use crate::utils::constants::SESSION_INBOX_CAPACITY;
+use crate::utils::logger::LogContext;
+use crate::worker::track_cache::ResolvedTrack;
+use crate::worker::types::{GuildQueue, QueuedTrack};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoopMode {
+    Off,
+    Track,
+    Queue,
+}
+
+impl LoopMode {
+    /// The mode a Loop command switches to.
+    fn next(self) -> Self {
+        match self {
+            LoopMode::Off => LoopMode::Track,
+            LoopMode::Track => LoopMode::Queue,
+            LoopMode::Queue => LoopMode::Off,
+        }
+    }
+}
+
+/// A copy of a guild session's state.
+pub struct GuildSnapshot {
+    pub volume: f32,
+    pub normalization: bool,
+    pub gain: f32,
+    pub track: Option<TrackHandle>,
+    pub paused: bool,
+    pub now_playing: Option<ResolvedTrack>,
+    pub queue: Vec<QueuedTrack>,
+    pub is_playing: bool,
+    pub loop_mode: LoopMode,
+    pub filters: Value,
+}
+
+pub enum SessionCommand {
+    Snapshot(oneshot::Sender<GuildSnapshot>),
+    Enqueue(Vec<QueuedTrack>, oneshot::Sender<Result<bool>>),
+    NextTrack(oneshot::Sender<Option<QueuedTrack>>),
+    Upcoming(usize, oneshot::Sender<Vec<QueuedTrack>>),
+    SetNowPlaying(ResolvedTrack),
+    SetCurrentTrack(TrackHandle, f32, oneshot::Sender<Result<()>>),
+    SetVolume(f32, oneshot::Sender<Result<()>>),
+    SetNormalization(bool, oneshot::Sender<Result<()>>),
+    SetPaused(bool, oneshot::Sender<Result<()>>),
+    Skip(oneshot::Sender<Result<()>>),
+    StopPlayback(oneshot::Sender<Result<()>>),
+    CycleLoop(oneshot::Sender<LoopMode>),
+    Shuffle(oneshot::Sender<usize>),
+    SetFilters(Value),
+    ClearPlayback,
+}
+
+/// Everything the worker knows about one guild's playback. Each session is
+/// owned by its own task, which applies commands from its inbox one at a
+/// time, so no lock is shared between guilds.
+struct GuildSession {
+    guild_id: NonZero<u64>,
+    volume: f32,
+    normalization: bool,
+    gain: f32,
+    track: Option<TrackHandle>,
+    paused: bool,
+    /// The entry handed out by `NextTrack` that has not started yet. It only
+    /// becomes `current` once it plays, so an entry that fails to resolve is
+    /// not repeated by the loop modes.
+    pending: Option<QueuedTrack>,
+    current: Option<QueuedTrack>,
+    now_playing: Option<ResolvedTrack>,
+    queue: GuildQueue,
+    loop_mode: LoopMode,
+    skip_requested: bool,
+    filters: Value,
+    idle_since: Option<Instant>,
+}
+
+impl GuildSession {
+    fn new(guild_id: NonZero<u64>) -> Self {
+        GuildSession {
+            guild_id,
+            volume: runtime().default_volume,
+            normalization: CONFIG.audio.loudness_normalization,
+            gain: 1.0,
+            track: None,
+            paused: false,
+            pending: None,
+            current: None,
+            now_playing: None,
+            queue: GuildQueue::new(),
+            loop_mode: LoopMode::Off,
+            skip_requested: false,
+            filters: Value::Object(Default::default()),
+            idle_since: Some(Instant::now()),
+        }
+    }
+
+    fn effective_volume(&self) -> f32 {
+        self.volume * self.gain
+    }
+
+    fn apply_volume(&self) -> Result<()> {
+        if let Some(track) = &self.track {
+            track.set_volume(self.effective_volume())?;
+        }
+        Ok(())
+    }
+
+    fn is_idle(&self) -> bool {
+        self.track.is_none() && self.queue.track_queue.is_empty()
+    }
+
+    fn reset_playback(&mut self) {
+        self.queue.is_playing = false;
+        self.track = None;
+        self.paused = false;
+        self.pending = None;
+        self.current = None;
+        self.now_playing = None;
+        self.gain = 1.0;
+    }
+
+    fn snapshot(&self) -> GuildSnapshot {
+        GuildSnapshot {
+            volume: self.volume,
+            normalization: self.normalization,
+            gain: self.gain,
+            track: self.track.clone(),
+            paused: self.paused,
+            now_playing: self.now_playing.clone(),
+            queue: self.queue.track_queue.iter().cloned().collect(),
+            is_playing: self.queue.is_playing,
+            loop_mode: self.loop_mode,
+            filters: self.filters.clone(),
+        }
+    }
+
+    /// Pops the next queued entry, first putting the finished one back
+    /// according to the loop mode. A skip always moves past the current
+    /// track, even when it is on repeat. When the queue is exhausted the
+    /// guild is marked idle so the next enqueue starts playback again.
+    fn next_track(&mut self) -> Option<QueuedTrack> {
+        let skipped = std::mem::take(&mut self.skip_requested);
+        if let Some(finished) = self.current.take() {
+            match self.loop_mode {
+                LoopMode::Track if !skipped => self.queue.track_queue.push_front(finished),
+                LoopMode::Queue => self.queue.add_track(finished),
+                _ => {}
+            }
+        }
+
+        let next = self.queue.next_track();
+        if next.is_none() {
+            self.reset_playback();
+        }
+        self.pending = next.clone();
+        next
+    }
+
+    fn playing_track(&self) -> Result<&TrackHandle> {
+        self.track
+            .as_ref()
+            .ok_or_else(|| anyhow!("Nothing is playing in guild {}", self.guild_id))
+    }
+
+    fn handle(&mut self, command: SessionCommand) {
+        match command {
+            SessionCommand::Snapshot(reply) => {
+                let _ = reply.send(self.snapshot());
+            }
+            SessionCommand::Enqueue(tracks, reply) => {
+                let limit = CONFIG.config.max_queue_length;
+                let room = limit.saturating_sub(self.queue.track_queue.len());
+                if tracks.len() > room {
+                    let _ = reply.send(Err(anyhow!(
+                        "The queue is limited to {} tracks, only {} more can be added",
+                        limit,
+                        room
+                    )));
+                    return;
+                }
+                for track in tracks {
+                    self.queue.add_track(track);
+                }
+                let should_start = !self.queue.is_playing;
+                self.queue.is_playing = true;
+                let _ = reply.send(Ok(should_start));
+            }
+            SessionCommand::NextTrack(reply) => {
+                let _ = reply.send(self.next_track());
+            }
+            SessionCommand::Upcoming(count, reply) => {
+                let upcoming = self.queue.track_queue.iter().take(count).cloned().collect();
+                let _ = reply.send(upcoming);
+            }
+            SessionCommand::SetNowPlaying(track) => self.now_playing = Some(track),
+            SessionCommand::SetCurrentTrack(track, gain, reply) => {
+                self.gain = gain;
+                let result = track.set_volume(self.effective_volume()).map_err(Into::into);
+                if result.is_ok() {
+                    self.track = Some(track);
+                    self.paused = false;
+                    self.current = self.pending.take();
+                }
+                let _ = reply.send(result);
+            }
+            SessionCommand::SetVolume(volume, reply) => {
+                self.volume = volume;
+                let _ = reply.send(self.apply_volume());
+            }
+            SessionCommand::SetNormalization(enabled, reply) => {
+                self.normalization = enabled;
+                if !enabled {
+                    self.gain = 1.0;
+                }
+                let _ = reply.send(self.apply_volume());
+            }
+            SessionCommand::SetPaused(paused, reply) => {
+                let result = self.playing_track().and_then(|track| {
+                    if paused {
+                        track.pause()?;
+                    } else {
+                        track.play()?;
+                    }
+                    Ok(())
+                });
+                if result.is_ok() {
+                    self.paused = paused;
+                }
+                let _ = reply.send(result);
+            }
+            SessionCommand::Skip(reply) => {
+                // Stopping the track fires its `QueueAdvancer`, which starts
+                // the next entry.
+                let result = self
+                    .playing_track()
+                    .and_then(|track| track.stop().map_err(Into::into));
+                if result.is_ok() {
+                    self.skip_requested = true;
+                }
+                let _ = reply.send(result);
+            }
+            SessionCommand::StopPlayback(reply) => {
+                // The stopped track's `QueueAdvancer` finds the queue empty
+                // and marks the guild idle. Dropping `current` keeps a looping
+                // queue from taking the stopped track back.
+                self.queue.track_queue.clear();
+                self.current = None;
+                let result = match &self.track {
+                    Some(track) => {
+                        self.skip_requested = true;
+                        track.stop().map_err(Into::into)
+                    }
+                    None => Ok(()),
+                };
+                let _ = reply.send(result);
+            }
+            SessionCommand::CycleLoop(reply) => {
+                self.loop_mode = self.loop_mode.next();
+                let _ = reply.send(self.loop_mode);
+            }
+            SessionCommand::Shuffle(reply) => {
+                self.queue
+                    .track_queue
+                    .make_contiguous()
+                    .shuffle(&mut rand::thread_rng());
+                let _ = reply.send(self.queue.track_queue.len());
+            }
+            SessionCommand::SetFilters(filters) => self.filters = filters,
+            SessionCommand::ClearPlayback => {
+                self.queue.clear();
+                self.reset_playback();
+            }
+        }
+
+        if !self.is_idle() {
+            self.idle_since = None;
+        } else if self.idle_since.is_none() {
+            self.idle_since = Some(Instant::now());
+        }
+    }
+
+    async fn run(mut self, mut inbox: mpsc::Receiver<SessionCommand>) {
+        loop {
+            let idle_deadline = self
+                .idle_since
+                .map(|since| since + Duration::from_secs(runtime().bot_idle_time_seconds));
+            let idle_timeout = async {
+                match idle_deadline {
+                    Some(deadline) => sleep_until(deadline).await,
+                    None => pending().await,
+                }
+            };
+
+            tokio::select! {
+                command = inbox.recv() => match command {
+                    Some(command) => self.handle(command),
+                    None => break,
+                },
+                _ = idle_timeout => {
+                    if close_if_idle(self.guild_id, &inbox) {
+                        self.leave().await;
+                        break;
+                    }
+                }
+            }
+        }
+        debug!("Session for guild {} ended", self.guild_id);
+    }
+
+    /// Leaves the voice channel once the session has been idle for
+    /// `BOT_IDLE_TIME_SECONDS`.
+    async fn leave(&self) {
+        let Some(songbird) = SONGBIRD.get() else {
+            return;
+        };
+        if songbird.get(GuildId(self.guild_id)).is_none() {
+            return;
+        }
+        info!(
+            "Leaving guild {} after {}s without playback",
+            self.guild_id,
+            runtime().bot_idle_time_seconds
+        );
+        if let Err(e) = songbird.remove(GuildId(self.guild_id)).await {
+            warn!("Failed to leave idle guild {}: {}", self.guild_id, e);
+        }
+    }
+}
+
+static SESSIONS: Lazy<Mutex<HashMap<NonZero<u64>, mpsc::Sender<SessionCommand>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static SONGBIRD: OnceCell<Arc<Songbird>> = OnceCell::new();
+
+/// Gives sessions the voice manager they leave idle channels through.
+pub fn set_songbird(songbird: Arc<Songbird>) {
+    let _ = SONGBIRD.set(songbird);
+}
+
+/// Removes the session from the registry unless a command reached its inbox
+/// in the meantime. Holding the registry lock means no new sender can be
+/// handed out while deciding.
+fn close_if_idle(guild_id: NonZero<u64>, inbox: &mpsc::Receiver<SessionCommand>) -> bool {
+    let mut sessions = SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
+    if !inbox.is_empty() {
+        return false;
+    }
+    sessions.remove(&guild_id);
+    true
+}
+
+/// The guild's session inbox, starting the session if it is not running.
+pub fn open(guild_id: NonZero<u64>) -> mpsc::Sender<SessionCommand> {
+    let mut sessions = SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(sender) = sessions.get(&guild_id).filter(|sender| !sender.is_closed()) {
+        return sender.clone();
+    }
+
+    let (sender, inbox) = mpsc::channel(SESSION_INBOX_CAPACITY);
+    let session = GuildSession::new(guild_id);
+    tokio::spawn(LogContext::guild(guild_id).scope(session.run(inbox)));
+    sessions.insert(guild_id, sender.clone());
+    sender
+}
+
+fn existing(guild_id: NonZero<u64>) -> Option<mpsc::Sender<SessionCommand>> {
+    let sessions = SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
+    sessions.get(&guild_id).cloned()
+}
+
+/// Guilds with a running session.
+pub fn guild_ids() -> Vec<NonZero<u64>> {
+    let sessions = SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
+    sessions.keys().copied().collect()
+}
+
+/// Delivers a command, starting the session if needed. A session that ended
+/// between looking it up and sending is started again.
+pub async fn send(guild_id: NonZero<u64>, command: SessionCommand) {
+    let mut command = command;
+    for _ in 0..2 {
+        match open(guild_id).send(command).await {
+            Ok(()) => return,
+            Err(mpsc::error::SendError(returned)) => command = returned,
+        }
+    }
+    warn!("Session for guild {} is not accepting commands", guild_id);
+}
+
+/// Sends a command built around a reply channel and waits for the reply.
+pub async fn request<T>(
+    guild_id: NonZero<u64>,
+    command: impl FnOnce(oneshot::Sender<T>) -> SessionCommand,
+) -> Result<T> {
+    let (reply, response) = oneshot::channel();
+    send(guild_id, command(reply)).await;
+    response
+        .await
+        .map_err(|_| anyhow!("Session for guild {} stopped", guild_id))
+}
+
+/// Delivers a command only if the session is already running.
+pub async fn notify(guild_id: NonZero<u64>, command: SessionCommand) {
+    if let Some(sender) = existing(guild_id) {
+        let _ = sender.send(command).await;
+    }
+}
+
+/// Like `request`, but only asks a session that is already running.
+pub async fn query<T>(
+    guild_id: NonZero<u64>,
+    command: impl FnOnce(oneshot::Sender<T>) -> SessionCommand,
+) -> Option<T> {
+    let sender = existing(guild_id)?;
+    let (reply, response) = oneshot::channel();
+    sender.send(command(reply)).await.ok()?;
+    response.await.ok()
+}
@@ -0,0 +1,84 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use log::warn;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::transport::{InboundMessage, Transport};
+use crate::utils::helpers::get_timestamp;
+
+/// A message the worker could not process, as written to the dead-letter
+/// topic. UTF-8 payloads are stored as text so they can be fixed by hand;
+/// anything else is kept as base64.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeadLetter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_base64: Option<String>,
+    pub error: String,
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub timestamp: u64,
+}
+
+impl DeadLetter {
+    pub fn new(message: &InboundMessage, error: &str) -> Self {
+        let (payload, payload_base64) = match message.payload.as_deref() {
+            Some(bytes) => match std::str::from_utf8(bytes) {
+                Ok(text) => (Some(text.to_string()), None),
+                Err(_) => (None, Some(STANDARD.encode(bytes))),
+            },
+            None => (None, None),
+        };
+
+        DeadLetter {
+            payload,
+            payload_base64,
+            error: error.to_string(),
+            topic: message.topic.clone(),
+            partition: message.partition,
+            offset: message.offset,
+            timestamp: get_timestamp(),
+        }
+    }
+
+    /// The original payload bytes.
+    pub fn raw_payload(&self) -> Result<Vec<u8>> {
+        match (&self.payload_base64, &self.payload) {
+            (Some(encoded), _) => STANDARD
+                .decode(encoded)
+                .context("Dead letter payload is not valid base64"),
+            (None, Some(text)) => Ok(text.clone().into_bytes()),
+            (None, None) => Err(anyhow::anyhow!("Dead letter has no payload")),
+        }
+    }
+}
+
+/// Writes a failed message to the dead-letter topic. The message must only
+/// be acknowledged once this succeeds, or its payload is lost.
+pub async fn publish(
+    transport: &dyn Transport,
+    dead_letter_topic: &str,
+    message: &InboundMessage,
+    error: &str,
+) -> Result<()> {
+    let record = DeadLetter::new(message, error);
+    let data = serde_json::to_vec(&record).context("Failed to serialize dead letter")?;
+
+    transport
+        .send(dead_letter_topic, &data)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to dead-letter message {}[{}]@{}",
+                message.topic, message.partition, message.offset
+            )
+        })?;
+    warn!(
+        "Dead-lettered message {}[{}]@{}: {}",
+        message.topic, message.partition, message.offset, error
+    );
+    Ok(())
+}
@@ -0,0 +1,563 @@
+use anyhow::{anyhow, Context, Result};
+use log::{debug, warn};
+use once_cell::sync::OnceCell;
+use rdkafka::consumer::{CommitMode, Consumer, ConsumerContext, Rebalance, StreamConsumer};
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use rdkafka::message::{Header, Headers, OwnedHeaders};
+use rdkafka::{ClientConfig, ClientContext, Offset, Statistics, TopicPartitionList};
+use rdkafka::Message as KafkaMessage;
+use serenity::async_trait;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::monitoring::metrics;
+use crate::transport::{InboundMessage, Transport};
+use crate::utils::config::{KafkaConfig, CONFIG};
+use crate::utils::constants::{
+    KAFKA_SEND_TIMEOUT, KAFKA_STATISTICS_INTERVAL_MS, READINESS_TIMEOUT_SECONDS,
+};
+use crate::utils::helpers::minutes_to_duration;
+
+const SECURITY_PROTOCOLS: [&str; 4] = ["PLAINTEXT", "SSL", "SASL_PLAINTEXT", "SASL_SSL"];
+const SASL_MECHANISMS: [&str; 4] = ["PLAIN", "SCRAM-SHA-256", "SCRAM-SHA-512", "OAUTHBEARER"];
+
+/// The configured security protocol. Without `KAFKA_SECURITY_PROTOCOL` it is
+/// derived from the older `KAFKA_USE_SSL` and `KAFKA_USE_SASL` flags.
+fn security_protocol(config: &KafkaConfig) -> String {
+    if let Some(protocol) = &config.kafka_security_protocol {
+        return protocol.to_uppercase();
+    }
+    let protocol = match (
+        config.kafka_use_ssl.unwrap_or(false),
+        config.kafka_use_sasl.unwrap_or(false),
+    ) {
+        (true, true) => "SASL_SSL",
+        (true, false) => "SSL",
+        (false, true) => "SASL_PLAINTEXT",
+        (false, false) => "PLAINTEXT",
+    };
+    protocol.to_string()
+}
+
+fn check_file(problems: &mut Vec<String>, variable: &str, path: &str) {
+    if !Path::new(path).is_file() {
+        problems.push(format!("{} points to {}, which is not a readable file", variable, path));
+    }
+}
+
+/// Builds the librdkafka security properties, followed by the passthrough
+/// `KAFKA_PROPERTY_*` properties. Every problem with the configuration is
+/// collected so they can all be reported at once.
+pub fn security_properties(config: &KafkaConfig) -> Result<Vec<(String, String)>, Vec<String>> {
+    let mut properties: Vec<(String, String)> = Vec::new();
+    let mut problems = Vec::new();
+
+    let protocol = security_protocol(config);
+    if !SECURITY_PROTOCOLS.contains(&protocol.as_str()) {
+        problems.push(format!(
+            "KAFKA_SECURITY_PROTOCOL must be one of {}, got {}",
+            SECURITY_PROTOCOLS.join(", "),
+            protocol
+        ));
+    }
+    properties.push(("security.protocol".to_string(), protocol.clone()));
+
+    let uses_ssl = protocol == "SSL" || protocol == "SASL_SSL";
+    let uses_sasl = protocol == "SASL_PLAINTEXT" || protocol == "SASL_SSL";
+
+    if uses_ssl {
+        if let Some(ca) = &config.kafka_ssl_ca {
+            check_file(&mut problems, "KAFKA_SSL_CA", ca);
+            properties.push(("ssl.ca.location".to_string(), ca.clone()));
+        }
+        match (&config.kafka_ssl_cert, &config.kafka_ssl_key) {
+            (Some(cert), Some(key)) => {
+                check_file(&mut problems, "KAFKA_SSL_CERT", cert);
+                check_file(&mut problems, "KAFKA_SSL_KEY", key);
+                properties.push(("ssl.certificate.location".to_string(), cert.clone()));
+                properties.push(("ssl.key.location".to_string(), key.clone()));
+            }
+            (None, None) => {}
+            _ => problems.push("KAFKA_SSL_CERT and KAFKA_SSL_KEY must be set together".to_string()),
+        }
+        if let Some(password) = &config.kafka_ssl_key_password {
+            properties.push(("ssl.key.password".to_string(), password.clone()));
+        }
+    } else if config.kafka_ssl_ca.is_some()
+        || config.kafka_ssl_cert.is_some()
+        || config.kafka_ssl_key.is_some()
+    {
+        problems.push(format!(
+            "KAFKA_SSL_* settings are set but security protocol {} does not use SSL",
+            protocol
+        ));
+    }
+
+    if uses_sasl {
+        let mechanism = config
+            .kafka_sasl_mechanism
+            .as_deref()
+            .unwrap_or("PLAIN")
+            .to_uppercase();
+        match mechanism.as_str() {
+            "PLAIN" | "SCRAM-SHA-256" | "SCRAM-SHA-512" => {
+                match (&config.kafka_username, &config.kafka_password) {
+                    (Some(username), Some(password)) => {
+                        properties.push(("sasl.username".to_string(), username.clone()));
+                        properties.push(("sasl.password".to_string(), password.clone()));
+                    }
+                    _ => problems.push(format!(
+                        "KAFKA_USERNAME and KAFKA_PASSWORD are required for SASL {}",
+                        mechanism
+                    )),
+                }
+            }
+            "OAUTHBEARER" => match (
+                &config.kafka_oauth_token_endpoint,
+                &config.kafka_oauth_client_id,
+                &config.kafka_oauth_client_secret,
+            ) {
+                (Some(endpoint), Some(client_id), Some(client_secret)) => {
+                    properties.push(("sasl.oauthbearer.method".to_string(), "oidc".to_string()));
+                    properties.push(("sasl.oauthbearer.token.endpoint.url".to_string(), endpoint.clone()));
+                    properties.push(("sasl.oauthbearer.client.id".to_string(), client_id.clone()));
+                    properties.push(("sasl.oauthbearer.client.secret".to_string(), client_secret.clone()));
+                    if let Some(scope) = &config.kafka_oauth_scope {
+                        properties.push(("sasl.oauthbearer.scope".to_string(), scope.clone()));
+                    }
+                }
+                _ => problems.push(
+                    "KAFKA_OAUTH_TOKEN_ENDPOINT, KAFKA_OAUTH_CLIENT_ID and KAFKA_OAUTH_CLIENT_SECRET are required for SASL OAUTHBEARER"
+                        .to_string(),
+                ),
+            },
+            _ => problems.push(format!(
+                "KAFKA_SASL_MECHANISM must be one of {}, got {}",
+                SASL_MECHANISMS.join(", "),
+                mechanism
+            )),
+        }
+        properties.push(("sasl.mechanisms".to_string(), mechanism));
+    } else if config.kafka_sasl_mechanism.is_some() {
+        problems.push(format!(
+            "KAFKA_SASL_MECHANISM is set but security protocol {} does not use SASL",
+            protocol
+        ));
+    }
+
+    properties.extend(config.kafka_properties.iter().cloned());
+
+    if problems.is_empty() {
+        Ok(properties)
+    } else {
+        Err(problems)
+    }
+}
+
+/// Applies the security and passthrough properties last, so passthrough
+/// properties can override anything set before them.
+fn apply_security(kafka_config: &mut ClientConfig) -> Result<()> {
+    let properties = security_properties(&CONFIG.kafka).map_err(|problems| {
+        anyhow!("Invalid Kafka configuration: {}", problems.join("; "))
+    })?;
+    for (key, value) in properties {
+        kafka_config.set(key, value);
+    }
+    Ok(())
+}
+
+pub fn initialize_producer(brokers: &str) -> Result<FutureProducer> {
+    let mut kafka_config = ClientConfig::new();
+    kafka_config
+        .set("bootstrap.servers", brokers)
+        .set("linger.ms", CONFIG.kafka.kafka_linger_ms.to_string())
+        .set("batch.num.messages", CONFIG.kafka.kafka_batch_size.to_string());
+    apply_security(&mut kafka_config)?;
+
+    let producer: FutureProducer = kafka_config
+        .create()
+        .context("Failed to create Generic Producer")?;
+    Ok(producer)
+}
+
+static PRODUCER: OnceCell<FutureProducer> = OnceCell::new();
+
+/// The producer shared by every Kafka transport in the process. It is safe
+/// to use concurrently, so sends from unrelated jobs are not serialized and
+/// librdkafka batches them together.
+pub fn shared_producer(brokers: &str) -> Result<FutureProducer> {
+    PRODUCER
+        .get_or_try_init(|| initialize_producer(brokers))
+        .cloned()
+}
+
+fn record<'a>(
+    topic: &'a str,
+    key: Option<&'a str>,
+    payload: &'a [u8],
+    headers: &HashMap<String, String>,
+) -> FutureRecord<'a, str, [u8]> {
+    let headers = headers.iter().fold(OwnedHeaders::new(), |owned, (key, value)| {
+        owned.insert(Header {
+            key,
+            value: Some(value),
+        })
+    });
+    let record = FutureRecord::to(topic).payload(payload).headers(headers);
+    match key {
+        Some(key) => record.key(key),
+        None => record,
+    }
+}
+
+/// Fetches `topic`'s metadata through a fresh producer, returning its
+/// partition count. Used to check that the brokers can be reached.
+pub async fn topic_partitions(brokers: &str, topic: &str, timeout: Duration) -> Result<usize> {
+    let producer = initialize_producer(brokers)?;
+    let topic = topic.to_string();
+
+    tokio::task::spawn_blocking(move || {
+        let metadata = producer
+            .client()
+            .fetch_metadata(Some(&topic), timeout)
+            .context("Failed to fetch metadata")?;
+        let topic_metadata = metadata
+            .topics()
+            .iter()
+            .find(|t| t.name() == topic)
+            .with_context(|| format!("Topic {} not found", topic))?;
+        if let Some(e) = topic_metadata.error() {
+            return Err(anyhow!("Topic {} is unavailable: {:?}", topic, e));
+        }
+        Ok(topic_metadata.partitions().len())
+    })
+    .await?
+}
+
+/// Consumer context that exports the per-partition consumer lag from
+/// librdkafka's periodic statistics, and forgets the offsets of partitions
+/// the consumer loses or is handed in a rebalance.
+pub struct TransportContext {
+    offsets: Arc<OffsetTracker>,
+}
+
+impl ClientContext for TransportContext {
+    fn stats(&self, statistics: Statistics) {
+        for topic in statistics.topics.values() {
+            for partition in topic.partitions.values() {
+                // Partition -1 is librdkafka's internal unassigned partition,
+                // and a negative lag means it is not known yet.
+                if partition.partition < 0 || partition.consumer_lag < 0 {
+                    continue;
+                }
+                metrics::KAFKA_CONSUMER_LAG
+                    .with_label_values(&[&topic.topic, &partition.partition.to_string()])
+                    .set(partition.consumer_lag);
+            }
+        }
+    }
+}
+
+impl ConsumerContext for TransportContext {
+    /// Jobs still running for revoked partitions must not commit them, since
+    /// another consumer now owns their offsets.
+    fn pre_rebalance(&self, rebalance: &Rebalance) {
+        if let Rebalance::Revoke(partitions) = rebalance {
+            self.offsets.forget(partitions);
+        }
+    }
+
+    /// Assigned partitions resume from their committed offset, so nothing
+    /// tracked for them before is still meaningful.
+    fn post_rebalance(&self, rebalance: &Rebalance) {
+        match rebalance {
+            Rebalance::Assign(partitions) => self.offsets.forget(partitions),
+            Rebalance::Error(e) => warn!("Kafka rebalance failed: {}", e),
+            Rebalance::Revoke(_) => {}
+        }
+    }
+}
+
+pub fn initialize_consumer(
+    brokers: &str,
+    group_id: &str,
+    topic: &str,
+    offset_reset: &str,
+    offsets: Arc<OffsetTracker>,
+) -> Result<StreamConsumer<TransportContext>> {
+    let mut kafka_config = ClientConfig::new();
+    kafka_config
+        .set("group.id", group_id)
+        .set("bootstrap.servers", brokers)
+        .set("enable.partition.eof", "false")
+        .set("session.timeout.ms", "6000")
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", offset_reset)
+        .set("statistics.interval.ms", KAFKA_STATISTICS_INTERVAL_MS.to_string());
+    apply_security(&mut kafka_config)?;
+
+    let consumer: StreamConsumer<TransportContext> = kafka_config
+        .create_with_context(TransportContext { offsets })
+        .context("Failed to create Consumer")?;
+
+    consumer
+        .subscribe(&[topic])
+        .with_context(|| format!("Can't subscribe to topic {}", topic))?;
+
+    Ok(consumer)
+}
+
+#[derive(Default)]
+struct PartitionOffsets {
+    in_flight: BTreeSet<i64>,
+    completed_up_to: Option<i64>,
+    committed: Option<i64>,
+}
+
+/// Tracks which offsets of each partition are still being worked on. Jobs
+/// finish out of order, so the committable offset is the lowest one still in
+/// flight; committing past it would drop that job if the worker crashed.
+#[derive(Default)]
+pub struct OffsetTracker {
+    partitions: Mutex<HashMap<(String, i32), PartitionOffsets>>,
+}
+
+impl OffsetTracker {
+    pub fn begin(&self, topic: &str, partition: i32, offset: i64) {
+        let mut partitions = self.partitions.lock().unwrap();
+        let offsets = partitions.entry((topic.to_string(), partition)).or_default();
+        offsets.in_flight.insert(offset);
+    }
+
+    /// Marks an offset as done. Returns the offset to commit for the
+    /// partition when it has moved forward.
+    pub fn complete(&self, topic: &str, partition: i32, offset: i64) -> Option<i64> {
+        let mut partitions = self.partitions.lock().unwrap();
+        let offsets = partitions.get_mut(&(topic.to_string(), partition))?;
+        if !offsets.in_flight.remove(&offset) {
+            return None;
+        }
+        offsets.completed_up_to = offsets.completed_up_to.max(Some(offset + 1));
+
+        let next = match offsets.in_flight.first() {
+            Some(&lowest) => lowest,
+            None => offsets.completed_up_to?,
+        };
+        if offsets.committed >= Some(next) {
+            return None;
+        }
+        offsets.committed = Some(next);
+        Some(next)
+    }
+
+    /// Drops everything tracked for `partitions`. Offsets completed for them
+    /// afterwards are not committed.
+    pub fn forget(&self, partitions: &TopicPartitionList) {
+        let mut tracked = self.partitions.lock().unwrap();
+        for element in partitions.elements() {
+            tracked.remove(&(element.topic().to_string(), element.partition()));
+        }
+    }
+}
+
+pub struct KafkaTransport {
+    consumer: StreamConsumer<TransportContext>,
+    producer: FutureProducer,
+    offsets: Arc<OffsetTracker>,
+}
+
+impl KafkaTransport {
+    /// Consumes the request topic as `KAFKA_GROUP_ID`. A group without
+    /// committed offsets starts at the end of the topic, so a new or reset
+    /// group never replays old requests against live guilds.
+    pub async fn new(brokers: &str) -> Result<Self> {
+        Self::subscribe(
+            brokers,
+            &CONFIG.kafka.kafka_group_id,
+            &CONFIG.kafka.kafka_topic,
+            "latest",
+        )
+        .await
+    }
+
+    /// Consumes `topic` instead of the request topic. With `offset_reset` set
+    /// to `earliest`, a new group starts from the beginning of the topic.
+    pub async fn subscribe(
+        brokers: &str,
+        group_id: &str,
+        topic: &str,
+        offset_reset: &str,
+    ) -> Result<Self> {
+        let offsets = Arc::new(OffsetTracker::default());
+        Ok(KafkaTransport {
+            consumer: initialize_consumer(brokers, group_id, topic, offset_reset, offsets.clone())?,
+            producer: shared_producer(brokers)?,
+            offsets,
+        })
+    }
+}
+
+#[async_trait]
+impl Transport for KafkaTransport {
+    async fn recv(&self) -> Result<Option<InboundMessage>> {
+        let message = self.consumer.recv().await?;
+        self.offsets.begin(message.topic(), message.partition(), message.offset());
+        Ok(Some(InboundMessage {
+            payload: message.payload().map(|p| p.to_vec()),
+            topic: message.topic().to_string(),
+            partition: message.partition(),
+            offset: message.offset(),
+            id: None,
+            headers: message
+                .headers()
+                .map(|headers| {
+                    headers
+                        .iter()
+                        .filter_map(|header| {
+                            let value = std::str::from_utf8(header.value?).ok()?;
+                            Some((header.key.to_string(), value.to_string()))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }))
+    }
+
+    async fn send(&self, topic: &str, payload: &[u8]) -> Result<()> {
+        self.send_with_headers(topic, payload, &HashMap::new()).await
+    }
+
+    async fn send_keyed(&self, topic: &str, key: &str, payload: &[u8]) -> Result<()> {
+        self.producer
+            .send(record(topic, Some(key), payload, &HashMap::new()), minutes_to_duration(KAFKA_SEND_TIMEOUT))
+            .await
+            .map(|_| ())
+            .map_err(|(e, _)| {
+                metrics::KAFKA_PRODUCER_ERRORS.inc();
+                anyhow!(e)
+            })
+    }
+
+    async fn send_with_headers(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        headers: &HashMap<String, String>,
+    ) -> Result<()> {
+        self.producer
+            .send(record(topic, None, payload, headers), minutes_to_duration(KAFKA_SEND_TIMEOUT))
+            .await
+            .map(|_| ())
+            .map_err(|(e, _)| {
+                metrics::KAFKA_PRODUCER_ERRORS.inc();
+                anyhow!(e)
+            })
+    }
+
+    /// Hands the message to the producer's queue and returns. Delivery
+    /// failures are counted and logged once the broker reports them.
+    async fn send_detached(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        headers: &HashMap<String, String>,
+    ) -> Result<()> {
+        let delivery = self
+            .producer
+            .send_result(record(topic, None, payload, headers))
+            .map_err(|(e, _)| {
+                metrics::KAFKA_PRODUCER_ERRORS.inc();
+                anyhow!(e)
+            })?;
+        let topic = topic.to_string();
+        tokio::spawn(async move {
+            match delivery.await {
+                Ok(Ok(_)) => {}
+                Ok(Err((e, _))) => {
+                    metrics::KAFKA_PRODUCER_ERRORS.inc();
+                    warn!("Failed to deliver message to {}: {}", topic, e);
+                }
+                // The producer went away before the broker answered.
+                Err(_) => {
+                    metrics::KAFKA_PRODUCER_ERRORS.inc();
+                    warn!("Lost the delivery report for a message to {}", topic);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Commits the partition up to the lowest offset whose job is unfinished.
+    async fn ack(&self, message: &InboundMessage) -> Result<()> {
+        let Some(next) = self
+            .offsets
+            .complete(&message.topic, message.partition, message.offset)
+        else {
+            return Ok(());
+        };
+
+        let mut partitions = TopicPartitionList::new();
+        partitions.add_partition_offset(&message.topic, message.partition, Offset::Offset(next))?;
+        self.consumer.commit(&partitions, CommitMode::Async)?;
+        debug!(
+            "Committed offset {} for {}[{}]",
+            next, message.topic, message.partition
+        );
+        Ok(())
+    }
+
+    /// Ready once the consumer has a subscription and the producer can
+    /// fetch that topic's metadata from the brokers.
+    async fn check_ready(&self) -> Result<()> {
+        let subscription = self.consumer.subscription()?;
+        let topic = subscription
+            .elements()
+            .first()
+            .map(|element| element.topic().to_string())
+            .context("Consumer is not subscribed to any topic")?;
+
+        let producer = self.producer.clone();
+        tokio::task::spawn_blocking(move || {
+            producer
+                .client()
+                .fetch_metadata(Some(&topic), Duration::from_secs(READINESS_TIMEOUT_SECONDS))
+        })
+        .await?
+        .context("Producer can't reach the brokers")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commits_up_to_the_lowest_unfinished_offset() {
+        let tracker = OffsetTracker::default();
+        for offset in 0..3 {
+            tracker.begin("requests", 0, offset);
+        }
+
+        // Offset 0 is still running, so the partition can't move past it.
+        assert_eq!(tracker.complete("requests", 0, 1), Some(0));
+        assert_eq!(tracker.complete("requests", 0, 0), Some(2));
+        assert_eq!(tracker.complete("requests", 0, 2), Some(3));
+    }
+
+    #[test]
+    fn does_not_commit_forgotten_partitions() {
+        let tracker = OffsetTracker::default();
+        tracker.begin("requests", 0, 7);
+        tracker.begin("requests", 1, 7);
+
+        let mut revoked = TopicPartitionList::new();
+        revoked.add_partition("requests", 0);
+        tracker.forget(&revoked);
+
+        assert_eq!(tracker.complete("requests", 0, 7), None);
+        assert_eq!(tracker.complete("requests", 1, 7), Some(8));
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::Result;
+use serenity::async_trait;
+use std::collections::HashMap;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Mutex;
+
+use ravalink_interconnect::protocol::Message;
+
+use crate::transport::{InboundMessage, Transport};
+
+/// What the worker did with a `MemoryTransport`, in the order it did it.
+pub enum Outbound {
+    Sent { topic: String, payload: Vec<u8> },
+    Acked { offset: i64 },
+}
+
+/// In-process transport backed by channels, used by the tests. Lets the
+/// worker run without a broker, with a `MemoryHandle` playing the part of
+/// the bot.
+pub struct MemoryTransport {
+    inbound: Mutex<UnboundedReceiver<InboundMessage>>,
+    outbound: UnboundedSender<Outbound>,
+}
+
+/// The bot side of a `MemoryTransport`.
+pub struct MemoryHandle {
+    topic: String,
+    inbound: UnboundedSender<InboundMessage>,
+    outbound: Mutex<UnboundedReceiver<Outbound>>,
+    offset: Mutex<i64>,
+}
+
+impl MemoryTransport {
+    pub fn new(topic: &str) -> (Self, MemoryHandle) {
+        let (inbound_tx, inbound_rx) = mpsc::unbounded_channel();
+        let (outbound_tx, outbound_rx) = mpsc::unbounded_channel();
+
+        let transport = MemoryTransport {
+            inbound: Mutex::new(inbound_rx),
+            outbound: outbound_tx,
+        };
+        let handle = MemoryHandle {
+            topic: topic.to_string(),
+            inbound: inbound_tx,
+            outbound: Mutex::new(outbound_rx),
+            offset: Mutex::new(0),
+        };
+
+        (transport, handle)
+    }
+}
+
+#[async_trait]
+impl Transport for MemoryTransport {
+    async fn recv(&self) -> Result<Option<InboundMessage>> {
+        Ok(self.inbound.lock().await.recv().await)
+    }
+
+    async fn send(&self, topic: &str, payload: &[u8]) -> Result<()> {
+        self.outbound.send(Outbound::Sent {
+            topic: topic.to_string(),
+            payload: payload.to_vec(),
+        })?;
+        Ok(())
+    }
+
+    async fn ack(&self, message: &InboundMessage) -> Result<()> {
+        self.outbound.send(Outbound::Acked { offset: message.offset })?;
+        Ok(())
+    }
+}
+
+impl MemoryHandle {
+    /// Delivers a raw payload to the worker as if it came off the topic.
+    /// Returns the offset it was given.
+    pub async fn publish_raw(&self, payload: Vec<u8>) -> Result<i64> {
+        let mut offset = self.offset.lock().await;
+        self.inbound.send(InboundMessage {
+            payload: Some(payload),
+            topic: self.topic.clone(),
+            partition: 0,
+            offset: *offset,
+            id: None,
+            headers: HashMap::new(),
+        })?;
+        *offset += 1;
+        Ok(*offset - 1)
+    }
+
+    pub async fn publish(&self, message: &Message) -> Result<i64> {
+        self.publish_raw(serde_json::to_vec(message)?).await
+    }
+
+    /// Waits for the next thing the worker did: a message it produced or a
+    /// delivery it acknowledged.
+    pub async fn next_outbound(&self) -> Option<Outbound> {
+        self.outbound.lock().await.recv().await
+    }
+
+    /// Waits for the next message the worker produced, with its topic,
+    /// skipping acknowledgements.
+    pub async fn next_sent(&self) -> Option<(String, Message)> {
+        loop {
+            if let Outbound::Sent { topic, payload } = self.next_outbound().await? {
+                let message = serde_json::from_slice(&payload).ok()?;
+                return Some((topic, message));
+            }
+        }
+    }
+}
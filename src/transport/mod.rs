@@ -0,0 +1,81 @@
+use anyhow::Result;
+use serenity::async_trait;
+use std::collections::HashMap;
+
+pub mod dead_letter;
+pub mod kafka;
+#[cfg(test)]
+pub mod memory;
+pub mod redis;
+pub mod reply;
+
+/// A message as read from the transport, before it is parsed.
+#[derive(Clone, Debug)]
+pub struct InboundMessage {
+    pub payload: Option<Vec<u8>>,
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    /// Transport-specific delivery id, such as a Redis stream entry id.
+    pub id: Option<String>,
+    /// Message headers, such as the producer's trace context.
+    pub headers: HashMap<String, String>,
+}
+
+/// Moves raw protocol payloads in and out of the worker, so the consume loop
+/// and `WorkerPool` do not depend on a particular broker.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Waits for the next inbound message. Returns `Ok(None)` once the
+    /// transport is closed and no more messages will arrive.
+    async fn recv(&self) -> Result<Option<InboundMessage>>;
+
+    async fn send(&self, topic: &str, payload: &[u8]) -> Result<()>;
+
+    /// Sends with a partitioning key. Requests are keyed by their guild id,
+    /// so each guild's requests stay on one partition and reach the one
+    /// worker holding its voice connection. Transports without keys ignore it.
+    async fn send_keyed(&self, topic: &str, _key: &str, payload: &[u8]) -> Result<()> {
+        self.send(topic, payload).await
+    }
+
+    /// Sends with message headers. Transports without headers drop them.
+    async fn send_with_headers(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        _headers: &HashMap<String, String>,
+    ) -> Result<()> {
+        self.send(topic, payload).await
+    }
+
+    /// Sends without waiting for the broker to confirm the message, for
+    /// frequent messages nobody is waiting on. Transports that can't tell
+    /// the two apart send as usual.
+    async fn send_detached(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        headers: &HashMap<String, String>,
+    ) -> Result<()> {
+        self.send_with_headers(topic, payload, headers).await
+    }
+
+    /// Acknowledges a message once its job has finished and any response has
+    /// been produced, so an unfinished job is redelivered after a crash.
+    async fn ack(&self, _message: &InboundMessage) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether events of jobs received through this transport are published
+    /// back on it.
+    fn publishes_events(&self) -> bool {
+        true
+    }
+
+    /// Checks that the transport can currently receive and send, for the
+    /// readiness probe.
+    async fn check_ready(&self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,334 @@
+use anyhow::{bail, Context, Result};
+use log::{debug, error, info, warn};
+use redis::aio::MultiplexedConnection;
+use redis::streams::{
+    StreamClaimReply, StreamId, StreamInfoConsumersReply, StreamMaxlen, StreamPendingCountReply,
+    StreamPendingReply, StreamReadOptions, StreamReadReply,
+};
+use redis::{AsyncCommands, Client};
+use serenity::async_trait;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::time::interval;
+
+use crate::transport::{InboundMessage, Transport};
+use crate::utils::config::{CONFIG, INSTANCE_ID};
+use crate::utils::constants::{REDIS_STREAM_BLOCK_MS, REDIS_STREAM_READ_COUNT};
+
+const PAYLOAD_FIELD: &str = "payload";
+
+/// Names of the request streams this worker reads. With a single shard the
+/// stream is the topic itself; otherwise bots add requests to
+/// `<topic>:<guild_id % shards>` and each worker reads the shards listed in
+/// `REDIS_STREAM_SHARD_IDS`. A worker refuses to start on a stream another
+/// live worker reads, so a guild is always served by the same worker.
+pub fn request_streams(topic: &str) -> Vec<String> {
+    let config = &CONFIG.transport;
+    if config.redis_stream_shards <= 1 {
+        return vec![topic.to_string()];
+    }
+
+    let shard_ids: Vec<u32> = match &config.redis_stream_shard_ids {
+        Some(ids) => ids.clone(),
+        None => (0..config.redis_stream_shards).collect(),
+    };
+
+    shard_ids
+        .into_iter()
+        .map(|shard| format!("{}:{}", topic, shard))
+        .collect()
+}
+
+/// The stream a bot adds `guild_id`'s requests to.
+pub fn request_stream(topic: &str, guild_id: u64) -> String {
+    let shards = CONFIG.transport.redis_stream_shards;
+    if shards <= 1 {
+        return topic.to_string();
+    }
+    format!("{}:{}", topic, guild_id % u64::from(shards))
+}
+
+fn inbound(stream: &str, entry: StreamId) -> InboundMessage {
+    InboundMessage {
+        payload: entry.get::<Vec<u8>>(PAYLOAD_FIELD),
+        topic: stream.to_string(),
+        partition: 0,
+        offset: -1,
+        id: Some(entry.id),
+        headers: HashMap::new(),
+    }
+}
+
+/// The key a worker keeps refreshing while it runs, so its peers can tell a
+/// consumer that is gone from one that is only busy.
+fn heartbeat_key(group: &str, consumer: &str) -> String {
+    format!("{}:heartbeat:{}", group, consumer)
+}
+
+/// Redis Streams transport. Requests are read through a consumer group and
+/// acknowledged with `XACK`; messages the worker produces are appended to the
+/// topic's stream, capped at `REDIS_STREAM_MAXLEN` entries. Workers refresh a
+/// heartbeat key, and the requests of a consumer whose heartbeat has lapsed
+/// for `REDIS_CLAIM_IDLE_MS` are claimed with `XCLAIM` and run again.
+pub struct RedisStreamTransport {
+    // Blocking reads get their own connection so they never stall sends and acks.
+    reader: Mutex<MultiplexedConnection>,
+    writer: MultiplexedConnection,
+    buffered: Mutex<VecDeque<InboundMessage>>,
+    /// Entries handed to the worker and not yet acknowledged.
+    in_flight: std::sync::Mutex<HashSet<String>>,
+    streams: Vec<String>,
+    group: String,
+    consumer: String,
+    claim_idle: Duration,
+    last_claim: Mutex<Option<Instant>>,
+}
+
+impl RedisStreamTransport {
+    /// Reads the request streams as `INSTANCE_ID`, so a restarted worker
+    /// keeps the consumer it had before and runs its pending entries again.
+    /// Fails if another live worker already reads one of the streams.
+    pub async fn new(redis_url: &str, topic: &str) -> Result<Self> {
+        let transport = Self::connect(
+            redis_url,
+            request_streams(topic),
+            &CONFIG.transport.redis_consumer_group,
+            &INSTANCE_ID,
+            "$",
+        )
+        .await?;
+
+        // Beating first means two workers starting together both fail.
+        transport.start_heartbeat();
+        transport.check_exclusive().await?;
+        transport.read_own_pending().await?;
+        Ok(transport)
+    }
+
+    /// Reads `streams` through `group`. A group created here starts at
+    /// `start_id`: `$` for new entries only, `0` for the whole stream.
+    pub async fn connect(
+        redis_url: &str,
+        streams: Vec<String>,
+        group: &str,
+        consumer: &str,
+        start_id: &str,
+    ) -> Result<Self> {
+        let client = Client::open(redis_url).context("Invalid Redis URL")?;
+        let reader = client.get_multiplexed_async_connection().await?;
+        let mut writer = client.get_multiplexed_async_connection().await?;
+        let group = group.to_string();
+
+        for stream in &streams {
+            let created: redis::RedisResult<()> =
+                writer.xgroup_create_mkstream(stream, &group, start_id).await;
+            match created {
+                Ok(()) => info!("Created consumer group {} on stream {}", group, stream),
+                Err(e) if e.code() == Some("BUSYGROUP") => {
+                    debug!("Consumer group {} already exists on stream {}", group, stream)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(RedisStreamTransport {
+            reader: Mutex::new(reader),
+            writer,
+            buffered: Mutex::new(VecDeque::new()),
+            in_flight: std::sync::Mutex::new(HashSet::new()),
+            streams,
+            group,
+            consumer: consumer.to_string(),
+            claim_idle: Duration::from_millis(CONFIG.transport.redis_claim_idle_ms),
+            last_claim: Mutex::new(None),
+        })
+    }
+
+    async fn is_alive(&self, consumer: &str) -> Result<bool> {
+        let mut writer = self.writer.clone();
+        let alive: bool = writer.exists(heartbeat_key(&self.group, consumer)).await?;
+        Ok(alive)
+    }
+
+    /// Two workers reading one stream through the group would split a
+    /// guild's requests between them, and only one holds its voice
+    /// connection.
+    async fn check_exclusive(&self) -> Result<()> {
+        let mut writer = self.writer.clone();
+        for stream in &self.streams {
+            let reply: StreamInfoConsumersReply = writer.xinfo_consumers(stream, &self.group).await?;
+            for other in reply.consumers.iter().filter(|other| other.name != self.consumer) {
+                if self.is_alive(&other.name).await? {
+                    bail!(
+                        "Worker {} already reads stream {} in group {}; set REDIS_STREAM_SHARDS above 1 and give each worker its own REDIS_STREAM_SHARD_IDS",
+                        other.name,
+                        stream,
+                        self.group
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Refreshes this worker's heartbeat three times per `claim_idle`, and
+    /// lets it expire after `claim_idle` once the worker is gone.
+    fn start_heartbeat(&self) {
+        let mut writer = self.writer.clone();
+        let key = heartbeat_key(&self.group, &self.consumer);
+        let ttl = self.claim_idle.max(Duration::from_millis(1));
+        tokio::spawn(async move {
+            let mut beats = interval(ttl / 3);
+            loop {
+                beats.tick().await;
+                let beat: redis::RedisResult<()> = writer.pset_ex(&key, 1, ttl.as_millis() as u64).await;
+                if let Err(e) = beat {
+                    error!("Failed to refresh Redis heartbeat {}: {}", key, e);
+                }
+            }
+        });
+    }
+
+    /// Queues the entries this consumer read before a restart and never
+    /// acknowledged.
+    async fn read_own_pending(&self) -> Result<()> {
+        let ids = vec!["0"; self.streams.len()];
+        let options = StreamReadOptions::default().group(&self.group, &self.consumer);
+        let reply: Option<StreamReadReply> = self
+            .reader
+            .lock()
+            .await
+            .xread_options(&self.streams, &ids, &options)
+            .await?;
+
+        let mut buffered = self.buffered.lock().await;
+        for key in reply.map(|r| r.keys).unwrap_or_default() {
+            if !key.ids.is_empty() {
+                warn!("Running {} requests left unacknowledged on stream {} by the previous run", key.ids.len(), key.key);
+            }
+            buffered.extend(key.ids.into_iter().map(|entry| inbound(&key.key, entry)));
+        }
+        Ok(())
+    }
+
+    /// Takes over the pending entries of consumers whose heartbeat has
+    /// lapsed, at most once per `claim_idle`. Entries of live workers, this
+    /// one included, are left alone however long they have been pending.
+    async fn claim_idle_entries(&self, buffered: &mut VecDeque<InboundMessage>) -> Result<()> {
+        let mut last_claim = self.last_claim.lock().await;
+        if last_claim.is_some_and(|at| at.elapsed() < self.claim_idle) {
+            return Ok(());
+        }
+        *last_claim = Some(Instant::now());
+
+        let mut writer = self.writer.clone();
+        let min_idle = self.claim_idle.as_millis() as u64;
+        for stream in &self.streams {
+            let pending: StreamPendingReply = writer.xpending(stream, &self.group).await?;
+            let StreamPendingReply::Data(pending) = pending else {
+                continue;
+            };
+            for owner in pending.consumers {
+                if owner.name == self.consumer || self.is_alive(&owner.name).await? {
+                    continue;
+                }
+
+                let entries: StreamPendingCountReply = writer
+                    .xpending_consumer_count(stream, &self.group, "-", "+", owner.pending, &owner.name)
+                    .await?;
+                let ids: Vec<String> = {
+                    let in_flight = self.in_flight.lock().unwrap();
+                    entries
+                        .ids
+                        .into_iter()
+                        .map(|entry| entry.id)
+                        .filter(|id| !in_flight.contains(id))
+                        .collect()
+                };
+                if ids.is_empty() {
+                    continue;
+                }
+
+                let claimed: StreamClaimReply = writer
+                    .xclaim(stream, &self.group, &self.consumer, min_idle, &ids)
+                    .await?;
+                if !claimed.ids.is_empty() {
+                    warn!(
+                        "Claimed {} requests left unacknowledged by worker {} on stream {}",
+                        claimed.ids.len(),
+                        owner.name,
+                        stream
+                    );
+                }
+                buffered.extend(claimed.ids.into_iter().map(|entry| inbound(stream, entry)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Transport for RedisStreamTransport {
+    async fn recv(&self) -> Result<Option<InboundMessage>> {
+        let mut buffered = self.buffered.lock().await;
+        let ids = vec![">"; self.streams.len()];
+        let options = StreamReadOptions::default()
+            .group(&self.group, &self.consumer)
+            .count(REDIS_STREAM_READ_COUNT)
+            .block(REDIS_STREAM_BLOCK_MS);
+
+        while buffered.is_empty() {
+            self.claim_idle_entries(&mut buffered).await?;
+            if !buffered.is_empty() {
+                break;
+            }
+
+            let reply: Option<StreamReadReply> = self
+                .reader
+                .lock()
+                .await
+                .xread_options(&self.streams, &ids, &options)
+                .await?;
+
+            for key in reply.map(|r| r.keys).unwrap_or_default() {
+                buffered.extend(key.ids.into_iter().map(|entry| inbound(&key.key, entry)));
+            }
+        }
+
+        let message = buffered.pop_front();
+        if let Some(id) = message.as_ref().and_then(|message| message.id.clone()) {
+            self.in_flight.lock().unwrap().insert(id);
+        }
+        Ok(message)
+    }
+
+    async fn send(&self, topic: &str, payload: &[u8]) -> Result<()> {
+        let mut writer = self.writer.clone();
+        let _: String = writer
+            .xadd_maxlen(
+                topic,
+                StreamMaxlen::Approx(CONFIG.transport.redis_stream_maxlen),
+                "*",
+                &[(PAYLOAD_FIELD, payload)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn ack(&self, message: &InboundMessage) -> Result<()> {
+        let Some(id) = message.id.as_deref() else {
+            return Ok(());
+        };
+        let mut writer = self.writer.clone();
+        let _: u64 = writer.xack(&message.topic, &self.group, &[id]).await?;
+        self.in_flight.lock().unwrap().remove(id);
+        Ok(())
+    }
+
+    async fn check_ready(&self) -> Result<()> {
+        let mut writer = self.writer.clone();
+        let _: String = redis::cmd("PING").query_async(&mut writer).await?;
+        Ok(())
+    }
+}
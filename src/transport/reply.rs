@@ -0,0 +1,54 @@
+use anyhow::Result;
+use ravalink_interconnect::protocol::{Message, Response};
+use serenity::async_trait;
+use std::collections::HashMap;
+use tokio::sync::{oneshot, Mutex};
+
+use crate::transport::{InboundMessage, Transport};
+
+/// Transport handed to the `WorkerPool` for jobs submitted in-process by the
+/// request/response front ends. Responses are routed back to the caller
+/// waiting on the job; events are left to the event bus.
+pub struct ReplyTransport {
+    pending: Mutex<HashMap<String, oneshot::Sender<Response>>>,
+}
+
+impl ReplyTransport {
+    pub fn new() -> Self {
+        ReplyTransport {
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn register(&self, job_id: &str) -> oneshot::Receiver<Response> {
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().await.insert(job_id.to_string(), tx);
+        rx
+    }
+
+    pub async fn forget(&self, job_id: &str) {
+        self.pending.lock().await.remove(job_id);
+    }
+}
+
+#[async_trait]
+impl Transport for ReplyTransport {
+    /// Requests are submitted directly to the pool, never through `recv`.
+    async fn recv(&self) -> Result<Option<InboundMessage>> {
+        Ok(None)
+    }
+
+    async fn send(&self, _topic: &str, payload: &[u8]) -> Result<()> {
+        if let Message::Response(response) = serde_json::from_slice::<Message>(payload)? {
+            if let Some(waiter) = self.pending.lock().await.remove(&response.job_id) {
+                let _ = waiter.send(response);
+            }
+        }
+        Ok(())
+    }
+
+    /// The front ends read events from the event bus themselves.
+    fn publishes_events(&self) -> bool {
+        false
+    }
+}
@@ -1,61 +1,886 @@
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use serde_derive::{Deserialize, Serialize};
 use dotenvy::dotenv;
+use log::LevelFilter;
+use std::collections::HashMap;
 use std::env;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::RwLock;
+use crate::transport::kafka::security_properties;
+use crate::utils::constants::{
+    CONFIG_FILE_ENV, DEFAULT_ADMIN_BIND_ADDRESS, DEFAULT_APPLE_MUSIC_STOREFRONT,
+    DEFAULT_BOT_IDLE_TIME_SECONDS, DEFAULT_DISK_CACHE_MAX_BYTES, DEFAULT_DISK_CACHE_MIN_PLAYS,
+    DEFAULT_EVENT_BUS_CAPACITY, DEFAULT_GRPC_BIND_ADDRESS, DEFAULT_GUILD_JOB_BACKLOG,
+    DEFAULT_JOB_EXPIRATION_TIME_SECONDS, DEFAULT_KAFKA_BATCH_SIZE, DEFAULT_KAFKA_GROUP_ID,
+    DEFAULT_KAFKA_LINGER_MS, DEFAULT_LAVALINK_BIND_ADDRESS, DEFAULT_LOG_LEVEL, DEFAULT_LOG_MODULES,
+    DEFAULT_LOUDNESS_ANALYSIS_SECONDS, DEFAULT_LOUDNESS_ANALYSIS_TIMEOUT_MS,
+    DEFAULT_LOUDNESS_TARGET_LUFS, DEFAULT_MAX_QUEUE_LENGTH, DEFAULT_MONITORING_BIND_ADDRESS,
+    DEFAULT_OTEL_SERVICE_NAME, DEFAULT_PLAYER_UPDATE_INTERVAL_SECONDS, DEFAULT_PLAYLIST_MAX_ENTRIES,
+    DEFAULT_PRERESOLVE_AHEAD, DEFAULT_REDIS_CLAIM_IDLE_MS, DEFAULT_REDIS_CONSUMER_GROUP,
+    DEFAULT_REDIS_STREAM_MAXLEN, DEFAULT_TRACK_CACHE_MAX_ENTRIES, DEFAULT_TRACK_CACHE_TTL_SECONDS,
+    DEFAULT_VOLUME, KAFKA_PROPERTY_PREFIX,
+};
+
+#[derive(Deserialize, Clone, Copy, Serialize, PartialEq, Eq, Debug)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
 
 #[derive(Deserialize, Clone, Serialize)]
 pub struct ServerConfig {
     pub discord_bot_id: u64,
+    #[serde(skip_serializing)]
     pub discord_bot_token: String,
     pub job_expiration_time_seconds: u64,
-    pub bot_idle_time_seconds: u64,
+    pub playlist_max_entries: usize,
+    /// Tracks a guild may have waiting in its queue.
+    pub max_queue_length: usize,
+    /// Jobs a guild may have queued or running before new ones are rejected
+    /// as busy.
+    pub guild_job_backlog: usize,
+    pub log_format: LogFormat,
 }
 
 #[derive(Deserialize, Clone, Serialize)]
 pub struct KafkaConfig {
     pub kafka_uri: String,
     pub kafka_topic: String,
+    /// Consumer group shared by every worker, so each request is handled
+    /// once and committed offsets survive restarts. With several workers,
+    /// requests must be keyed by guild id so a guild's requests reach the
+    /// worker holding its voice connection.
+    pub kafka_group_id: String,
     pub kafka_use_ssl: Option<bool>,
     pub kafka_use_sasl: Option<bool>,
     pub kafka_username: Option<String>,
+    #[serde(skip_serializing)]
     pub kafka_password: Option<String>,
     pub kafka_ssl_cert: Option<String>,
     pub kafka_ssl_key: Option<String>,
     pub kafka_ssl_ca: Option<String>,
+    #[serde(skip_serializing)]
+    pub kafka_ssl_key_password: Option<String>,
+    /// PLAINTEXT, SSL, SASL_PLAINTEXT or SASL_SSL. Derived from
+    /// `kafka_use_ssl`/`kafka_use_sasl` when unset.
+    pub kafka_security_protocol: Option<String>,
+    /// PLAIN, SCRAM-SHA-256, SCRAM-SHA-512 or OAUTHBEARER. Defaults to PLAIN.
+    pub kafka_sasl_mechanism: Option<String>,
+    pub kafka_oauth_token_endpoint: Option<String>,
+    pub kafka_oauth_client_id: Option<String>,
+    #[serde(skip_serializing)]
+    pub kafka_oauth_client_secret: Option<String>,
+    pub kafka_oauth_scope: Option<String>,
+    /// How long the producer waits to fill a batch before sending it.
+    pub kafka_linger_ms: u64,
+    /// Most messages the producer sends in one batch.
+    pub kafka_batch_size: usize,
+    /// Extra librdkafka properties from `KAFKA_PROPERTY_*` variables, applied
+    /// after everything else. Skipped like the secrets, since they can carry
+    /// passwords such as `KAFKA_PROPERTY_SASL_PASSWORD`.
+    #[serde(skip_serializing)]
+    pub kafka_properties: Vec<(String, String)>,
+}
+
+#[derive(Deserialize, Clone, Serialize)]
+pub struct AudioConfig {
+    pub loudness_normalization: bool,
+    pub loudness_target_lufs: f32,
+    pub loudness_analysis_seconds: u64,
+    /// How long a track's start waits for its loudness analysis before it
+    /// plays without normalization.
+    pub loudness_analysis_timeout_ms: u64,
+}
+
+#[derive(Deserialize, Clone, Serialize)]
+pub struct ResolverConfig {
+    pub spotify_client_id: Option<String>,
+    #[serde(skip_serializing)]
+    pub spotify_client_secret: Option<String>,
+    #[serde(skip_serializing)]
+    pub apple_music_token: Option<String>,
+    pub apple_music_storefront: String,
+}
+
+#[derive(Deserialize, Clone, Serialize)]
+pub struct CacheConfig {
+    pub track_cache_ttl_seconds: u64,
+    /// Resolved tracks kept in memory; the soonest to expire are dropped first.
+    pub track_cache_max_entries: usize,
+    pub preresolve_ahead: usize,
+    pub disk_cache_dir: Option<String>,
+    pub disk_cache_max_bytes: u64,
+    pub disk_cache_min_plays: u32,
+}
+
+#[derive(Deserialize, Clone, Copy, Serialize, PartialEq, Eq, Debug)]
+pub enum TransportKind {
+    Kafka,
+    Redis,
+}
+
+#[derive(Deserialize, Clone, Serialize)]
+pub struct TransportConfig {
+    pub kind: TransportKind,
+    pub redis_consumer_group: String,
+    pub redis_stream_shards: u32,
+    pub redis_stream_shard_ids: Option<Vec<u32>>,
+    pub redis_stream_maxlen: usize,
+    /// How long a worker's heartbeat outlives it, in milliseconds. Once it
+    /// has lapsed, other workers claim the requests it left unacknowledged.
+    pub redis_claim_idle_ms: u64,
+    pub dead_letter_topic: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Serialize)]
+pub struct LavalinkConfig {
+    pub enabled: bool,
+    pub bind_address: String,
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    pub player_update_interval_seconds: u64,
+}
+
+#[derive(Deserialize, Clone, Serialize)]
+pub struct GrpcConfig {
+    pub enabled: bool,
+    pub bind_address: String,
+    /// Bearer token every gRPC call must carry.
+    #[serde(skip_serializing)]
+    pub token: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Serialize)]
+pub struct MonitoringConfig {
+    pub enabled: bool,
+    pub bind_address: String,
+}
+
+#[derive(Deserialize, Clone, Serialize)]
+pub struct AdminConfig {
+    pub enabled: bool,
+    pub bind_address: String,
+    /// Bearer token operators must send. Required when the interface is on.
+    #[serde(skip_serializing)]
+    pub token: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Serialize)]
+pub struct TracingConfig {
+    /// OTLP gRPC collector spans are exported to. Tracing is off without it.
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+#[derive(Deserialize, Clone, Serialize)]
+pub struct EventsConfig {
+    /// Events a subscriber can have waiting before publishing waits for it.
+    pub bus_capacity: usize,
+    /// Keeps each guild's playback state in Redis, updated on every event.
+    pub persist_sessions: bool,
+    /// URLs every worker event is posted to as JSON.
+    pub webhook_urls: Vec<String>,
+}
+
+/// Settings that are re-read on SIGHUP. Read them through `runtime()` rather
+/// than `CONFIG.runtime`, which only holds the values from startup.
+#[derive(Deserialize, Clone, Serialize, PartialEq, Debug)]
+pub struct RuntimeConfig {
+    pub log_level: LevelFilter,
+    /// Per-module overrides of `log_level`, from `LOG_MODULES`.
+    pub log_modules: Vec<(String, LevelFilter)>,
+    pub bot_idle_time_seconds: u64,
+    pub default_volume: f32,
+}
+
+impl RuntimeConfig {
+    /// The level for a log target: the most specific `log_modules` entry
+    /// covering it, otherwise `log_level`.
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.log_modules
+            .iter()
+            .filter(|(module, _)| {
+                target == module
+                    || target
+                        .strip_prefix(module.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.log_level)
+    }
+
+    /// The most verbose level any target is logged at.
+    pub fn max_level(&self) -> LevelFilter {
+        self.log_modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.log_level, LevelFilter::max)
+    }
 }
 
 #[derive(Deserialize, Clone, Serialize)]
 pub struct Config {
+    pub runtime: RuntimeConfig,
     pub config: ServerConfig,
     pub kafka: KafkaConfig,
+    pub transport: TransportConfig,
+    pub audio: AudioConfig,
+    pub resolver: ResolverConfig,
+    pub cache: CacheConfig,
+    pub lavalink: LavalinkConfig,
+    pub grpc: GrpcConfig,
+    pub monitoring: MonitoringConfig,
+    pub tracing: TracingConfig,
+    pub admin: AdminConfig,
+    pub events: EventsConfig,
     pub redis_url: Option<String>,
 }
 
-pub static CONFIG: Lazy<Config> = Lazy::new(|| {
+/// Configuration given on the command line: `--config <path>` picks the
+/// config file and `--set KEY=VALUE` overrides a single setting.
+#[derive(Default)]
+pub struct ConfigArgs {
+    pub file: Option<String>,
+    pub overrides: HashMap<String, String>,
+    errors: Vec<String>,
+}
+
+impl ConfigArgs {
+    /// Takes the configuration flags out of `args`, returning the remaining
+    /// arguments. Malformed flags are reported along with the rest of the
+    /// validation errors.
+    pub fn extract(args: impl IntoIterator<Item = String>) -> (Self, Vec<String>) {
+        let mut config_args = ConfigArgs::default();
+        let mut remaining = Vec::new();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => match args.next() {
+                    Some(path) => config_args.file = Some(path),
+                    None => config_args.errors.push("--config needs a path".to_string()),
+                },
+                "--set" => match args.next().as_deref().and_then(|v| v.split_once('=')) {
+                    Some((key, value)) => {
+                        config_args
+                            .overrides
+                            .insert(normalize_key(key), value.to_string());
+                    }
+                    None => config_args.errors.push("--set needs KEY=VALUE".to_string()),
+                },
+                _ => remaining.push(arg),
+            }
+        }
+
+        (config_args, remaining)
+    }
+}
+
+static CONFIG_ARGS: OnceCell<ConfigArgs> = OnceCell::new();
+
+/// Registers the command-line layer. Must be called before `CONFIG` is first
+/// used for the flags to take effect.
+pub fn set_args(args: ConfigArgs) {
+    let _ = CONFIG_ARGS.set(args);
+}
+
+/// File keys use the environment variable names, optionally split into
+/// tables: `[kafka] uri = ...` is the same setting as `KAFKA_URI`.
+fn normalize_key(key: &str) -> String {
+    key.trim().replace(['-', '.'], "_").to_uppercase()
+}
+
+fn flatten(prefix: &str, value: serde_json::Value, settings: &mut HashMap<String, String>) {
+    let text = match value {
+        serde_json::Value::Null => return,
+        serde_json::Value::Object(table) => {
+            for (key, value) in table {
+                let key = match prefix {
+                    "" => normalize_key(&key),
+                    _ => format!("{}_{}", prefix, normalize_key(&key)),
+                };
+                flatten(&key, value, settings);
+            }
+            return;
+        }
+        serde_json::Value::String(text) => text,
+        serde_json::Value::Array(items) => items
+            .into_iter()
+            .map(|item| match item {
+                serde_json::Value::String(text) => text,
+                other => other.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(","),
+        other => other.to_string(),
+    };
+    settings.insert(prefix.to_string(), text);
+}
+
+fn read_config_file(path: &str) -> Result<HashMap<String, String>, String> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+
+    let value: serde_json::Value = match extension {
+        "toml" => {
+            toml::from_str(&contents).map_err(|e| format!("{} is not valid TOML: {}", path, e))?
+        }
+        "yaml" | "yml" => serde_yaml::from_str(&contents)
+            .map_err(|e| format!("{} is not valid YAML: {}", path, e))?,
+        _ => return Err(format!("{} must be a .toml, .yaml or .yml file", path)),
+    };
+
+    let mut settings = HashMap::new();
+    flatten("", value, &mut settings);
+    Ok(settings)
+}
+
+/// Looks settings up across the layers, highest precedence first: CLI flags,
+/// environment variables, then the config file. Every problem is collected
+/// so they can be reported together.
+struct Layers<'a> {
+    args: Option<&'a ConfigArgs>,
+    file: HashMap<String, String>,
+    errors: Vec<String>,
+}
+
+impl<'a> Layers<'a> {
+    fn new(args: Option<&'a ConfigArgs>) -> Self {
+        let mut layers = Layers {
+            args,
+            file: HashMap::new(),
+            errors: args.map(|args| args.errors.clone()).unwrap_or_default(),
+        };
+
+        let path = args
+            .and_then(|args| args.file.clone())
+            .or_else(|| env::var(CONFIG_FILE_ENV).ok());
+        if let Some(path) = path {
+            match read_config_file(&path) {
+                Ok(file) => layers.file = file,
+                Err(e) => layers.errors.push(e),
+            }
+        }
+
+        layers
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.args
+            .and_then(|args| args.overrides.get(key).cloned())
+            .or_else(|| env::var(key).ok())
+            .or_else(|| self.file.get(key).cloned())
+    }
+
+    fn string_or(&self, key: &str, default: &str) -> String {
+        self.get(key).unwrap_or_else(|| default.to_string())
+    }
+
+    fn required(&mut self, key: &str) -> String {
+        self.get(key).unwrap_or_else(|| {
+            self.errors.push(format!("{} must be set", key));
+            String::new()
+        })
+    }
+
+    fn parse<T: FromStr>(&mut self, key: &str) -> Option<T>
+    where
+        T::Err: Display,
+    {
+        let value = self.get(key)?;
+        match value.trim().parse() {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                self.errors
+                    .push(format!("{} has invalid value {:?}: {}", key, value, e));
+                None
+            }
+        }
+    }
+
+    fn parse_or<T: FromStr>(&mut self, key: &str, default: T) -> T
+    where
+        T::Err: Display,
+    {
+        self.parse(key).unwrap_or(default)
+    }
+
+    /// A comma-separated list, reporting every entry that does not parse.
+    fn parse_list<T: FromStr>(&mut self, key: &str) -> Option<Vec<T>>
+    where
+        T::Err: Display,
+    {
+        let value = self.get(key)?;
+        let mut parsed = Vec::new();
+        for item in value.split(',').map(str::trim) {
+            match item.parse() {
+                Ok(item) => parsed.push(item),
+                Err(e) => self
+                    .errors
+                    .push(format!("{} has invalid entry {:?}: {}", key, item, e)),
+            }
+        }
+        Some(parsed)
+    }
+
+    fn parse_required<T: FromStr + Default>(&mut self, key: &str) -> T
+    where
+        T::Err: Display,
+    {
+        if self.get(key).is_none() {
+            self.errors.push(format!("{} must be set", key));
+            return T::default();
+        }
+        self.parse(key).unwrap_or_default()
+    }
+
+    fn check_address(&mut self, key: &str, address: &str) {
+        if let Err(e) = address.parse::<SocketAddr>() {
+            self.errors
+                .push(format!("{} has invalid address {:?}: {}", key, address, e));
+        }
+    }
+
+    /// Settings starting with `prefix`, from every layer.
+    fn prefixed(&self, prefix: &str) -> Vec<(String, String)> {
+        let mut keys: Vec<String> = self
+            .file
+            .keys()
+            .cloned()
+            .chain(env::vars().map(|(key, _)| key))
+            .chain(
+                self.args
+                    .into_iter()
+                    .flat_map(|args| args.overrides.keys().cloned()),
+            )
+            .filter(|key| key.starts_with(prefix))
+            .collect();
+        keys.sort();
+        keys.dedup();
+
+        keys.into_iter()
+            .filter_map(|key| Some((key.clone(), self.get(&key)?)))
+            .collect()
+    }
+}
+
+/// Parses `LOG_MODULES`, a comma-separated list of `module=level` entries,
+/// on top of the built-in defaults for noisy dependencies.
+fn log_modules(layers: &mut Layers) -> Vec<(String, LevelFilter)> {
+    let configured = layers.get("LOG_MODULES").unwrap_or_default();
+    let mut modules: Vec<(String, LevelFilter)> = Vec::new();
+
+    for entry in DEFAULT_LOG_MODULES.split(',').chain(configured.split(',')) {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+        let parsed = entry
+            .split_once('=')
+            .map(|(module, level)| (module.trim(), level.trim().parse()));
+        match parsed {
+            Some((module, Ok(level))) => {
+                modules.retain(|(existing, _)| existing != module);
+                modules.push((module.to_string(), level));
+            }
+            _ => layers.errors.push(format!(
+                "LOG_MODULES entry {:?} must look like module=level",
+                entry
+            )),
+        }
+    }
+
+    modules
+}
+
+/// Turns `KAFKA_PROPERTY_*` settings into librdkafka properties:
+/// `KAFKA_PROPERTY_SOCKET_KEEPALIVE_ENABLE` becomes `socket.keepalive.enable`,
+/// and a double underscore stands for a literal underscore.
+fn kafka_properties(layers: &Layers) -> Vec<(String, String)> {
+    layers
+        .prefixed(KAFKA_PROPERTY_PREFIX)
+        .into_iter()
+        .map(|(key, value)| {
+            let name = key[KAFKA_PROPERTY_PREFIX.len()..]
+                .to_lowercase()
+                .split("__")
+                .map(|part| part.replace('_', "."))
+                .collect::<Vec<_>>()
+                .join("_");
+            (name, value)
+        })
+        .collect()
+}
+
+/// Builds the configuration from the config file, the environment and the
+/// command line, returning every validation error at once.
+pub fn load() -> Result<Config, Vec<String>> {
     dotenv().ok();
+    let mut layers = Layers::new(CONFIG_ARGS.get());
+
+    let transport_kind = match layers.get("TRANSPORT").as_deref() {
+        None | Some("kafka") => TransportKind::Kafka,
+        Some("redis") => TransportKind::Redis,
+        Some(other) => {
+            layers
+                .errors
+                .push(format!("TRANSPORT must be kafka or redis, got {:?}", other));
+            TransportKind::Kafka
+        }
+    };
+
+    let log_format = match layers.get("LOG_FORMAT").as_deref() {
+        None | Some("text") => LogFormat::Text,
+        Some("json") => LogFormat::Json,
+        Some(other) => {
+            layers
+                .errors
+                .push(format!("LOG_FORMAT must be text or json, got {:?}", other));
+            LogFormat::Text
+        }
+    };
 
-    Config {
+    let config = Config {
+        runtime: RuntimeConfig {
+            log_level: layers.parse_or("LOG_LEVEL", DEFAULT_LOG_LEVEL),
+            log_modules: log_modules(&mut layers),
+            bot_idle_time_seconds: layers
+                .parse_or("BOT_IDLE_TIME_SECONDS", DEFAULT_BOT_IDLE_TIME_SECONDS),
+            default_volume: layers.parse_or("DEFAULT_VOLUME", DEFAULT_VOLUME),
+        },
         config: ServerConfig {
-            discord_bot_id: env::var("DISCORD_BOT_ID")
-                .expect("DISCORD_BOT_ID must be set")
-                .parse()
-                .expect("DISCORD_BOT_ID must be a valid u64"),
-            discord_bot_token: env::var("DISCORD_BOT_TOKEN")
-                .expect("DISCORD_BOT_TOKEN must be set"),
-            job_expiration_time_seconds: 3600,
-            bot_idle_time_seconds: 600,
+            discord_bot_id: layers.parse_required("DISCORD_BOT_ID"),
+            discord_bot_token: layers.required("DISCORD_BOT_TOKEN"),
+            job_expiration_time_seconds: layers.parse_or(
+                "JOB_EXPIRATION_TIME_SECONDS",
+                DEFAULT_JOB_EXPIRATION_TIME_SECONDS,
+            ),
+            playlist_max_entries: layers
+                .parse_or("PLAYLIST_MAX_ENTRIES", DEFAULT_PLAYLIST_MAX_ENTRIES),
+            max_queue_length: layers.parse_or("MAX_QUEUE_LENGTH", DEFAULT_MAX_QUEUE_LENGTH),
+            guild_job_backlog: layers
+                .parse_or("GUILD_JOB_BACKLOG", DEFAULT_GUILD_JOB_BACKLOG),
+            log_format,
         },
         kafka: KafkaConfig {
-            kafka_uri: env::var("KAFKA_URI").expect("KAFKA_URI must be set"),
-            kafka_topic: env::var("KAFKA_TOPIC").expect("KAFKA_TOPIC must be set"),
-            kafka_use_ssl: env::var("KAFKA_USE_SSL").ok().map(|v| v == "true"),
-            kafka_use_sasl: env::var("KAFKA_USE_SASL").ok().map(|v| v == "true"),
-            kafka_username: env::var("KAFKA_USERNAME").ok(),
-            kafka_password: env::var("KAFKA_PASSWORD").ok(),
-            kafka_ssl_cert: env::var("KAFKA_SSL_CERT").ok(),
-            kafka_ssl_key: env::var("KAFKA_SSL_KEY").ok(),
-            kafka_ssl_ca: env::var("KAFKA_SSL_CA").ok(),
-        },
-        redis_url: env::var("REDIS_URL").ok(),
+            kafka_uri: match transport_kind {
+                TransportKind::Kafka => layers.required("KAFKA_URI"),
+                TransportKind::Redis => layers.get("KAFKA_URI").unwrap_or_default(),
+            },
+            kafka_topic: layers.required("KAFKA_TOPIC"),
+            kafka_group_id: layers.string_or("KAFKA_GROUP_ID", DEFAULT_KAFKA_GROUP_ID),
+            kafka_use_ssl: layers.parse("KAFKA_USE_SSL"),
+            kafka_use_sasl: layers.parse("KAFKA_USE_SASL"),
+            kafka_username: layers.get("KAFKA_USERNAME"),
+            kafka_password: layers.get("KAFKA_PASSWORD"),
+            kafka_ssl_cert: layers.get("KAFKA_SSL_CERT"),
+            kafka_ssl_key: layers.get("KAFKA_SSL_KEY"),
+            kafka_ssl_ca: layers.get("KAFKA_SSL_CA"),
+            kafka_ssl_key_password: layers.get("KAFKA_SSL_KEY_PASSWORD"),
+            kafka_security_protocol: layers.get("KAFKA_SECURITY_PROTOCOL"),
+            kafka_sasl_mechanism: layers.get("KAFKA_SASL_MECHANISM"),
+            kafka_oauth_token_endpoint: layers.get("KAFKA_OAUTH_TOKEN_ENDPOINT"),
+            kafka_oauth_client_id: layers.get("KAFKA_OAUTH_CLIENT_ID"),
+            kafka_oauth_client_secret: layers.get("KAFKA_OAUTH_CLIENT_SECRET"),
+            kafka_oauth_scope: layers.get("KAFKA_OAUTH_SCOPE"),
+            kafka_linger_ms: layers.parse_or("KAFKA_LINGER_MS", DEFAULT_KAFKA_LINGER_MS),
+            kafka_batch_size: layers.parse_or("KAFKA_BATCH_SIZE", DEFAULT_KAFKA_BATCH_SIZE),
+            kafka_properties: kafka_properties(&layers),
+        },
+        transport: TransportConfig {
+            kind: transport_kind,
+            redis_consumer_group: layers
+                .string_or("REDIS_CONSUMER_GROUP", DEFAULT_REDIS_CONSUMER_GROUP),
+            redis_stream_shards: layers.parse_or("REDIS_STREAM_SHARDS", 1),
+            redis_stream_shard_ids: layers.parse_list("REDIS_STREAM_SHARD_IDS"),
+            redis_stream_maxlen: layers
+                .parse_or("REDIS_STREAM_MAXLEN", DEFAULT_REDIS_STREAM_MAXLEN),
+            redis_claim_idle_ms: layers.parse_or("REDIS_CLAIM_IDLE_MS", DEFAULT_REDIS_CLAIM_IDLE_MS),
+            dead_letter_topic: layers.get("DEAD_LETTER_TOPIC"),
+        },
+        audio: AudioConfig {
+            loudness_normalization: layers.parse_or("LOUDNESS_NORMALIZATION", false),
+            loudness_target_lufs: layers
+                .parse_or("LOUDNESS_TARGET_LUFS", DEFAULT_LOUDNESS_TARGET_LUFS),
+            loudness_analysis_seconds: layers.parse_or(
+                "LOUDNESS_ANALYSIS_SECONDS",
+                DEFAULT_LOUDNESS_ANALYSIS_SECONDS,
+            ),
+            loudness_analysis_timeout_ms: layers.parse_or(
+                "LOUDNESS_ANALYSIS_TIMEOUT_MS",
+                DEFAULT_LOUDNESS_ANALYSIS_TIMEOUT_MS,
+            ),
+        },
+        resolver: ResolverConfig {
+            spotify_client_id: layers.get("SPOTIFY_CLIENT_ID"),
+            spotify_client_secret: layers.get("SPOTIFY_CLIENT_SECRET"),
+            apple_music_token: layers.get("APPLE_MUSIC_TOKEN"),
+            apple_music_storefront: layers
+                .string_or("APPLE_MUSIC_STOREFRONT", DEFAULT_APPLE_MUSIC_STOREFRONT),
+        },
+        cache: CacheConfig {
+            track_cache_ttl_seconds: layers
+                .parse_or("TRACK_CACHE_TTL_SECONDS", DEFAULT_TRACK_CACHE_TTL_SECONDS),
+            track_cache_max_entries: layers
+                .parse_or("TRACK_CACHE_MAX_ENTRIES", DEFAULT_TRACK_CACHE_MAX_ENTRIES),
+            preresolve_ahead: layers.parse_or("PRERESOLVE_AHEAD", DEFAULT_PRERESOLVE_AHEAD),
+            disk_cache_dir: layers.get("DISK_CACHE_DIR"),
+            disk_cache_max_bytes: layers
+                .parse_or("DISK_CACHE_MAX_BYTES", DEFAULT_DISK_CACHE_MAX_BYTES),
+            disk_cache_min_plays: layers
+                .parse_or("DISK_CACHE_MIN_PLAYS", DEFAULT_DISK_CACHE_MIN_PLAYS),
+        },
+        lavalink: LavalinkConfig {
+            enabled: layers.parse_or("LAVALINK_ENABLED", false),
+            bind_address: layers.string_or("LAVALINK_BIND_ADDRESS", DEFAULT_LAVALINK_BIND_ADDRESS),
+            password: layers.get("LAVALINK_PASSWORD"),
+            player_update_interval_seconds: layers.parse_or(
+                "LAVALINK_PLAYER_UPDATE_INTERVAL",
+                DEFAULT_PLAYER_UPDATE_INTERVAL_SECONDS,
+            ),
+        },
+        grpc: GrpcConfig {
+            enabled: layers.parse_or("GRPC_ENABLED", false),
+            bind_address: layers.string_or("GRPC_BIND_ADDRESS", DEFAULT_GRPC_BIND_ADDRESS),
+            token: layers.get("GRPC_TOKEN"),
+        },
+        monitoring: MonitoringConfig {
+            enabled: layers.parse_or("MONITORING_ENABLED", false),
+            bind_address: layers
+                .string_or("MONITORING_BIND_ADDRESS", DEFAULT_MONITORING_BIND_ADDRESS),
+        },
+        tracing: TracingConfig {
+            otlp_endpoint: layers.get("OTEL_EXPORTER_OTLP_ENDPOINT"),
+            service_name: layers.string_or("OTEL_SERVICE_NAME", DEFAULT_OTEL_SERVICE_NAME),
+        },
+        admin: AdminConfig {
+            enabled: layers.parse_or("ADMIN_ENABLED", false),
+            bind_address: layers.string_or("ADMIN_BIND_ADDRESS", DEFAULT_ADMIN_BIND_ADDRESS),
+            token: layers.get("ADMIN_TOKEN"),
+        },
+        events: EventsConfig {
+            bus_capacity: layers.parse_or("EVENT_BUS_CAPACITY", DEFAULT_EVENT_BUS_CAPACITY),
+            persist_sessions: layers.parse_or("PERSIST_SESSIONS", false),
+            webhook_urls: layers
+                .get("EVENT_WEBHOOK_URLS")
+                .map(|urls| {
+                    urls.split(',')
+                        .map(|url| url.trim().to_string())
+                        .filter(|url| !url.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        },
+        redis_url: layers.get("REDIS_URL"),
+    };
+
+    if config.lavalink.enabled {
+        layers.check_address("LAVALINK_BIND_ADDRESS", &config.lavalink.bind_address);
+        if config.lavalink.password.as_deref().unwrap_or_default().is_empty() {
+            layers
+                .errors
+                .push("LAVALINK_PASSWORD must be set to enable the Lavalink API".to_string());
+        }
     }
+    if config.grpc.enabled {
+        layers.check_address("GRPC_BIND_ADDRESS", &config.grpc.bind_address);
+        if config.grpc.token.as_deref().unwrap_or_default().is_empty() {
+            layers
+                .errors
+                .push("GRPC_TOKEN must be set to enable the gRPC control plane".to_string());
+        }
+    }
+    if config.monitoring.enabled {
+        layers.check_address("MONITORING_BIND_ADDRESS", &config.monitoring.bind_address);
+    }
+    if config.admin.enabled {
+        layers.check_address("ADMIN_BIND_ADDRESS", &config.admin.bind_address);
+        if config.admin.token.as_deref().unwrap_or_default().is_empty() {
+            layers
+                .errors
+                .push("ADMIN_TOKEN must be set to enable the admin interface".to_string());
+        }
+    }
+    if config.events.persist_sessions && config.redis_url.is_none() {
+        layers
+            .errors
+            .push("REDIS_URL must be set to persist sessions".to_string());
+    }
+    match config.transport.kind {
+        TransportKind::Kafka => {
+            if let Err(problems) = security_properties(&config.kafka) {
+                layers.errors.extend(problems);
+            }
+        }
+        TransportKind::Redis => {
+            if config.redis_url.is_none() {
+                layers
+                    .errors
+                    .push("REDIS_URL must be set to use the Redis transport".to_string());
+            }
+            let shards = config.transport.redis_stream_shards;
+            for id in config.transport.redis_stream_shard_ids.iter().flatten() {
+                if *id >= shards {
+                    layers.errors.push(format!(
+                        "REDIS_STREAM_SHARD_IDS entry {} is not below REDIS_STREAM_SHARDS ({})",
+                        id, shards
+                    ));
+                }
+            }
+        }
+    }
+
+    if layers.errors.is_empty() {
+        Ok(config)
+    } else {
+        Err(layers.errors)
+    }
+}
+
+pub static CONFIG: Lazy<Config> = Lazy::new(|| match load() {
+    Ok(config) => config,
+    Err(errors) => {
+        // The logger may not be set up yet, so report straight to stderr.
+        eprintln!("Invalid configuration:");
+        for error in errors {
+            eprintln!("  - {}", error);
+        }
+        std::process::exit(1);
+    }
+});
+
+/// Identifies this worker process in logs: `INSTANCE_ID`, else `HOSTNAME`
+/// (the pod name on Kubernetes), else a random id.
+pub static INSTANCE_ID: Lazy<String> = Lazy::new(|| {
+    env::var("INSTANCE_ID")
+        .or_else(|_| env::var("HOSTNAME"))
+        .unwrap_or_else(|_| nanoid::nanoid!())
 });
+
+static RUNTIME: Lazy<RwLock<RuntimeConfig>> =
+    Lazy::new(|| RwLock::new(CONFIG.runtime.clone()));
+
+/// The current values of the settings that can be reloaded.
+pub fn runtime() -> RuntimeConfig {
+    RUNTIME.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// The current log level for `target`, without copying the whole runtime
+/// configuration on every log call.
+pub fn log_level_for(target: &str) -> LevelFilter {
+    RUNTIME
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .level_for(target)
+}
+
+/// Settings left out of the serialized configuration so they never end up
+/// in a dump or a log. Reloads compare them separately.
+fn secrets(config: &Config) -> [(&'static str, Option<&str>); 9] {
+    [
+        ("config.discord_bot_token", Some(config.config.discord_bot_token.as_str())),
+        ("kafka.kafka_password", config.kafka.kafka_password.as_deref()),
+        ("kafka.kafka_ssl_key_password", config.kafka.kafka_ssl_key_password.as_deref()),
+        ("kafka.kafka_oauth_client_secret", config.kafka.kafka_oauth_client_secret.as_deref()),
+        ("resolver.spotify_client_secret", config.resolver.spotify_client_secret.as_deref()),
+        ("resolver.apple_music_token", config.resolver.apple_music_token.as_deref()),
+        ("lavalink.password", config.lavalink.password.as_deref()),
+        ("grpc.token", config.grpc.token.as_deref()),
+        ("admin.token", config.admin.token.as_deref()),
+    ]
+}
+
+/// Collects the dotted paths of the values that differ between two
+/// serialized configurations.
+fn diff_keys(path: &str, old: &serde_json::Value, new: &serde_json::Value, changed: &mut Vec<String>) {
+    match (old, new) {
+        (serde_json::Value::Object(old), serde_json::Value::Object(new)) => {
+            let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                let missing = serde_json::Value::Null;
+                diff_keys(
+                    &path,
+                    old.get(key).unwrap_or(&missing),
+                    new.get(key).unwrap_or(&missing),
+                    changed,
+                );
+            }
+        }
+        _ if old != new => changed.push(path.to_string()),
+        _ => {}
+    }
+}
+
+/// The settings that differ between `old` and `new`, apart from the
+/// reloadable ones.
+fn changed_keys(old: &Config, new: &Config) -> Vec<String> {
+    let mut without_runtime = new.clone();
+    without_runtime.runtime = old.runtime.clone();
+
+    let mut changed = Vec::new();
+    if let (Ok(old), Ok(new)) = (serde_json::to_value(old), serde_json::to_value(&without_runtime)) {
+        diff_keys("", &old, &new, &mut changed);
+    }
+    changed.extend(
+        secrets(old)
+            .into_iter()
+            .zip(secrets(new))
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|((key, _), _)| key.to_string()),
+    );
+    if old.kafka.kafka_properties != new.kafka.kafka_properties {
+        changed.push("kafka.kafka_properties".to_string());
+    }
+    changed
+}
+
+/// Re-reads every layer and applies the reloadable settings. Changes to any
+/// other setting are only reported, since they need a restart. On errors the
+/// current settings are kept.
+pub fn reload() -> Result<RuntimeConfig, Vec<String>> {
+    let config = load()?;
+
+    let changed = changed_keys(&CONFIG, &config);
+    if !changed.is_empty() {
+        log::warn!(
+            "Changes to {} need a restart; only LOG_LEVEL, LOG_MODULES, BOT_IDLE_TIME_SECONDS and DEFAULT_VOLUME are reloaded",
+            changed.join(", ")
+        );
+    }
+
+    *RUNTIME.write().unwrap_or_else(|e| e.into_inner()) = config.runtime.clone();
+    log::set_max_level(config.runtime.max_level());
+    Ok(config.runtime)
+}
+
+/// Overrides the log level and the given per-module levels until the next
+/// reload, which goes back to the configured values.
+pub fn set_log_levels(
+    level: Option<LevelFilter>,
+    modules: Vec<(String, LevelFilter)>,
+) -> RuntimeConfig {
+    let mut runtime = RUNTIME.write().unwrap_or_else(|e| e.into_inner());
+    if let Some(level) = level {
+        runtime.log_level = level;
+    }
+    for (module, level) in modules {
+        runtime.log_modules.retain(|(existing, _)| *existing != module);
+        runtime.log_modules.push((module, level));
+    }
+    log::set_max_level(runtime.max_level());
+    runtime.clone()
+}
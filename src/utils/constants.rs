@@ -1,3 +1,59 @@
-pub const DEFAULT_JOB_EXPIRATION_TIME_SECONDS: u64 = 30;
-pub const DEFAULT_BOT_IDLE_TIME_SECONDS: u64 = 60;
+pub const DEFAULT_JOB_EXPIRATION_TIME_SECONDS: u64 = 3600;
+pub const DEFAULT_BOT_IDLE_TIME_SECONDS: u64 = 600;
 pub const KAFKA_SEND_TIMEOUT: u64 = 30;
+pub const DEFAULT_VOLUME: f32 = 1.0;
+pub const DEFAULT_LOUDNESS_TARGET_LUFS: f32 = -14.0;
+pub const DEFAULT_LOUDNESS_ANALYSIS_SECONDS: u64 = 10;
+pub const REPLAYGAIN_REFERENCE_LUFS: f32 = -18.0;
+pub const R128_REFERENCE_LUFS: f32 = -23.0;
+pub const MAX_LOUDNESS_GAIN_DB: f32 = 12.0;
+pub const DEFAULT_APPLE_MUSIC_STOREFRONT: &str = "us";
+pub const SPOTIFY_ALBUM_PAGE_LIMIT: u64 = 50;
+pub const SPOTIFY_PLAYLIST_PAGE_LIMIT: u64 = 100;
+pub const DEEZER_PAGE_LIMIT: u64 = 100;
+pub const DEFAULT_PLAYLIST_MAX_ENTRIES: usize = 100;
+pub const DEFAULT_TRACK_CACHE_TTL_SECONDS: u64 = 3600;
+pub const DEFAULT_TRACK_CACHE_MAX_ENTRIES: usize = 10_000;
+pub const DEFAULT_PRERESOLVE_AHEAD: usize = 2;
+pub const STREAM_URL_EXPIRY_MARGIN_SECONDS: u64 = 60;
+pub const TRACK_CACHE_KEY_PREFIX: &str = "ravalink:track:";
+pub const DEFAULT_DISK_CACHE_MAX_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+pub const DEFAULT_DISK_CACHE_MIN_PLAYS: u32 = 2;
+pub const DISK_CACHE_MAX_TRACKED_PLAYS: usize = 10_000;
+pub const DEFAULT_REDIS_CONSUMER_GROUP: &str = "ravalink";
+pub const DEFAULT_REDIS_STREAM_MAXLEN: usize = 10000;
+pub const REDIS_STREAM_BLOCK_MS: usize = 5000;
+pub const REDIS_STREAM_READ_COUNT: usize = 16;
+pub const DEFAULT_REDIS_CLAIM_IDLE_MS: u64 = 60_000;
+pub const DEFAULT_LAVALINK_BIND_ADDRESS: &str = "127.0.0.1:2333";
+pub const DEFAULT_PLAYER_UPDATE_INTERVAL_SECONDS: u64 = 5;
+pub const LAVALINK_STATS_INTERVAL_SECONDS: u64 = 60;
+pub const DISPATCH_TIMEOUT_SECONDS: u64 = 30;
+pub const LAVALINK_SEARCH_RESULTS: usize = 5;
+pub const DEFAULT_GRPC_BIND_ADDRESS: &str = "127.0.0.1:50051";
+pub const DEAD_LETTER_REPLAY_GROUP: &str = "ravalink-dead-letter-replay";
+pub const DEAD_LETTER_REPLAY_IDLE_SECONDS: u64 = 10;
+pub const KAFKA_PROPERTY_PREFIX: &str = "KAFKA_PROPERTY_";
+pub const CONFIG_FILE_ENV: &str = "RAVALINK_CONFIG";
+pub const DEFAULT_LOG_LEVEL: log::LevelFilter = log::LevelFilter::Debug;
+pub const CLI_CONSUMER_GROUP: &str = "ravalink-cli";
+pub const CHECK_TIMEOUT_SECONDS: u64 = 10;
+pub const DEFAULT_MONITORING_BIND_ADDRESS: &str = "0.0.0.0:9100";
+pub const KAFKA_STATISTICS_INTERVAL_MS: u64 = 15000;
+pub const READINESS_TIMEOUT_SECONDS: u64 = 2;
+pub const DEFAULT_LOG_MODULES: &str = "serenity=warn,tracing=warn";
+pub const DEFAULT_OTEL_SERVICE_NAME: &str = "ravalink";
+pub const DEFAULT_ADMIN_BIND_ADDRESS: &str = "127.0.0.1:9200";
+pub const SESSION_INBOX_CAPACITY: usize = 64;
+pub const DEFAULT_GUILD_JOB_BACKLOG: usize = 32;
+pub const GUILD_LANE_SWEEP_SECONDS: u64 = 60;
+pub const DEFAULT_EVENT_BUS_CAPACITY: usize = 256;
+pub const SESSION_KEY_PREFIX: &str = "ravalink:session:";
+pub const SESSION_TTL_SECONDS: u64 = 86400;
+pub const WEBHOOK_TIMEOUT_SECONDS: u64 = 5;
+pub const DEFAULT_KAFKA_LINGER_MS: u64 = 5;
+pub const DEFAULT_KAFKA_BATCH_SIZE: usize = 10000;
+pub const DEFAULT_MAX_QUEUE_LENGTH: usize = 1000;
+pub const DEFAULT_KAFKA_GROUP_ID: &str = "ravalink";
+pub const DEFAULT_LOUDNESS_ANALYSIS_TIMEOUT_MS: u64 = 1500;
+pub const WEBHOOK_MAX_IN_FLIGHT: usize = 64;
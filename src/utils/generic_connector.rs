@@ -1,121 +1,124 @@
 use std::sync::Arc;
 use std::future::Future;
-use log::{debug, error};
-use rdkafka::Message as KafkaMessage;
-use anyhow::Result;
-use rdkafka::consumer::{Consumer, StreamConsumer};
-use rdkafka::producer::{FutureProducer, FutureRecord};
-use rdkafka::ClientConfig;
+use log::{debug, error, info};
+use anyhow::{anyhow, Result};
+use opentelemetry::trace::{FutureExt, SpanKind, TraceContextExt};
+use opentelemetry::KeyValue;
 use songbird::Songbird;
-use tokio::sync::broadcast::Sender;
 
-use crate::utils::constants::KAFKA_SEND_TIMEOUT;
-use crate::worker::types::{ServerIPC, ServerIPCData};
+use crate::monitoring::telemetry;
+use crate::transport::dead_letter;
+use crate::transport::{InboundMessage, Transport};
 use crate::utils::config::CONFIG;
+use crate::worker::drain;
+use crate::worker::events::EventBus;
+use crate::worker::types::ServerIPC;
 use ravalink_interconnect::protocol::Message;
-use crate::utils::helpers::minutes_to_duration;
-
-fn configure_kafka_ssl(mut kafka_config: ClientConfig) -> ClientConfig {
-    let config = &CONFIG.kafka;
-    if config.kafka_use_ssl.unwrap_or(false) {
-        kafka_config
-            .set("security.protocol", "ssl")
-            .set(
-                "ssl.ca.location",
-                config.kafka_ssl_ca.as_deref().expect("Kafka CA Not Found"),
-            )
-            .set(
-                "ssl.certificate.location",
-                config.kafka_ssl_cert.as_deref().expect("Kafka Cert Not Found"),
-            )
-            .set(
-                "ssl.key.location",
-                config.kafka_ssl_key.as_deref().expect("Kafka Key Not Found"),
-            );
-    } else if config.kafka_use_sasl.unwrap_or(false) {
-        kafka_config
-            .set("security.protocol", "SASL_PLAINTEXT")
-            .set("sasl.mechanisms", "PLAIN")
-            .set(
-                "sasl.username",
-                config.kafka_username.as_deref().expect("Kafka Username Not Found"),
-            )
-            .set(
-                "sasl.password",
-                config.kafka_password.as_deref().expect("Kafka Password Not Found"),
-            );
-    }
-
-    kafka_config
-}
-
-pub async fn initialize_producer(brokers: &str) -> FutureProducer {
-    let kafka_config = ClientConfig::new()
-        .set("bootstrap.servers", brokers)
-        .clone();
-
-    let kafka_config = configure_kafka_ssl(kafka_config);
-
-    let producer: FutureProducer = kafka_config.create().expect("Failed to create Generic Producer");
-    producer
-}
 
 pub async fn initialize_consume_generic<F, Fut>(
-    brokers: &str,
+    transport: Arc<dyn Transport>,
     ipc: &mut ServerIPC,
     songbird: Option<Arc<Songbird>>,
-    group_id: &str,
     callback: F,
 )
 where
-    F: Fn(Message, Arc<Sender<ServerIPCData>>, Option<Arc<Songbird>>) -> Fut + Send + Sync,
+    F: Fn(Message, InboundMessage, Arc<EventBus>, Option<Arc<Songbird>>) -> Fut + Send + Sync,
     Fut: Future<Output = Result<()>> + Send + 'static,
 {
-    let kafka_config = ClientConfig::new()
-        .set("group.id", group_id)
-        .set("bootstrap.servers", brokers)
-        .set("enable.partition.eof", "false")
-        .set("session.timeout.ms", "6000")
-        .set("enable.auto.commit", "true")
-        .clone();
-
-    let kafka_config = configure_kafka_ssl(kafka_config);
-
-    let consumer: StreamConsumer = kafka_config.create().expect("Failed to create Consumer");
+    loop {
+        let received = tokio::select! {
+            received = transport.recv() => received,
+            _ = drain::started() => {
+                // Stay up rather than return, so running jobs and playback
+                // finish before the operator stops the process.
+                info!("Draining, no longer taking messages from the transport");
+                std::future::pending().await
+            }
+        };
 
-    consumer
-        .subscribe(&[&CONFIG.kafka.kafka_topic])
-        .expect("Can't subscribe to specified topic");
+        match received {
+            Ok(Some(m)) => {
+                // The span continues the producer's trace, and the job
+                // queued by the callback continues this span.
+                let parent = telemetry::extract(&m.headers);
+                let span = telemetry::start_span(
+                    "consume",
+                    SpanKind::Consumer,
+                    vec![
+                        KeyValue::new("messaging.destination.name", m.topic.clone()),
+                        KeyValue::new("messaging.destination.partition.id", m.partition as i64),
+                        KeyValue::new("messaging.kafka.offset", m.offset),
+                    ],
+                    &parent,
+                );
+                let cx = parent.with_span(span);
 
+                let failure = match &m.payload {
+                    Some(payload) => match serde_json::from_slice::<Message>(payload) {
+                        Ok(parsed_message) => callback(
+                            parsed_message,
+                            m.clone(),
+                            Arc::clone(&ipc.bus),
+                            songbird.clone(),
+                        )
+                        .with_context(cx.clone())
+                        .await
+                        .err()
+                        .map(|e| format!("Callback execution failed: {}", e)),
+                        Err(e) => Some(format!("Failed to parse message: {}", e)),
+                    },
+                    None => Some("Received empty payload".to_string()),
+                };
+                let outcome = match &failure {
+                    Some(failure) => Err(anyhow!(failure.clone())),
+                    None => Ok(()),
+                };
+                telemetry::record_result(&cx, &outcome);
 
-    loop {
-        match consumer.recv().await {
-            Ok(m) => {
-                if let Some(payload) = m.payload() {
-                    match serde_json::from_slice::<Message>(payload) {
-                        Ok(parsed_message) => {
-                            if let Err(e) = callback(
-                                parsed_message,
-                                Arc::clone(&ipc.sender),
-                                songbird.clone(),
-                            ).await
-                            {
-                                error!("Callback execution failed: {}", e);
+                // Messages handed to the worker pool are acknowledged once
+                // their job completes; anything else is finished here.
+                if let Some(failure) = failure {
+                    error!(
+                        topic = m.topic.as_str(), partition = m.partition, offset = m.offset;
+                        "{}", failure
+                    );
+                    let dead_lettered = match CONFIG.transport.dead_letter_topic.as_deref() {
+                        Some(topic) => dead_letter::publish(transport.as_ref(), topic, &m, &failure).await,
+                        None => Ok(()),
+                    };
+                    // Without its dead letter the message stays unacknowledged
+                    // and is redelivered rather than lost.
+                    match dead_lettered {
+                        Ok(()) => {
+                            if let Err(e) = transport.ack(&m).await {
+                                error!("Failed to acknowledge message: {}", e);
                             }
                         }
-                        Err(e) => error!("Failed to parse message: {}", e),
+                        Err(e) => error!("{:#}, leaving it unacknowledged", e),
                     }
-                } else {
-                    error!("Received empty payload!");
                 }
             }
+            Ok(None) => {
+                info!("Transport closed, stopping consumer");
+                break;
+            }
             Err(e) => error!("Failed to receive message: {}", e),
         }
     }
 }
 
-pub async fn send_generic_message(message: &Message, topic: &str, producer: &FutureProducer) {
-    let data = match serde_json::to_string(message) {
+pub async fn send_generic_message(message: &Message, topic: &str, transport: &dyn Transport) {
+    produce(message, topic, transport, false).await;
+}
+
+/// Like `send_generic_message`, but returns once the message is queued
+/// rather than once the broker has it.
+pub async fn send_generic_message_detached(message: &Message, topic: &str, transport: &dyn Transport) {
+    produce(message, topic, transport, true).await;
+}
+
+async fn produce(message: &Message, topic: &str, transport: &dyn Transport, detached: bool) {
+    let data = match serde_json::to_vec(message) {
         Ok(d) => d,
         Err(e) => {
             error!("Failed to serialize Message: {}", e);
@@ -123,11 +126,20 @@ pub async fn send_generic_message(message: &Message, topic: &str, producer: &Fut
         }
     };
 
-    let record: FutureRecord<'_, String, String> = FutureRecord::to(topic).payload(&data);
-    if let Err((e, _)) = producer.send(record, minutes_to_duration(KAFKA_SEND_TIMEOUT)).await {
-        error!("Failed to send Message: {}", e);
+    let cx = telemetry::child_context(
+        "produce",
+        SpanKind::Producer,
+        vec![KeyValue::new("messaging.destination.name", topic.to_string())],
+    );
+    let headers = telemetry::inject(&cx);
+    let result = if detached {
+        transport.send_detached(topic, &data, &headers).await
     } else {
-        debug!("Sent Message: {:?}", message);
+        transport.send_with_headers(topic, &data, &headers).await
+    };
+    match &result {
+        Err(e) => error!("Failed to send Message: {}", e),
+        Ok(()) => debug!("Sent Message: {:?}", message),
     }
+    telemetry::record_result(&cx, &result);
 }
-
@@ -1,6 +1,7 @@
 use std::env;
 use std::fs;
 use colored::Colorize;
+use crate::utils::config::{LogFormat, CONFIG};
 use crate::utils::logger::loggers;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
@@ -39,9 +40,12 @@ pub fn print_warnings() {
 
 
 pub async fn initialize() {
-    print_banner();
-    print_warnings();
+    // The banner would break up JSON log lines.
+    if CONFIG.config.log_format == LogFormat::Text {
+        print_banner();
+    }
     loggers().await;
+    print_warnings();
 }
 
 
@@ -1,5 +1,89 @@
 use fern::colors::{Color, ColoredLevelConfig};
-use log::LevelFilter;
+use log::kv::{Error as KvError, Key, Value, VisitSource};
+use log::{LevelFilter, Record};
+use serde_json::Map;
+use std::future::Future;
+use std::num::NonZero;
+
+use crate::utils::config::{log_level_for, runtime, LogFormat, CONFIG, INSTANCE_ID};
+
+/// Correlation fields added to every log line written by the task it is
+/// scoped to, so a job can be followed from the transport to voice events.
+#[derive(Clone, Default)]
+pub struct LogContext {
+    pub job_id: Option<String>,
+    pub guild_id: Option<NonZero<u64>>,
+    pub command: Option<&'static str>,
+}
+
+tokio::task_local! {
+    static LOG_CONTEXT: LogContext;
+}
+
+impl LogContext {
+    pub fn job(job_id: &str, guild_id: NonZero<u64>, command: Option<&'static str>) -> Self {
+        LogContext {
+            job_id: Some(job_id.to_string()),
+            guild_id: Some(guild_id),
+            command,
+        }
+    }
+
+    pub fn guild(guild_id: NonZero<u64>) -> Self {
+        LogContext {
+            guild_id: Some(guild_id),
+            ..LogContext::default()
+        }
+    }
+
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        LOG_CONTEXT.scope(self, future).await
+    }
+}
+
+/// Copies a record's key-value pairs into the JSON line.
+struct JsonFields<'a>(&'a mut Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), KvError> {
+        let value = if let Some(number) = value.to_u64() {
+            number.into()
+        } else if let Some(number) = value.to_i64() {
+            number.into()
+        } else if let Some(flag) = value.to_bool() {
+            flag.into()
+        } else {
+            value.to_string().into()
+        };
+        self.0.insert(key.as_str().to_string(), value);
+        Ok(())
+    }
+}
+
+fn json_line(message: &std::fmt::Arguments, record: &Record) -> String {
+    let mut line = Map::new();
+    line.insert("timestamp".into(), chrono::Utc::now().to_rfc3339().into());
+    line.insert("level".into(), record.level().as_str().into());
+    line.insert("target".into(), record.target().into());
+    line.insert("message".into(), message.to_string().into());
+    line.insert("instance_id".into(), INSTANCE_ID.as_str().into());
+
+    let _ = LOG_CONTEXT.try_with(|context| {
+        if let Some(job_id) = &context.job_id {
+            line.insert("job_id".into(), job_id.as_str().into());
+        }
+        if let Some(guild_id) = context.guild_id {
+            line.insert("guild_id".into(), guild_id.get().into());
+        }
+        if let Some(command) = context.command {
+            line.insert("command".into(), command.into());
+        }
+    });
+    // Fields passed to the log macro itself take precedence.
+    let _ = record.key_values().visit(&mut JsonFields(&mut line));
+
+    serde_json::Value::Object(line).to_string()
+}
 
 pub async fn loggers() {
     let colors = ColoredLevelConfig::new()
@@ -10,21 +94,28 @@ pub async fn loggers() {
 
     let mut loggers = fern::Dispatch::new();
 
-    loggers = loggers.format(move |out, message, record| {
-        out.finish(format_args!(
-            "{}[{}][{}] {}",
-            chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]"),
-            record.target(),
-            colors.color(record.level()),
-            message
-        ))
-    });
+    loggers = match CONFIG.config.log_format {
+        LogFormat::Text => loggers.format(move |out, message, record| {
+            out.finish(format_args!(
+                "{}[{}][{}] {}",
+                chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]"),
+                record.target(),
+                colors.color(record.level()),
+                message
+            ))
+        }),
+        LogFormat::Json => loggers.format(|out, message, record| {
+            out.finish(format_args!("{}", json_line(message, record)))
+        }),
+    };
 
-    loggers = loggers.level(log::LevelFilter::Debug);
+    // Levels are looked up on every record rather than fixed here, so
+    // `config::reload` can change them at runtime.
     loggers = loggers
-        .level_for("serenity", LevelFilter::Warn) 
-        .level_for("tracing", LevelFilter::Warn);
+        .level(LevelFilter::Trace)
+        .filter(|metadata| metadata.level() <= log_level_for(metadata.target()));
     loggers = loggers.chain(std::io::stdout());
 
     loggers.apply().unwrap();
+    log::set_max_level(runtime().max_level());
 }
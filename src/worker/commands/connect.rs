@@ -1,27 +1,31 @@
-use crate::worker::types::ServerIPCData;
+use crate::worker::types::{ServerEventType, ServerIPCData, ServerMessage};
 use anyhow::{Context, Result};
+use log::info;
+use opentelemetry::KeyValue;
 use ravalink_interconnect::protocol::Request;
-use rdkafka::producer;
-use rdkafka::producer::FutureProducer;
 use songbird::events::TrackEvent;
 use songbird::id::ChannelId;
 use songbird::id::GuildId;
 use songbird::Songbird;
-use tokio::sync::Mutex;
 use std::fmt;
 use std::sync::Arc;
-use tokio::sync::broadcast::Sender;
-use crate::handlers::voice::{TrackEndNotifier, TrackErrorNotifier};
+use crate::handlers::voice::{TrackEndNotifier, TrackErrorNotifier, TrackStartNotifier};
+use crate::monitoring::telemetry;
+use crate::state::guild;
+use crate::transport::Transport;
+use crate::worker::events::EventBus;
 
 #[allow(clippy::enum_variant_names)]
 pub enum ChannelControlError {
-    ManagerAcquisitionFailed
+    ManagerAcquisitionFailed,
+    ChannelNotProvided,
 }
 
 impl fmt::Display for ChannelControlError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             ChannelControlError::ManagerAcquisitionFailed => write!(f, "Failed to acquire manager"),
+            ChannelControlError::ChannelNotProvided => write!(f, "Voice Channel not provided"),
         }
     }
 }
@@ -29,35 +33,98 @@ impl fmt::Display for ChannelControlError {
 pub async fn run(
     request: &Request,
     manager: &mut Option<Arc<Songbird>>,
-    ipc: Arc<Sender<ServerIPCData>>,
-    producer : Arc<Mutex<FutureProducer>>
+    ipc: Arc<EventBus>,
+    transport : Arc<dyn Transport>
 ) -> Result<()> {
     let gid = request.guild_id;
-    let vcid = request.voice_channel_id.expect("Voice Channel not provided");
+    let vcid = request
+        .voice_channel_id
+        .context(ChannelControlError::ChannelNotProvided.to_string())?;
 
-    if let Ok(handler_lock) = manager
+    let manager = manager
         .as_mut()
-        .context(ChannelControlError::ManagerAcquisitionFailed.to_string())?
-        .join(GuildId(gid), ChannelId(vcid))
-        .await
-    {
-        let mut handler = handler_lock.lock().await;
-        handler.add_global_event(
-            TrackEvent::Error.into(),
-            TrackErrorNotifier {
-                job_id: request.job_id.clone(),
-                guild_id: request.guild_id.clone(),
-                ipc : ipc.clone(),
-                producer : producer.clone(),
-
-            },
-        );
-        handler.add_global_event(TrackEvent::End.into(), TrackEndNotifier {
+        .context(ChannelControlError::ManagerAcquisitionFailed.to_string())?;
+    let previous_channel = match manager.get(GuildId(gid)) {
+        Some(call) => call.lock().await.current_channel().map(|channel| channel.0),
+        None => None,
+    };
+
+    // Joining through the manager reuses the guild's existing Call, so a
+    // move keeps the driver along with the playing track and its position.
+    let handler_lock = if previous_channel == Some(vcid) {
+        manager
+            .get(GuildId(gid))
+            .context(ChannelControlError::ManagerAcquisitionFailed.to_string())?
+    } else {
+        let joined = telemetry::in_span(
+            if previous_channel.is_some() { "voice move" } else { "voice join" },
+            vec![
+                KeyValue::new("ravalink.guild_id", gid.get().to_string()),
+                KeyValue::new("ravalink.voice_channel_id", vcid.get().to_string()),
+            ],
+            async { Ok(manager.join(GuildId(gid), ChannelId(vcid)).await?) },
+        )
+        .await;
+        joined?
+    };
+    guild::open(gid);
+
+    // Replace the notifiers rather than stacking another set on the Call, so
+    // each track event is reported once and with the latest job id.
+    let publish = transport.publishes_events();
+    let mut handler = handler_lock.lock().await;
+    handler.remove_all_global_events();
+    handler.add_global_event(
+        TrackEvent::Error.into(),
+        TrackErrorNotifier {
             job_id: request.job_id.clone(),
             guild_id: request.guild_id.clone(),
             ipc: ipc.clone(),
-            producer : producer.clone(),
+            publish,
+        },
+    );
+    handler.add_global_event(TrackEvent::Play.into(), TrackStartNotifier {
+        job_id: request.job_id.clone(),
+        guild_id: request.guild_id.clone(),
+        ipc: ipc.clone(),
+        publish,
+    });
+    handler.add_global_event(TrackEvent::End.into(), TrackEndNotifier {
+        job_id: request.job_id.clone(),
+        guild_id: request.guild_id.clone(),
+        ipc: ipc.clone(),
+        publish,
+    });
+    drop(handler);
+
+    if let Some(from) = previous_channel.filter(|from| *from != vcid) {
+        info!("Moved guild {} from voice channel {} to {}", gid, from, vcid);
+        ipc.publish(ServerIPCData {
+            message: ServerMessage::Event(ServerEventType::ChannelMoved { from, to: vcid }),
+            guild_id: gid,
+            job_id: request.job_id.clone(),
+            publish,
         });
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Joins the request's voice channel first when the bot is not in a voice
+/// channel in the guild, so a Play can be sent without an earlier Connect.
+/// Without a `voice_channel_id` nothing is joined.
+pub async fn join_if_needed(
+    request: &Request,
+    manager: &Arc<Songbird>,
+    ipc: Arc<EventBus>,
+    transport: Arc<dyn Transport>,
+) -> Result<()> {
+    if request.voice_channel_id.is_none() {
+        return Ok(());
+    }
+    if let Some(call) = manager.get(GuildId(request.guild_id)) {
+        if call.lock().await.current_channel().is_some() {
+            return Ok(());
+        }
+    }
+    run(request, &mut Some(manager.clone()), ipc, transport).await
+}
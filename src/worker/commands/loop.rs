@@ -0,0 +1,10 @@
+use anyhow::Result;
+use log::info;
+use ravalink_interconnect::protocol::Request;
+use crate::state::guild;
+
+pub async fn run(request: &Request) -> Result<()> {
+    let mode = guild::cycle_loop(request.guild_id).await?;
+    info!("Loop mode for guild {} is now {:?}", request.guild_id, mode);
+    Ok(())
+}
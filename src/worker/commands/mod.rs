@@ -10,6 +10,10 @@ pub mod stop;
 pub mod pause;
 pub mod resume;
 pub mod skip;
+pub mod volume;
+pub mod r#loop;
+pub mod shuffle;
+pub mod seek;
 
 pub async fn get_manager_call(
     guild_id: NonZero<u64>,
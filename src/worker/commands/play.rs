@@ -1,23 +1,39 @@
 use anyhow::Result;
+use log::{debug, warn};
 use ravalink_interconnect::protocol::Request;
-use songbird::input::YoutubeDl;
-use songbird::tracks::TrackHandle;
-use songbird::Songbird;
+use songbird::events::TrackEvent;
+use songbird::input::{File, Input};
+use songbird::tracks::{Track, TrackHandle};
+use songbird::{Event, Songbird};
 use std::fmt;
+use std::num::NonZero;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+use crate::handlers::voice::QueueAdvancer;
+use crate::resolver::{isrc_query, RESOLVER};
 use crate::worker::commands::get_manager_call;
+use crate::worker::disk_cache::DISK_CACHE;
+use crate::worker::loudness;
+use crate::worker::playlist;
+use crate::worker::track_cache::{ResolvedTrack, TRACK_CACHE};
+use crate::worker::types::{QueueSummary, QueuedTrack, TrackSource};
+use crate::utils::config::CONFIG;
+use crate::state::guild;
 use reqwest::Client;
-use songbird::input::Compose;
 
 #[derive(Debug)]
 enum PlaybackError {
     MissingAudioURL,
+    EmptyPlaylist,
 }
 
 impl fmt::Display for PlaybackError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             PlaybackError::MissingAudioURL => write!(f, "Missing Audio URL"),
+            PlaybackError::EmptyPlaylist => write!(f, "Playlist has no playable entries"),
         }
     }
 }
@@ -29,20 +45,172 @@ pub async fn run(
     manager: &mut Option<Arc<Songbird>>,
     client: Client,
     url: String,
-) -> Result<TrackHandle> {
-    let handler_lock = get_manager_call(request.guild_id, manager).await?;
-    let mut handler = handler_lock.lock().await;
-    let mut source = YoutubeDl::new(client, url);
+) -> Result<Option<QueueSummary>> {
+    if url.trim().is_empty() {
+        return Err(PlaybackError::MissingAudioURL.into());
+    }
+
+    // Fail early if the bot is not in a voice channel for this guild.
+    get_manager_call(request.guild_id, manager).await?;
 
-    match source.aux_metadata().await {
-        Ok(metadata) => {
-            println!("AuxMetadata: {:?}", metadata);
+    let (tracks, summary) = if playlist::is_playlist_url(&url) {
+        let tracks = playlist::expand(&url).await?;
+        let summary = QueueSummary::from_tracks(&tracks);
+        (tracks, Some(summary))
+    } else {
+        match RESOLVER.resolve(&url).await? {
+            Some(entries) => {
+                let tracks: Vec<QueuedTrack> = entries
+                    .into_iter()
+                    .take(CONFIG.config.playlist_max_entries)
+                    .map(QueuedTrack::from)
+                    .collect();
+                let summary = QueueSummary::from_tracks(&tracks);
+                (tracks, Some(summary))
+            }
+            None => (vec![QueuedTrack::from_url(url)], None),
         }
+    };
+
+    if tracks.is_empty() {
+        return Err(PlaybackError::EmptyPlaylist.into());
+    }
+
+    if guild::enqueue(request.guild_id, tracks).await? {
+        play_next(request.guild_id, manager, client).await?;
+    } else {
+        preresolve_upcoming(request.guild_id).await;
+    }
+
+    Ok(summary)
+}
+
+/// Starts the next queued track of the guild, if any. The track carries a
+/// `QueueAdvancer` so the queue keeps moving once it finishes. Entries that
+/// fail to resolve are skipped; the last failure is returned if none plays.
+pub async fn play_next(
+    guild_id: NonZero<u64>,
+    manager: &mut Option<Arc<Songbird>>,
+    client: Client,
+) -> Result<Option<TrackHandle>> {
+    let handler_lock = match get_manager_call(guild_id, manager).await {
+        Ok(handler_lock) => handler_lock,
         Err(e) => {
-            eprintln!("Error fetching metadata: {:?}", e);
+            guild::clear_playback(guild_id).await;
+            return Err(e);
+        }
+    };
+
+    let mut last_error = None;
+    let (resolved, cached_file) = loop {
+        let Some(next) = guild::next_track(guild_id).await else {
+            return match last_error {
+                Some(e) => Err(e),
+                None => Ok(None),
+            };
+        };
+
+        // A cached YouTube link plays without waiting on yt-dlp.
+        if let (Some(cache), TrackSource::Url(url)) = (DISK_CACHE.as_ref(), &next.source) {
+            if let Some((path, resolved)) = cache.get_by_url(url).await {
+                break (resolved, Some(path));
+            }
+        }
+
+        match TRACK_CACHE.resolve(next.source.query()).await {
+            Ok(resolved) => break (resolved, None),
+            Err(e) => {
+                if let Some(isrc) = next.isrc.as_deref() {
+                    match TRACK_CACHE.resolve(&isrc_query(isrc)).await {
+                        Ok(resolved) => break (resolved, None),
+                        Err(e) => debug!("No match for ISRC {}: {:?}", isrc, e),
+                    }
+                }
+                warn!("Skipping track {:?} that failed to resolve: {:?}", next.source, e);
+                last_error = Some(e);
+            }
+        }
+    };
+    debug!("Resolved track: {:?}", resolved.title);
+
+    let cached_file = match (cached_file, DISK_CACHE.as_ref(), resolved.track_id.as_deref()) {
+        (Some(path), _, _) => Some(path),
+        (None, Some(cache), Some(track_id)) => cache.get(track_id).await,
+        _ => None,
+    };
+
+    let gain = if guild::is_normalization_enabled(guild_id).await {
+        normalization_gain(client.clone(), &resolved, cached_file.as_ref()).await
+    } else {
+        1.0
+    };
+
+    let input: Input = match cached_file {
+        Some(path) => {
+            debug!("Playing {:?} from disk cache", resolved.track_id);
+            File::new(path).into()
+        }
+        None => {
+            if let Some(cache) = DISK_CACHE.as_ref() {
+                cache.record_play(&resolved, client.clone()).await;
+            }
+            resolved.to_input(client.clone())
+        }
+    };
+
+    guild::set_now_playing(guild_id, resolved).await;
+
+    // Start at the final volume rather than correcting it once the first
+    // frames have already played at unity gain.
+    let track = Track::new(input).volume(guild::volume(guild_id).await * gain);
+    let mut handler = handler_lock.lock().await;
+    let track_handle = handler.play(track);
+    drop(handler);
+
+    let advancer = QueueAdvancer {
+        guild_id,
+        manager: manager.clone(),
+        client,
+    };
+    track_handle.add_event(Event::Track(TrackEvent::End), advancer.clone())?;
+    track_handle.add_event(Event::Track(TrackEvent::Error), advancer)?;
+
+    guild::set_current_track(guild_id, track_handle.clone(), gain).await?;
+    preresolve_upcoming(guild_id).await;
+    Ok(Some(track_handle))
+}
+
+/// The gain bringing the track to the loudness target. Tagged gain is used
+/// when the file carries it; otherwise the start of the track is measured, for
+/// at most `LOUDNESS_ANALYSIS_TIMEOUT_MS` so a slow source does not hold up
+/// playback.
+async fn normalization_gain(client: Client, resolved: &ResolvedTrack, cached_file: Option<&PathBuf>) -> f32 {
+    let limit = Duration::from_millis(CONFIG.audio.loudness_analysis_timeout_ms);
+    let deadline = Instant::now() + limit;
+    let analysis = async {
+        match cached_file {
+            Some(path) => loudness::analyze(File::new(path.clone()), deadline).await,
+            None => loudness::analyze_resolved(client, resolved, deadline).await,
+        }
+    };
+    match timeout(limit, analysis).await {
+        Ok(Ok(gain)) => gain,
+        Ok(Err(e)) => {
+            warn!("Loudness analysis failed, playing without normalization: {:?}", e);
+            1.0
+        }
+        Err(_) => {
+            warn!("Loudness analysis timed out, playing without normalization");
+            1.0
         }
     }
+}
 
-    let track_handle = handler.play_input(source.into());
-    Ok(track_handle)
-}
\ No newline at end of file
+async fn preresolve_upcoming(guild_id: NonZero<u64>) {
+    let queries = guild::upcoming(guild_id, CONFIG.cache.preresolve_ahead)
+        .await
+        .into_iter()
+        .map(|track| track.source.query().to_string())
+        .collect();
+    TRACK_CACHE.preresolve(queries);
+}
@@ -0,0 +1,7 @@
+use anyhow::Result;
+use ravalink_interconnect::protocol::Request;
+use crate::state::guild;
+
+pub async fn run(request: &Request) -> Result<()> {
+    guild::resume(request.guild_id).await
+}
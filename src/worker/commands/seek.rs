@@ -0,0 +1,9 @@
+use anyhow::Result;
+use ravalink_interconnect::protocol::Request;
+use std::time::Duration;
+use crate::state::guild;
+
+/// Seeks the current track to `position`, in milliseconds.
+pub async fn run(request: &Request, position: u64) -> Result<()> {
+    guild::seek(request.guild_id, Duration::from_millis(position)).await
+}
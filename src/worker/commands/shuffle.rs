@@ -0,0 +1,8 @@
+use anyhow::Result;
+use ravalink_interconnect::protocol::Request;
+use crate::state::guild;
+
+pub async fn run(request: &Request) -> Result<()> {
+    guild::shuffle(request.guild_id).await?;
+    Ok(())
+}
@@ -6,6 +6,7 @@ use songbird::tracks::TrackHandle;
 use songbird::Songbird;
 use std::fmt;
 use std::sync::Arc;
+use crate::state::guild;
 
 #[allow(clippy::enum_variant_names)]
 pub enum ChannelControlError {
@@ -27,6 +28,7 @@ pub async fn run(
     manager: &mut Option<Arc<Songbird>>,
 
 ) -> Result<()> {
+    guild::clear_playback(request.guild_id).await;
     manager
         .as_mut()
         .context(ChannelControlError::ManagerAcquisitionFailed.to_string())?
@@ -0,0 +1,10 @@
+use anyhow::Result;
+use ravalink_interconnect::protocol::Request;
+use crate::state::guild;
+
+pub async fn run(
+    request: &Request,
+    volume: f32,
+) -> Result<()> {
+    guild::set_volume(request.guild_id, volume).await
+}
@@ -1,77 +1,116 @@
-use crate::utils::config::CONFIG;
-use crate::utils::generic_connector::{initialize_producer, send_generic_message, initialize_consume_generic};
+use crate::admin;
+use crate::grpc;
+use crate::lavalink;
+use crate::monitoring::{self, metrics};
+use crate::state::persistence;
+use crate::utils::config::{TransportKind, CONFIG};
+use crate::utils::generic_connector::{send_generic_message, send_generic_message_detached, initialize_consume_generic};
 
+use crate::transport::kafka::KafkaTransport;
+use crate::transport::redis::RedisStreamTransport;
+use crate::transport::{InboundMessage, Transport};
+use crate::worker::events::Subscriber;
 use crate::worker::types::ServerIPC;
+use crate::worker::webhooks;
 use anyhow::Result;
 use ravalink_interconnect::protocol::Message;
-use log::error;
-use rdkafka::producer::FutureProducer;
 use songbird::Songbird;
-use std::sync::{Arc, OnceLock};
-use tokio::sync::Mutex;
+use std::sync::Arc;
+use crate::worker::dispatcher::Dispatcher;
 use crate::worker::pool::WorkerPool;
 
-pub static WORKER_PRODUCER: OnceLock<Mutex<Option<FutureProducer>>> = OnceLock::new();
 
+pub async fn initialize_api(ipc: &mut ServerIPC, songbird: Option<Arc<Songbird>>) {
+    let transport: Arc<dyn Transport> = match CONFIG.transport.kind {
+        TransportKind::Kafka => {
+            let broker = CONFIG.kafka.kafka_uri.to_string();
+            Arc::new(
+                KafkaTransport::new(&broker)
+                    .await
+                    .expect("Failed to initialize Kafka transport"),
+            )
+        }
+        TransportKind::Redis => {
+            let redis_url = CONFIG
+                .redis_url
+                .as_deref()
+                .expect("REDIS_URL must be set to use the Redis transport");
+            Arc::new(
+                RedisStreamTransport::new(redis_url, &CONFIG.kafka.kafka_topic)
+                    .await
+                    .expect("Failed to initialize Redis Streams transport"),
+            )
+        }
+    };
 
-pub async fn initialize_api(
-    ipc: &mut ServerIPC,
-    songbird: Option<Arc<Songbird>>,
-    group_id: &String,
-) {
-    let broker = CONFIG.kafka.kafka_uri.to_string();
-    let _x = CONFIG.clone();
+    if CONFIG.monitoring.enabled {
+        monitoring::start(songbird.clone(), transport.clone()).await;
+    }
+    if CONFIG.admin.enabled {
+        admin::start(songbird.clone()).await;
+    }
 
-    initialize_worker_consume(broker, ipc, songbird, group_id).await;
+    let worker_pool = Arc::new(WorkerPool::new(ipc));
+    tokio::spawn(WorkerPool::publish_events(
+        ipc.bus.subscribe(Subscriber::Publisher),
+        transport.clone(),
+    ));
+    tokio::spawn(metrics::record_events(ipc.bus.subscribe(Subscriber::Metrics)));
+    if CONFIG.events.persist_sessions {
+        tokio::spawn(persistence::run(ipc.bus.subscribe(Subscriber::Sessions)));
+    }
+    if !CONFIG.events.webhook_urls.is_empty() {
+        tokio::spawn(webhooks::run(ipc.bus.subscribe(Subscriber::Webhooks)));
+    }
+
+    if CONFIG.lavalink.enabled || CONFIG.grpc.enabled {
+        let dispatcher = Arc::new(Dispatcher::new(worker_pool.clone(), songbird.clone()));
+        if CONFIG.lavalink.enabled {
+            lavalink::start(dispatcher.clone(), songbird.clone(), ipc.bus.clone()).await;
+        }
+        if CONFIG.grpc.enabled {
+            grpc::start(dispatcher, ipc.bus.clone()).await;
+        }
+    }
+
+    initialize_worker_consume(transport, worker_pool, ipc, songbird).await;
 }
 
 async fn parse_message_callback(
     message: Message,
+    delivery: InboundMessage,
     worker_pool: Arc<WorkerPool>,
+    transport: Arc<dyn Transport>,
     songbird: Option<Arc<Songbird>>,
 ) -> Result<()> {
-    if let Some(producer_mutex) = WORKER_PRODUCER.get() {
-        let producer_guard = producer_mutex.lock().await;
-        if let Some(producer) = &*producer_guard {
-            let producer = Arc::new(Mutex::new(producer.clone()));
-            worker_pool.send_job(message, producer, songbird).await?;
-        } else {
-            error!("Kafka producer is not initialized");
-        }
-    } else {
-        error!("Kafka producer is not initialized");
-    }
-
-    Ok(())
+    worker_pool.send_job(message, Some(delivery), transport, songbird).await
 }
 
 
 pub async fn initialize_worker_consume(
-    brokers: String,
+    transport: Arc<dyn Transport>,
+    worker_pool: Arc<WorkerPool>,
     ipc: &mut ServerIPC,
     songbird: Option<Arc<Songbird>>,
-    group_id: &String,
 ) {
-    let producer: FutureProducer = initialize_producer(&brokers).await;
-    let _ = WORKER_PRODUCER.set(Mutex::new(Some(producer)));
-
-
-    let worker_pool = Arc::new(WorkerPool::new(ipc));
-
     initialize_consume_generic(
-        &brokers,
+        transport.clone(),
         ipc,
         songbird,
-        group_id,
-        |message, _sender, songbird| {
+        |message, delivery, _sender, songbird| {
             let worker_pool = Arc::clone(&worker_pool);
-            parse_message_callback(message, worker_pool, songbird)
+            parse_message_callback(message, delivery, worker_pool, transport.clone(), songbird)
         },
     )
     .await;
 }
 
 
-pub async fn send_message(message: &Message, topic: &str, producer: &mut FutureProducer) {
-    send_generic_message(message, topic, producer).await;
+pub async fn send_message(message: &Message, topic: &str, transport: &dyn Transport) {
+    send_generic_message(message, topic, transport).await;
+}
+
+/// Sends without waiting for the broker's confirmation, for events.
+pub async fn send_message_detached(message: &Message, topic: &str, transport: &dyn Transport) {
+    send_generic_message_detached(message, topic, transport).await;
 }
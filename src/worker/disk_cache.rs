@@ -0,0 +1,286 @@
+use anyhow::{anyhow, Context, Result};
+use log::{debug, error, info, warn};
+use once_cell::sync::Lazy;
+use reqwest::header::RANGE;
+use reqwest::Client;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::utils::config::CONFIG;
+use crate::utils::constants::DISK_CACHE_MAX_TRACKED_PLAYS;
+use crate::utils::helpers::get_timestamp;
+use crate::worker::track_cache::{track_id_for_url, ResolvedTrack};
+
+const PARTIAL_EXTENSION: &str = "part";
+/// Each cached file has the resolved track stored next to it, so it can be
+/// played without resolving it again.
+const METADATA_EXTENSION: &str = "json";
+
+struct CacheEntry {
+    path: PathBuf,
+    size: u64,
+    last_access: u64,
+}
+
+struct PlayCount {
+    count: u32,
+    last_play: u64,
+}
+
+#[derive(Default)]
+struct CacheIndex {
+    entries: HashMap<String, CacheEntry>,
+    plays: HashMap<String, PlayCount>,
+    downloading: HashSet<String>,
+    total_size: u64,
+}
+
+impl CacheIndex {
+    /// Forgets the least recently played half of the play counts once more
+    /// than `max_tracked` tracks are being counted.
+    fn prune_plays(&mut self, max_tracked: usize) {
+        if self.plays.len() <= max_tracked {
+            return;
+        }
+        let mut last_plays: Vec<u64> = self.plays.values().map(|p| p.last_play).collect();
+        let middle = last_plays.len() / 2;
+        let (_, &mut cutoff, _) = last_plays.select_nth_unstable(middle);
+        self.plays.retain(|_, p| p.last_play > cutoff);
+    }
+
+    /// Removes least recently used files until the cache fits in `max_bytes`.
+    fn evict(&mut self, max_bytes: u64) {
+        while self.total_size > max_bytes {
+            let Some(key) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_access)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+
+            if let Some(entry) = self.entries.remove(&key) {
+                self.total_size = self.total_size.saturating_sub(entry.size);
+                let _ = fs::remove_file(entry.path.with_extension(METADATA_EXTENSION));
+                if let Err(e) = fs::remove_file(&entry.path) {
+                    warn!("Failed to evict cached track {:?}: {}", entry.path, e);
+                } else {
+                    debug!("Evicted cached track: {}", key);
+                }
+            }
+        }
+        self.prune_plays(DISK_CACHE_MAX_TRACKED_PLAYS);
+    }
+}
+
+/// Keeps only characters that are safe in a file name.
+fn sanitize_key(key: &str) -> String {
+    key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// LRU cache of downloaded audio containers on disk, keyed by the canonical
+/// track id (`<extractor>-<id>`) reported by yt-dlp or read from the URL. Tracks are only stored
+/// once they have been played `DISK_CACHE_MIN_PLAYS` times.
+pub struct DiskCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    min_plays: u32,
+    index: Mutex<CacheIndex>,
+}
+
+impl DiskCache {
+    pub fn new(dir: &Path, max_bytes: u64, min_plays: u32) -> Result<Self> {
+        fs::create_dir_all(dir).context("Failed to create disk cache directory")?;
+
+        let mut index = CacheIndex::default();
+        for entry in fs::read_dir(dir)?.flatten() {
+            let path = entry.path();
+            match path.extension().and_then(|e| e.to_str()) {
+                Some(PARTIAL_EXTENSION) => {
+                    let _ = fs::remove_file(&path);
+                    continue;
+                }
+                Some(METADATA_EXTENSION) => continue,
+                _ => {}
+            }
+            let (Some(key), Ok(metadata)) = (
+                path.file_stem().and_then(|s| s.to_str()).map(str::to_string),
+                entry.metadata(),
+            ) else {
+                continue;
+            };
+            let last_access = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            index.total_size += metadata.len();
+            index.entries.insert(key, CacheEntry { path, size: metadata.len(), last_access });
+        }
+        index.evict(max_bytes);
+
+        info!(
+            "Disk cache loaded {} tracks ({} bytes) from {:?}",
+            index.entries.len(),
+            index.total_size,
+            dir
+        );
+
+        Ok(DiskCache {
+            dir: dir.to_path_buf(),
+            max_bytes,
+            min_plays,
+            index: Mutex::new(index),
+        })
+    }
+
+    /// Returns the cached file for the track, marking it as recently used.
+    pub async fn get(&self, track_id: &str) -> Option<PathBuf> {
+        let key = sanitize_key(track_id);
+        let mut index = self.index.lock().await;
+        let entry = index.entries.get_mut(&key)?;
+        entry.last_access = get_timestamp();
+        Some(entry.path.clone())
+    }
+
+    /// Looks a track up by the URL it was queued with, before it is resolved.
+    /// Returns the cached file along with the track it was downloaded from.
+    /// Only URLs whose track id can be read without yt-dlp are recognised.
+    pub async fn get_by_url(&self, url: &str) -> Option<(PathBuf, ResolvedTrack)> {
+        let path = self.get(&track_id_for_url(url)?).await?;
+        let metadata = tokio::fs::read(path.with_extension(METADATA_EXTENSION)).await.ok()?;
+        match serde_json::from_slice(&metadata) {
+            Ok(track) => Some((path, track)),
+            Err(e) => {
+                warn!("Ignoring unreadable metadata for cached track {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Counts a play of a streamed track and downloads it in the background
+    /// once it has been played often enough.
+    pub async fn record_play(&'static self, track: &ResolvedTrack, client: Client) {
+        let Some(track_id) = track.track_id.as_deref() else {
+            return;
+        };
+        if track.protocol.as_deref() == Some("m3u8_native") {
+            return;
+        }
+        if matches!(track.filesize, Some(size) if size > self.max_bytes) {
+            return;
+        }
+
+        let key = sanitize_key(track_id);
+        {
+            let mut index = self.index.lock().await;
+            if index.entries.contains_key(&key) || index.downloading.contains(&key) {
+                return;
+            }
+            let plays = index
+                .plays
+                .entry(key.clone())
+                .or_insert(PlayCount { count: 0, last_play: 0 });
+            plays.count += 1;
+            plays.last_play = get_timestamp();
+            if plays.count < self.min_plays {
+                index.prune_plays(DISK_CACHE_MAX_TRACKED_PLAYS);
+                return;
+            }
+            index.downloading.insert(key.clone());
+        }
+
+        let track = track.clone();
+        tokio::spawn(async move {
+            if let Err(e) = self.download(&key, &track, client).await {
+                error!("Failed to cache track {}: {:?}", key, e);
+            }
+            self.index.lock().await.downloading.remove(&key);
+        });
+    }
+
+    async fn download(&self, key: &str, track: &ResolvedTrack, client: Client) -> Result<()> {
+        let ext = track.ext.as_deref().unwrap_or("bin");
+        let path = self.dir.join(format!("{}.{}", key, ext));
+        let partial = self.dir.join(format!("{}.{}", key, PARTIAL_EXTENSION));
+
+        let mut request = client.get(&track.stream_url).headers(track.headers());
+        if let Some(size) = track.filesize {
+            request = request.header(RANGE, format!("bytes=0-{}", size.saturating_sub(1)));
+        }
+        let mut response = request.send().await?.error_for_status()?;
+
+        let mut file = tokio::fs::File::create(&partial).await?;
+        let mut size: u64 = 0;
+        while let Some(chunk) = response.chunk().await? {
+            size += chunk.len() as u64;
+            if size > self.max_bytes {
+                drop(file);
+                let _ = tokio::fs::remove_file(&partial).await;
+                return Err(anyhow!("Track exceeds the disk cache size limit"));
+            }
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        drop(file);
+        tokio::fs::write(path.with_extension(METADATA_EXTENSION), serde_json::to_vec(track)?).await?;
+        tokio::fs::rename(&partial, &path).await?;
+
+        let mut index = self.index.lock().await;
+        index.total_size += size;
+        index.plays.remove(key);
+        index.entries.insert(
+            key.to_string(),
+            CacheEntry { path, size, last_access: get_timestamp() },
+        );
+        index.evict(self.max_bytes);
+        debug!("Cached track {} ({} bytes)", key, size);
+
+        Ok(())
+    }
+}
+
+pub static DISK_CACHE: Lazy<Option<DiskCache>> = Lazy::new(|| {
+    let dir = CONFIG.cache.disk_cache_dir.as_deref()?;
+    match DiskCache::new(
+        Path::new(dir),
+        CONFIG.cache.disk_cache_max_bytes,
+        CONFIG.cache.disk_cache_min_plays,
+    ) {
+        Ok(cache) => Some(cache),
+        Err(e) => {
+            error!("Failed to initialize disk cache, streaming only: {:?}", e);
+            None
+        }
+    }
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prunes_least_recently_played_counts() {
+        let mut index = CacheIndex::default();
+        for i in 0..10u64 {
+            index.plays.insert(format!("track-{}", i), PlayCount { count: 1, last_play: i });
+        }
+
+        index.prune_plays(10);
+        assert_eq!(index.plays.len(), 10);
+
+        index.plays.insert("track-10".to_string(), PlayCount { count: 1, last_play: 10 });
+        index.prune_plays(10);
+        assert_eq!(index.plays.len(), 5);
+        assert!(index.plays.contains_key("track-10"));
+        assert!(!index.plays.contains_key("track-5"));
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::{anyhow, Result};
+use nanoid::nanoid;
+use ravalink_interconnect::protocol::{Command, Message, Request, Response};
+use songbird::Songbird;
+use std::num::NonZero;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+
+use crate::transport::reply::ReplyTransport;
+use crate::transport::Transport;
+use crate::utils::constants::DISPATCH_TIMEOUT_SECONDS;
+use crate::utils::helpers::get_timestamp;
+use crate::worker::pool::WorkerPool;
+
+/// Runs commands for the request/response front ends (the Lavalink API and
+/// gRPC) through the same `WorkerPool` jobs the Kafka consumer submits, and
+/// waits for the worker's response.
+pub struct Dispatcher {
+    pool: Arc<WorkerPool>,
+    songbird: Option<Arc<Songbird>>,
+    transport: Arc<ReplyTransport>,
+}
+
+impl Dispatcher {
+    pub fn new(pool: Arc<WorkerPool>, songbird: Option<Arc<Songbird>>) -> Self {
+        Dispatcher {
+            pool,
+            songbird,
+            transport: Arc::new(ReplyTransport::new()),
+        }
+    }
+
+    pub async fn dispatch(
+        &self,
+        guild_id: NonZero<u64>,
+        voice_channel_id: Option<NonZero<u64>>,
+        command: Command,
+    ) -> Result<Response> {
+        let job_id = nanoid!();
+        let response = self.transport.register(&job_id).await;
+        let request = Message::Request(Request {
+            job_id: job_id.clone(),
+            guild_id,
+            voice_channel_id,
+            command,
+            timestamp: get_timestamp(),
+        });
+
+        let transport: Arc<dyn Transport> = self.transport.clone();
+        if let Err(e) = self
+            .pool
+            .send_job(request, None, transport, self.songbird.clone())
+            .await
+        {
+            self.transport.forget(&job_id).await;
+            return Err(e);
+        }
+
+        match timeout(Duration::from_secs(DISPATCH_TIMEOUT_SECONDS), response).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => Err(anyhow!("Job {} was dropped by the worker", job_id)),
+            Err(_) => {
+                self.transport.forget(&job_id).await;
+                Err(anyhow!("Job {} timed out", job_id))
+            }
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use once_cell::sync::Lazy;
+use tokio::sync::watch;
+
+/// Set once an operator starts draining the worker. A draining worker takes
+/// no new messages from the transport and reports itself not ready, while
+/// jobs already running and current playback carry on.
+static DRAINING: Lazy<watch::Sender<bool>> = Lazy::new(|| watch::channel(false).0);
+
+/// Starts draining. Returns `false` if the worker was already draining.
+pub fn start() -> bool {
+    !DRAINING.send_replace(true)
+}
+
+pub fn is_draining() -> bool {
+    *DRAINING.borrow()
+}
+
+/// Resolves once draining has started.
+pub async fn started() {
+    let mut receiver = DRAINING.subscribe();
+    let _ = receiver.wait_for(|draining| *draining).await;
+}
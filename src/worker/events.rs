@@ -0,0 +1,139 @@
+use log::warn;
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::monitoring::metrics;
+use crate::worker::types::ServerIPCData;
+
+/// The consumers of worker events. Each gets its own buffer, so one of them
+/// falling behind never costs another one an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subscriber {
+    /// Publishes events on the transport the job came from.
+    Publisher,
+    Metrics,
+    Sessions,
+    Webhooks,
+    Lavalink,
+    Grpc,
+}
+
+impl Subscriber {
+    pub fn label(self) -> &'static str {
+        match self {
+            Subscriber::Publisher => "publisher",
+            Subscriber::Metrics => "metrics",
+            Subscriber::Sessions => "sessions",
+            Subscriber::Webhooks => "webhooks",
+            Subscriber::Lavalink => "lavalink",
+            Subscriber::Grpc => "grpc",
+        }
+    }
+
+    /// What publishing does when the subscriber's buffer is full. The worker's
+    /// own consumers are waited for; API clients never hold back the others.
+    fn on_full(self) -> Overflow {
+        match self {
+            Subscriber::Publisher | Subscriber::Metrics | Subscriber::Sessions | Subscriber::Webhooks => {
+                Overflow::Wait
+            }
+            // One forwarder serves every Lavalink session, and disconnects a
+            // session whose own buffer fills up.
+            Subscriber::Lavalink => Overflow::Drop,
+            // Each gRPC subscription is a single client's stream.
+            Subscriber::Grpc => Overflow::Disconnect,
+        }
+    }
+}
+
+enum Overflow {
+    Wait,
+    Drop,
+    Disconnect,
+}
+
+/// Delivers every worker event to every subscriber, in the order they were
+/// published. Publishing only queues the event, so songbird's event handlers
+/// never wait on a subscriber. A forwarding task hands events out; when an
+/// internal subscriber's buffer is full it waits until there is room, while
+/// an API client that falls behind loses the event or its stream instead.
+pub struct EventBus {
+    subscribers: RwLock<Vec<(Subscriber, mpsc::Sender<ServerIPCData>)>>,
+    queue: mpsc::UnboundedSender<ServerIPCData>,
+    capacity: usize,
+}
+
+impl EventBus {
+    /// Creates the bus and starts its forwarding task, which stops once the
+    /// bus is dropped.
+    pub fn new(capacity: usize) -> Arc<Self> {
+        let (queue, mut events) = mpsc::unbounded_channel();
+        let bus = Arc::new(EventBus {
+            subscribers: RwLock::new(Vec::new()),
+            queue,
+            capacity: capacity.max(1),
+        });
+
+        let forwarder = Arc::downgrade(&bus);
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                let Some(bus) = forwarder.upgrade() else {
+                    break;
+                };
+                bus.deliver(event).await;
+            }
+        });
+
+        bus
+    }
+
+    /// Receives the events published from now on. Dropping the receiver
+    /// unsubscribes.
+    pub fn subscribe(&self, subscriber: Subscriber) -> mpsc::Receiver<ServerIPCData> {
+        let (sender, receiver) = mpsc::channel(self.capacity);
+        self.subscribers.write().unwrap().push((subscriber, sender));
+        receiver
+    }
+
+    pub fn publish(&self, event: ServerIPCData) {
+        // The forwarding task only stops once the bus itself is gone.
+        let _ = self.queue.send(event);
+    }
+
+    async fn deliver(&self, event: ServerIPCData) {
+        let subscribers = self.subscribers.read().unwrap().clone();
+        for (subscriber, sender) in subscribers {
+            let delivered = match sender.try_send(event.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(event)) => match subscriber.on_full() {
+                    Overflow::Wait => {
+                        metrics::EVENT_BUS_WAITS
+                            .with_label_values(&[subscriber.label()])
+                            .inc();
+                        sender.send(event).await.is_ok()
+                    }
+                    Overflow::Drop => {
+                        metrics::EVENT_BUS_DROPS
+                            .with_label_values(&[subscriber.label()])
+                            .inc();
+                        true
+                    }
+                    Overflow::Disconnect => {
+                        metrics::EVENT_BUS_DROPS
+                            .with_label_values(&[subscriber.label()])
+                            .inc();
+                        warn!("Disconnecting a {} subscriber that fell behind", subscriber.label());
+                        false
+                    }
+                },
+                Err(TrySendError::Closed(_)) => false,
+            };
+            if !delivered {
+                self.subscribers
+                    .write()
+                    .unwrap()
+                    .retain(|(_, other)| !other.same_channel(&sender));
+            }
+        }
+    }
+}
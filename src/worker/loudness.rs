@@ -0,0 +1,351 @@
+use anyhow::{bail, Context, Result};
+use log::debug;
+use reqwest::Client;
+use songbird::input::codecs::{get_codec_registry, get_probe};
+use songbird::input::Compose;
+use std::f64::consts::PI;
+use std::time::Instant;
+use symphonia_core::audio::SampleBuffer;
+use symphonia_core::codecs::DecoderOptions;
+use symphonia_core::formats::FormatOptions;
+use symphonia_core::io::MediaSourceStream;
+use symphonia_core::meta::{MetadataOptions, MetadataRevision, StandardTagKey};
+use symphonia_core::probe::Hint;
+
+use crate::utils::config::CONFIG;
+use crate::worker::track_cache::ResolvedTrack;
+use crate::utils::constants::{MAX_LOUDNESS_GAIN_DB, R128_REFERENCE_LUFS, REPLAYGAIN_REFERENCE_LUFS};
+
+// EBU R128 gating thresholds in LUFS / LU.
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Second-order IIR section in direct form I.
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Biquad { b, a, x: [0.0; 2], y: [0.0; 2] }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[1] * self.y[0]
+            - self.a[2] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+/// ITU-R BS.1770 K-weighting: a high-shelf pre-filter followed by a high-pass,
+/// with coefficients derived for arbitrary sample rates.
+struct KWeighting {
+    shelf: Biquad,
+    highpass: Biquad,
+}
+
+impl KWeighting {
+    fn new(rate: u32) -> Self {
+        let rate = rate as f64;
+
+        let f0 = 1681.974450955533;
+        let gain = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let highpass = Biquad::new(
+            [1.0, -2.0, 1.0],
+            [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        KWeighting { shelf, highpass }
+    }
+
+    fn process(&mut self, sample: f64) -> f64 {
+        self.highpass.process(self.shelf.process(sample))
+    }
+}
+
+/// Accumulates K-weighted energy in 100ms segments so that 400ms gating blocks
+/// with 75% overlap can be formed from four consecutive segments.
+struct LoudnessMeter {
+    filters: Vec<KWeighting>,
+    segment_len: usize,
+    segment_pos: usize,
+    segment_energy: f64,
+    segments: Vec<f64>,
+}
+
+impl LoudnessMeter {
+    fn new(rate: u32, channels: usize) -> Self {
+        LoudnessMeter {
+            filters: (0..channels).map(|_| KWeighting::new(rate)).collect(),
+            segment_len: (rate as usize / 10).max(1),
+            segment_pos: 0,
+            segment_energy: 0.0,
+            segments: Vec::new(),
+        }
+    }
+
+    fn push_interleaved(&mut self, samples: &[f32]) {
+        let channels = self.filters.len();
+        for frame in samples.chunks_exact(channels) {
+            for (filter, sample) in self.filters.iter_mut().zip(frame) {
+                let weighted = filter.process(*sample as f64);
+                self.segment_energy += weighted * weighted;
+            }
+            self.segment_pos += 1;
+            if self.segment_pos == self.segment_len {
+                self.segments.push(self.segment_energy / self.segment_len as f64);
+                self.segment_pos = 0;
+                self.segment_energy = 0.0;
+            }
+        }
+    }
+
+    fn duration_seconds(&self) -> f64 {
+        self.segments.len() as f64 / 10.0
+    }
+
+    fn integrated_loudness(&self) -> Option<f64> {
+        let blocks: Vec<f64> = self
+            .segments
+            .windows(4)
+            .map(|w| w.iter().sum::<f64>() / 4.0)
+            .filter(|z| block_loudness(*z) > ABSOLUTE_GATE)
+            .collect();
+
+        if blocks.is_empty() {
+            return None;
+        }
+
+        let relative_gate = block_loudness(blocks.iter().sum::<f64>() / blocks.len() as f64) + RELATIVE_GATE;
+        let gated: Vec<f64> = blocks
+            .into_iter()
+            .filter(|z| block_loudness(*z) > relative_gate)
+            .collect();
+
+        if gated.is_empty() {
+            return None;
+        }
+
+        Some(block_loudness(gated.iter().sum::<f64>() / gated.len() as f64))
+    }
+}
+
+fn block_loudness(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+fn parse_gain_db(value: &str) -> Option<f32> {
+    value
+        .trim()
+        .trim_end_matches(|c: char| c.is_alphabetic() || c.is_whitespace())
+        .parse()
+        .ok()
+}
+
+/// Returns the loudness of the track in LUFS as implied by ReplayGain or
+/// Opus R128 tags, if any are present.
+fn loudness_from_tags(revision: &MetadataRevision) -> Option<f32> {
+    for tag in revision.tags() {
+        if tag.std_key == Some(StandardTagKey::ReplayGainTrackGain) {
+            if let Some(gain) = parse_gain_db(&tag.value.to_string()) {
+                return Some(REPLAYGAIN_REFERENCE_LUFS - gain);
+            }
+        }
+        if tag.key.eq_ignore_ascii_case("R128_TRACK_GAIN") {
+            // Q7.8 fixed point relative to -23 LUFS.
+            if let Ok(gain) = tag.value.to_string().trim().parse::<i32>() {
+                return Some(R128_REFERENCE_LUFS - gain as f32 / 256.0);
+            }
+        }
+    }
+    None
+}
+
+/// Runs on a blocking thread, which outlives the caller's timeout; checking
+/// `deadline` between packets stops it from decoding the rest of the stream.
+fn measure(stream: MediaSourceStream, hint: Hint, max_seconds: u64, deadline: Instant) -> Result<f32> {
+    let mut probed = get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .context("Failed to probe track for loudness analysis")?;
+
+    if let Some(loudness) = probed.metadata.get().as_ref().and_then(|m| m.current()).and_then(loudness_from_tags) {
+        debug!("Using tagged loudness: {} LUFS", loudness);
+        return Ok(loudness);
+    }
+
+    let mut format = probed.format;
+
+    if let Some(loudness) = format.metadata().current().and_then(loudness_from_tags) {
+        debug!("Using tagged loudness: {} LUFS", loudness);
+        return Ok(loudness);
+    }
+
+    let track = format
+        .default_track()
+        .context("Track has no decodable audio stream")?;
+    let track_id = track.id;
+    let mut decoder = get_codec_registry()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .context("Unsupported codec for loudness analysis")?;
+
+    let mut meter: Option<LoudnessMeter> = None;
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+
+    while let Ok(packet) = format.next_packet() {
+        if Instant::now() >= deadline {
+            bail!("Loudness analysis ran past its deadline");
+        }
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(_) => continue,
+        };
+
+        let spec = *decoded.spec();
+        let buf = buffer.get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, spec));
+        if buf.capacity() < decoded.capacity() * spec.channels.count() {
+            *buf = SampleBuffer::new(decoded.capacity() as u64, spec);
+        }
+        buf.copy_interleaved_ref(decoded);
+
+        let meter = meter.get_or_insert_with(|| LoudnessMeter::new(spec.rate, spec.channels.count()));
+        meter.push_interleaved(buf.samples());
+
+        if meter.duration_seconds() >= max_seconds as f64 {
+            break;
+        }
+    }
+
+    let loudness = meter
+        .and_then(|m| m.integrated_loudness())
+        .context("Track is silent or too short to measure")?;
+
+    Ok(loudness as f32)
+}
+
+/// Converts a measured loudness into the linear gain needed to reach the
+/// configured target, limited to avoid boosting quiet tracks into clipping.
+pub fn gain_for_loudness(loudness: f32) -> f32 {
+    let gain_db = (CONFIG.audio.loudness_target_lufs - loudness)
+        .clamp(-MAX_LOUDNESS_GAIN_DB, MAX_LOUDNESS_GAIN_DB);
+    10f32.powf(gain_db / 20.0)
+}
+
+/// Measures the loudness of an audio source, preferring ReplayGain/R128 tags
+/// and otherwise decoding the first few seconds of audio, and returns the
+/// linear gain that brings it to the target loudness. Decoding gives up once
+/// `deadline` has passed.
+pub async fn analyze<C: Compose>(mut source: C, deadline: Instant) -> Result<f32> {
+    let stream = source
+        .create_async()
+        .await
+        .context("Failed to open track for loudness analysis")?;
+
+    let hint = stream.hint.unwrap_or_default();
+    let stream = MediaSourceStream::new(stream.input, Default::default());
+    let max_seconds = CONFIG.audio.loudness_analysis_seconds;
+
+    let loudness = tokio::task::spawn_blocking(move || measure(stream, hint, max_seconds, deadline)).await??;
+    debug!("Measured loudness: {} LUFS", loudness);
+
+    Ok(gain_for_loudness(loudness))
+}
+
+/// Analyzes a track streamed from its resolved URL.
+pub async fn analyze_resolved(client: Client, track: &ResolvedTrack, deadline: Instant) -> Result<f32> {
+    if track.protocol.as_deref() == Some("m3u8_native") {
+        bail!("Loudness analysis is not supported for HLS streams");
+    }
+    analyze(track.http_request(client), deadline).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48000;
+
+    /// A 1 kHz sine measures 3.01 LU below its peak level in dBFS.
+    fn sine(loudness: f64, seconds: usize) -> Vec<f32> {
+        let amplitude = 10f64.powf((loudness + 3.0103) / 20.0);
+        (0..RATE as usize * seconds)
+            .map(|i| (amplitude * (2.0 * PI * 1000.0 * i as f64 / RATE as f64).sin()) as f32)
+            .collect()
+    }
+
+    fn measure_samples(samples: &[f32]) -> Option<f64> {
+        let mut meter = LoudnessMeter::new(RATE, 1);
+        meter.push_interleaved(samples);
+        meter.integrated_loudness()
+    }
+
+    #[test]
+    fn k_weighting_matches_bs1770_coefficients_at_48k() {
+        let filter = KWeighting::new(RATE);
+        let expected_shelf_b = [1.53512485958697, -2.69169618940638, 1.19839281085285];
+        let expected_shelf_a = [1.0, -1.69065929318241, 0.73248077421585];
+        let expected_highpass_a = [1.0, -1.99004745483398, 0.99007225036621];
+        for (actual, expected) in filter.shelf.b.iter().zip(expected_shelf_b) {
+            assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+        }
+        for (actual, expected) in filter.shelf.a.iter().zip(expected_shelf_a) {
+            assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+        }
+        for (actual, expected) in filter.highpass.a.iter().zip(expected_highpass_a) {
+            assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn sine_at_minus_23_lufs_measures_minus_23() {
+        let loudness = measure_samples(&sine(-23.0, 5)).unwrap();
+        assert!((loudness + 23.0).abs() < 0.1, "measured {}", loudness);
+    }
+
+    #[test]
+    fn relative_gate_drops_quiet_passages() {
+        let mut samples = sine(-23.0, 5);
+        samples.extend(sine(-60.0, 5));
+        let loudness = measure_samples(&samples).unwrap();
+        assert!((loudness + 23.0).abs() < 0.2, "measured {}", loudness);
+    }
+
+    #[test]
+    fn silence_is_below_the_absolute_gate() {
+        assert_eq!(measure_samples(&vec![0.0; RATE as usize * 2]), None);
+    }
+
+    #[test]
+    fn parses_replaygain_values() {
+        assert_eq!(parse_gain_db("-6.50 dB"), Some(-6.5));
+        assert_eq!(parse_gain_db("+1.2 dB"), Some(1.2));
+        assert_eq!(parse_gain_db("loud"), None);
+    }
+}
@@ -1,4 +1,12 @@
 pub mod types;
 pub mod connector;
 pub mod pool;
-pub mod commands;
\ No newline at end of file
+pub mod dispatcher;
+pub mod commands;
+pub mod loudness;
+pub mod playlist;
+pub mod track_cache;
+pub mod disk_cache;
+pub mod drain;
+pub mod events;
+pub mod webhooks;
\ No newline at end of file
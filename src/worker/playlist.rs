@@ -0,0 +1,120 @@
+use anyhow::{anyhow, Context, Result};
+use opentelemetry::KeyValue;
+use reqwest::Url;
+use serde_derive::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+
+use crate::monitoring::{metrics, telemetry};
+use crate::utils::config::CONFIG;
+use crate::worker::types::{QueuedTrack, TrackSource};
+
+#[derive(Deserialize)]
+struct FlatEntry {
+    url: Option<String>,
+    webpage_url: Option<String>,
+    title: Option<String>,
+    uploader: Option<String>,
+    duration: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct FlatPlaylist {
+    #[serde(default)]
+    entries: Vec<FlatEntry>,
+}
+
+/// Detects YouTube playlists and SoundCloud sets, which would otherwise be
+/// handed to a single `YoutubeDl` input. A YouTube video opened from a
+/// playlist (`watch?v=...&list=...`) queues the whole playlist.
+pub fn is_playlist_url(link: &str) -> bool {
+    let Ok(url) = Url::parse(link) else {
+        return false;
+    };
+
+    match url.host_str() {
+        Some("www.youtube.com" | "youtube.com" | "m.youtube.com" | "music.youtube.com") => {
+            matches!(url.path(), "/playlist" | "/watch")
+                && url.query_pairs().any(|(key, value)| key == "list" && !value.is_empty())
+        }
+        Some("youtu.be") => url.query_pairs().any(|(key, value)| key == "list" && !value.is_empty()),
+        Some("soundcloud.com" | "www.soundcloud.com" | "m.soundcloud.com") => url
+            .path_segments()
+            .map(|mut segments| segments.nth(1) == Some("sets"))
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Lists the entries of a remote playlist with yt-dlp's flat-playlist mode,
+/// which does not resolve the individual streams. Each entry is resolved
+/// lazily when it reaches the front of the queue.
+pub async fn expand(url: &str) -> Result<Vec<QueuedTrack>> {
+    telemetry::in_span(
+        "yt-dlp expand_playlist",
+        vec![KeyValue::new("ravalink.query", url.to_string())],
+        run_flat_playlist(url),
+    )
+    .await
+}
+
+async fn run_flat_playlist(url: &str) -> Result<Vec<QueuedTrack>> {
+    let limit = CONFIG.config.playlist_max_entries.to_string();
+    let started = Instant::now();
+    let output = Command::new("yt-dlp")
+        .args(["--flat-playlist", "-J", "--playlist-end", &limit, "--", url])
+        .output()
+        .await;
+    metrics::record_ytdlp(
+        "expand_playlist",
+        started,
+        output.as_ref().is_ok_and(|output| output.status.success()),
+    );
+    let output = output.context("Failed to run yt-dlp")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "yt-dlp failed to expand playlist: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let playlist: FlatPlaylist = serde_json::from_slice(&output.stdout)
+        .context("Failed to parse yt-dlp playlist output")?;
+
+    Ok(playlist
+        .entries
+        .into_iter()
+        .filter_map(|entry| {
+            Some(QueuedTrack {
+                source: TrackSource::Url(entry.url.or(entry.webpage_url)?),
+                title: entry.title,
+                artist: entry.uploader,
+                isrc: None,
+                duration: entry.duration.map(Duration::from_secs_f64),
+            })
+        })
+        .take(CONFIG.config.playlist_max_entries)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_playlist_links() {
+        assert!(is_playlist_url("https://www.youtube.com/playlist?list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI"));
+        assert!(is_playlist_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI"));
+        assert!(is_playlist_url("https://youtu.be/dQw4w9WgXcQ?list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI"));
+        assert!(is_playlist_url("https://soundcloud.com/artist/sets/album"));
+    }
+
+    #[test]
+    fn leaves_single_tracks_alone() {
+        assert!(!is_playlist_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ"));
+        assert!(!is_playlist_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ&list="));
+        assert!(!is_playlist_url("https://soundcloud.com/artist/track"));
+        assert!(!is_playlist_url("never gonna give you up"));
+    }
+}
@@ -1,54 +1,89 @@
-use songbird::tracks::TrackHandle;
 use songbird::Songbird;
-use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio::sync::mpsc;
+use tokio::time::{interval, Duration};
+use std::collections::HashMap;
+use std::num::NonZero;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use anyhow::Result;
-use ravalink_interconnect::protocol::{Command, Event, EventType, Message, Response, ResponseType};
-use log::{info, error, debug};
-use rdkafka::producer::FutureProducer;
+use opentelemetry::trace::{FutureExt, SpanKind, TraceContextExt};
+use opentelemetry::{Context, KeyValue};
+use ravalink_interconnect::protocol::{Command, Event, EventType, Message, Request, Response, ResponseType};
+use log::{info, error, debug, warn};
+use crate::monitoring::{metrics, telemetry};
+use crate::transport::{InboundMessage, Transport};
 use crate::utils::config::CONFIG;
+use crate::utils::constants::GUILD_LANE_SWEEP_SECONDS;
 use crate::utils::helpers::get_timestamp;
-use crate::worker::connector::send_message;
-use crate::worker::commands::{connect, stop, play};
-use tokio::sync::broadcast::{Sender, Receiver};
+use crate::utils::logger::LogContext;
+use crate::worker::connector::{send_message, send_message_detached};
+use crate::worker::commands::{connect, stop, play, volume, pause, resume, skip, seek, shuffle, r#loop};
 use reqwest::Client as HttpClient;
 
+use super::events::EventBus;
 use super::types::{ServerEventType, ServerIPC, ServerIPCData, ServerMessage};
 
+type Job = (Message, Option<InboundMessage>, Arc<dyn Transport>, Option<Arc<Songbird>>, Context);
+
 pub struct WorkerPool {
-    job_sender: mpsc::Sender<(Message, Arc<Mutex<FutureProducer>>, Option<Arc<Songbird>>)>,
+    job_sender: mpsc::Sender<Job>,
     
 }
 
+/// A job on a guild's lane, or a request the lane turns down in its place so
+/// the failure still goes out after the responses to earlier jobs.
+enum LaneJob {
+    Run(Job),
+    Reject(Job, String),
+}
+
+/// Runs one guild's jobs one after another, in the order they arrived.
+struct GuildLane {
+    sender: mpsc::UnboundedSender<LaneJob>,
+    /// Jobs queued on the lane or running. Only the pool's dispatch loop
+    /// adds to it, so a lane seen at zero there is idle and can be dropped.
+    pending: Arc<AtomicUsize>,
+}
+
+impl GuildLane {
+    fn start(ipc: Arc<EventBus>) -> Self {
+        let (sender, mut inbox) = mpsc::unbounded_channel::<LaneJob>();
+        let pending = Arc::new(AtomicUsize::new(0));
+
+        let remaining = pending.clone();
+        tokio::spawn(async move {
+            while let Some(job) = inbox.recv().await {
+                match job {
+                    LaneJob::Run((job, delivery, transport, manager, trace)) => {
+                        WorkerPool::run_job(job, delivery, transport, manager, ipc.clone(), trace).await
+                    }
+                    LaneJob::Reject(job, reason) => WorkerPool::reject(job, reason).await,
+                }
+                remaining.fetch_sub(1, Ordering::SeqCst);
+            }
+        });
+
+        GuildLane { sender, pending }
+    }
+}
+
 impl WorkerPool {
     pub fn new(ipc: &mut ServerIPC) -> Self {
-        let (tx, mut rx) = mpsc::channel::<(Message, Arc<Mutex<FutureProducer>>, Option<Arc<Songbird>>)>(100);
+        let (tx, mut rx) = mpsc::channel::<Job>(100);
 
-        let sender = ipc.sender.clone();
-        let mut receiver = ipc.sender.subscribe();
+        let bus = ipc.bus.clone();
 
         tokio::spawn(async move {
+            let mut lanes: HashMap<NonZero<u64>, GuildLane> = HashMap::new();
+            let mut sweep = interval(Duration::from_secs(GUILD_LANE_SWEEP_SECONDS));
+
             loop {
- 
                 tokio::select! {
-                   
-                    event = receiver.recv() => {
-                        match event {
-                            Ok(event) => {
-                                if let Some(producer) = event.producer.clone() {
-                                    tokio::spawn(Self::process_ipc(event.clone(), producer));
-                                } else {
-                                    error!("Received event without a valid producer: {:?}", event);
-                                }
-                                debug!("Received event: {:?}", event);
-                            },
-                            Err(e) => {
-                                error!("Failed to receive event: {:?}", e);
-                            }
-                        }
+                    Some(job) = rx.recv() => {
+                        Self::route_job(job, &mut lanes, &bus);
                     },
-                    Some((job, producer, manager)) = rx.recv() => {
-                        tokio::spawn(Self::process_job(job, producer, manager, sender.clone()));
+                    _ = sweep.tick() => {
+                        lanes.retain(|_, lane| lane.pending.load(Ordering::SeqCst) > 0);
                     },
                     else => {
                         error!("Job channel closed");
@@ -62,73 +97,196 @@ impl WorkerPool {
     }
 
 
-    pub async fn send_job(&self, job: Message, producer: Arc<Mutex<FutureProducer>>, manager: Option<Arc<Songbird>>) -> Result<()> {
-        self.job_sender.send((job, producer, manager)).await.map_err(|e| {
+    /// Queues a job. `delivery` is the transport message it was read from,
+    /// acknowledged once the job has finished. The job's spans continue the
+    /// caller's current trace.
+    pub async fn send_job(&self, job: Message, delivery: Option<InboundMessage>, transport: Arc<dyn Transport>, manager: Option<Arc<Songbird>>) -> Result<()> {
+        self.job_sender.send((job, delivery, transport, manager, Context::current())).await.map_err(|e| {
             error!("Failed to send job to worker pool: {}", e);
             anyhow::anyhow!("Failed to send job")
         })
     }
 
-    async fn process_job(job: Message, producer: Arc<Mutex<FutureProducer>>, manager: Option<Arc<Songbird>>, ipc: Arc<Sender<ServerIPCData>>) {
+    /// Requests go to their guild's lane so a guild's commands run in order,
+    /// while different guilds run in parallel. Anything else runs at once.
+    /// Requests older than `JOB_EXPIRATION_TIME_SECONDS` are rejected, so a
+    /// redelivered backlog never replays stale commands against live guilds.
+    fn route_job(job: Job, lanes: &mut HashMap<NonZero<u64>, GuildLane>, ipc: &Arc<EventBus>) {
+        let (guild_id, age) = match &job.0 {
+            Message::Request(request) => (request.guild_id, get_timestamp().saturating_sub(request.timestamp)),
+            _ => {
+                let (job, delivery, transport, manager, trace) = job;
+                tokio::spawn(Self::run_job(job, delivery, transport, manager, ipc.clone(), trace));
+                return;
+            }
+        };
+
+        // Rejections are queued on the lane too, so they go out in order. They
+        // count as pending while queued, which keeps the lane from being swept.
+        let lane = lanes.entry(guild_id).or_insert_with(|| GuildLane::start(ipc.clone()));
+        let pending = lane.pending.load(Ordering::SeqCst);
+        let job = if age > CONFIG.config.job_expiration_time_seconds {
+            LaneJob::Reject(job, format!("expired: request is {} seconds old", age))
+        } else if pending >= CONFIG.config.guild_job_backlog {
+            LaneJob::Reject(job, format!("busy: guild {} already has {} jobs waiting", guild_id, pending))
+        } else {
+            LaneJob::Run(job)
+        };
+
+        lane.pending.fetch_add(1, Ordering::SeqCst);
+        if let Err(mpsc::error::SendError(job)) = lane.sender.send(job) {
+            // The lane task is gone, which only happens if it panicked.
+            error!("Job lane for guild {} stopped, starting a new one", guild_id);
+            let lane = GuildLane::start(ipc.clone());
+            lane.pending.fetch_add(1, Ordering::SeqCst);
+            let _ = lane.sender.send(job);
+            lanes.insert(guild_id, lane);
+        }
+    }
+
+    /// Fails a request without running it, because it expired or its guild's
+    /// backlog is full, and acknowledges it so it is not redelivered.
+    async fn reject(job: Job, reason: String) {
+        let (message, delivery, transport, _, _) = job;
+        if let Message::Request(request) = &message {
+            let command = metrics::command_name(&request.command);
+            let context = LogContext::job(&request.job_id, request.guild_id, Some(command));
+            context.scope(async {
+                warn!("Rejecting job: {}", reason);
+                Self::send_job_response(command, Message::Response(Response {
+                    job_id: request.job_id.clone(),
+                    guild_id: request.guild_id,
+                    response_type: ResponseType::Failure {
+                        reason: reason.clone(),
+                    },
+                    timestamp: request.timestamp,
+                }), transport.clone()).await;
+            }).await;
+        }
+
+        if let Some(delivery) = delivery {
+            if let Err(e) = transport.ack(&delivery).await {
+                error!("Failed to acknowledge message: {}", e);
+            }
+        }
+    }
+
+    /// Acknowledges the delivery after the job, including a panicked one, so
+    /// a single bad message cannot hold back its partition.
+    async fn run_job(job: Message, delivery: Option<InboundMessage>, transport: Arc<dyn Transport>, manager: Option<Arc<Songbird>>, ipc: Arc<EventBus>, trace: Context) {
+        let (command, context, attributes) = match &job {
+            Message::Request(request) => {
+                let command = metrics::command_name(&request.command);
+                let attributes = vec![
+                    KeyValue::new("ravalink.job_id", request.job_id.clone()),
+                    KeyValue::new("ravalink.guild_id", request.guild_id.get().to_string()),
+                    KeyValue::new("ravalink.command", command),
+                ];
+                (Some(command), LogContext::job(&request.job_id, request.guild_id, Some(command)), attributes)
+            }
+            _ => (None, LogContext::default(), Vec::new()),
+        };
+
+        let dispatch = trace.with_span(telemetry::start_span("dispatch", SpanKind::Internal, attributes.clone(), &trace));
+        let handler_name = match command {
+            Some(command) => format!("command {}", command),
+            None => "message".to_string(),
+        };
+        let handler = dispatch.with_span(telemetry::start_span(handler_name, SpanKind::Internal, attributes, &dispatch));
+
+        let job = context.scope(Self::process_job(job, transport.clone(), manager, ipc)).with_context(handler.clone());
+        let outcome = tokio::spawn(job).await.map_err(anyhow::Error::from);
+        telemetry::record_result(&handler, &outcome);
+        if let Err(e) = outcome {
+            error!("Job failed to complete: {:?}", e);
+            if let Some(command) = command {
+                metrics::JOBS.with_label_values(&[command, "panicked"]).inc();
+            }
+        }
+
+        if let Some(delivery) = delivery {
+            if let Err(e) = transport.ack(&delivery).await {
+                error!("Failed to acknowledge message: {}", e);
+            }
+        }
+        dispatch.span().end();
+    }
+
+    async fn process_job(job: Message, transport: Arc<dyn Transport>, manager: Option<Arc<Songbird>>, ipc: Arc<EventBus>) {
 
         let client = HttpClient::new();
-        let mut track: Option<TrackHandle> = None;
 
         match job {
             Message::Request(request) => {
+                let command = metrics::command_name(&request.command);
                 match request.command {
                     Command::Connect => {
                         if let Some(manager) = manager {
-                            if let Err(e) = connect::run(&request, &mut Some(manager), ipc, producer.clone()).await {
+                            if let Err(e) = connect::run(&request, &mut Some(manager), ipc, transport.clone()).await {
                                 error!("Failed to connect to voice channel: {:?}", e);
-                                Self::send_response(Message::Response(Response {
+                                Self::send_job_response(command, Message::Response(Response {
                                     job_id: request.job_id.clone(),
                                     guild_id: request.guild_id.clone(),
                                     response_type: ResponseType::Failure { reason: (e.to_string()) },
                                     timestamp: request.timestamp,
-                                }), producer).await;
+                                }), transport).await;
                             } else {
-                                Self::send_response(Message::Response(Response {
+                                Self::send_job_response(command, Message::Response(Response {
                                     job_id: request.job_id.clone(),
                                     guild_id: request.guild_id.clone(),
                                     response_type: ResponseType::Success,
                                     timestamp: request.timestamp,
-                                }), producer).await;
+                                }), transport).await;
                             }
                         }
                     }
                     Command::Search { query } => {
                         info!("Searching for: {}", query);
                         let search_results = vec![];
-                        Self::send_response(Message::Response(Response {
+                        Self::send_job_response(command, Message::Response(Response {
                             job_id: request.job_id.clone(),
                             guild_id: request.guild_id.clone(),
                             response_type: ResponseType::SearchResults { tracks: search_results },
                             timestamp: request.timestamp,
-                        }), producer).await;
+                        }), transport).await;
                     }
 
                     Command::Play { ref url } => {
                         if let Some(manager) = manager {
+                            if let Err(e) = connect::join_if_needed(&request, &manager, ipc, transport.clone()).await {
+                                error!("Failed to join voice channel before playing: {:?}", e);
+                                Self::send_job_response(command, Message::Response(Response {
+                                    job_id: request.job_id.clone(),
+                                    guild_id: request.guild_id.clone(),
+                                    response_type: ResponseType::Failure { reason: format!("Failed to join voice channel: {}", e) },
+                                    timestamp: request.timestamp,
+                                }), transport).await;
+                                return;
+                            }
                             match play::run(&request, &mut Some(manager), client.clone(), url.clone()).await {
-                                Ok(track_handle) => {
-                                    track = Some(track_handle);
-                    
-                                    Self::send_response(Message::Response(Response {
+                                Ok(summary) => {
+                                    let response_type = match summary {
+                                        Some(summary) => ResponseType::TracksQueued {
+                                            count: summary.count,
+                                            total_duration: summary.duration.as_secs(),
+                                        },
+                                        None => ResponseType::Success,
+                                    };
+                                    Self::send_job_response(command, Message::Response(Response {
                                         job_id: request.job_id.clone(),
                                         guild_id: request.guild_id.clone(),
-                                        response_type: ResponseType::Success,
+                                        response_type,
                                         timestamp: request.timestamp,
-                                    }), producer).await;
+                                    }), transport).await;
                                 }
                                 Err(e) => {
                                     error!("Failed to play track: {:?}", e);
-                                    Self::send_response(Message::Response(Response {
+                                    Self::send_job_response(command, Message::Response(Response {
                                         job_id: request.job_id.clone(),
                                         guild_id: request.guild_id.clone(),
                                         response_type: ResponseType::Failure { reason: (e.to_string()) },
                                         timestamp: request.timestamp,
-                                    }), producer).await;
+                                    }), transport).await;
                                 }
                             }
                         }
@@ -137,38 +295,83 @@ impl WorkerPool {
                         if let Some(manager) = manager {
                             if let Err(e) = stop::run(&request, &mut Some(manager)).await {
                                 error!("Failed to stop playback: {:?}", e);
-                                Self::send_response(Message::Response(Response {
+                                Self::send_job_response(command, Message::Response(Response {
                                     job_id: request.job_id.clone(),
                                     guild_id: request.guild_id.clone(),
                                     response_type: ResponseType::Failure { reason: (e.to_string()) },
                                     timestamp: request.timestamp,
-                                }), producer).await;
+                                }), transport).await;
                             } else {
-                                Self::send_response(Message::Response(Response {
+                                Self::send_job_response(command, Message::Response(Response {
                                     job_id: request.job_id.clone(),
                                     guild_id: request.guild_id.clone(),
                                     response_type: ResponseType::Success,
                                     timestamp: request.timestamp,
-                                }), producer).await;
+                                }), transport).await;
                             }
                         }
                     },
-                    Command::Pause => todo!(),
-                    Command::Resume => todo!(),
-                    Command::SeekToPosition { position } => todo!(),
-                    Command::SetVolume { volume } => todo!(),
-                    Command::GetPlaylists => todo!(),
-                    Command::AddToPlaylist { playlist_id, track } => todo!(),
-                    Command::RemoveFromPlaylist { playlist_id, track } => todo!(),
-                    Command::LoadPlaylist { playlist_id } => todo!(),
-                    Command::ClearPlaylist { playlist_id } => todo!(),
-                    Command::ShuffleQueue => todo!(),
-                    Command::Skip => todo!(),
-                    Command::Loop => todo!(),
+                    Command::Pause => {
+                        let outcome = pause::run(&request).await;
+                        Self::send_outcome(command, &request, outcome, transport).await;
+                    }
+                    Command::Resume => {
+                        let outcome = resume::run(&request).await;
+                        Self::send_outcome(command, &request, outcome, transport).await;
+                    }
+                    Command::SeekToPosition { position } => {
+                        let outcome = seek::run(&request, position).await;
+                        Self::send_outcome(command, &request, outcome, transport).await;
+                    }
+                    Command::SetVolume { volume: level } => {
+                        if let Err(e) = volume::run(&request, level as f32).await {
+                            error!("Failed to set volume: {:?}", e);
+                            Self::send_job_response(command, Message::Response(Response {
+                                job_id: request.job_id.clone(),
+                                guild_id: request.guild_id.clone(),
+                                response_type: ResponseType::Failure { reason: (e.to_string()) },
+                                timestamp: request.timestamp,
+                            }), transport).await;
+                        } else {
+                            Self::send_job_response(command, Message::Response(Response {
+                                job_id: request.job_id.clone(),
+                                guild_id: request.guild_id.clone(),
+                                response_type: ResponseType::Success,
+                                timestamp: request.timestamp,
+                            }), transport).await;
+                        }
+                    },
+                    Command::GetPlaylists
+                    | Command::AddToPlaylist { .. }
+                    | Command::RemoveFromPlaylist { .. }
+                    | Command::LoadPlaylist { .. }
+                    | Command::ClearPlaylist { .. } => {
+                        warn!("Rejecting unsupported command {}", command);
+                        Self::send_job_response(command, Message::Response(Response {
+                            job_id: request.job_id.clone(),
+                            guild_id: request.guild_id,
+                            response_type: ResponseType::Failure {
+                                reason: format!("unsupported: {} is not implemented by this worker", command),
+                            },
+                            timestamp: request.timestamp,
+                        }), transport).await;
+                    }
+                    Command::ShuffleQueue => {
+                        let outcome = shuffle::run(&request).await;
+                        Self::send_outcome(command, &request, outcome, transport).await;
+                    }
+                    Command::Skip => {
+                        let outcome = skip::run(&request).await;
+                        Self::send_outcome(command, &request, outcome, transport).await;
+                    }
+                    Command::Loop => {
+                        let outcome = r#loop::run(&request).await;
+                        Self::send_outcome(command, &request, outcome, transport).await;
+                    }
                 }
             }
         Message::Ping { id } => {
-            Self::send_response(Message::Pong { id }, producer).await;
+            Self::send_response(Message::Pong { id }, transport).await;
         }
         Message::Pong { id: _ } => {
             return;
@@ -179,8 +382,26 @@ impl WorkerPool {
         }
     }
 
-    async fn process_ipc(event: ServerIPCData, producer: Arc<Mutex<FutureProducer>>) {
+    /// Publishes worker events on `transport` one at a time and in order,
+    /// skipping those of jobs from the in-process front ends.
+    pub async fn publish_events(mut events: mpsc::Receiver<ServerIPCData>, transport: Arc<dyn Transport>) {
+        while let Some(event) = events.recv().await {
+            debug!("Received event: {:?}", event);
+            if !event.publish {
+                continue;
+            }
+            let context = LogContext::job(&event.job_id, event.guild_id, None);
+            context.scope(Self::process_ipc(event, transport.clone())).await;
+        }
+    }
+
+    async fn process_ipc(event: ServerIPCData, transport: Arc<dyn Transport>) {
         match event.message {
+            ServerMessage::Event(ServerEventType::TrackStarted { track }) => {
+                // The interconnect protocol has no track start event; only
+                // in-process subscribers such as the Lavalink API consume it.
+                debug!("Track {:?} started in guild {}", track.title, event.guild_id);
+            },
             ServerMessage::Event(ServerEventType::TrackError { error }) => {
     
                 Self::send_event(Message::Event(Event{
@@ -188,7 +409,7 @@ impl WorkerPool {
                     job_id: event.job_id.clone(),
                     guild_id: event.guild_id,
                     timestamp: get_timestamp()
-                }), producer).await;
+                }), transport).await;
             },
             ServerMessage::Event(ServerEventType::TrackEnded) => {
                 Self::send_event(Message::Event(Event{
@@ -196,19 +417,228 @@ impl WorkerPool {
                     job_id: event.job_id.clone(),
                     guild_id: event.guild_id,
                     timestamp: get_timestamp()
-                }), producer).await;
+                }), transport).await;
+            },
+            ServerMessage::Event(ServerEventType::ChannelMoved { from, to }) => {
+                // Like track starts, moves have no interconnect event and only
+                // reach in-process subscribers.
+                debug!("Guild {} moved from channel {} to {}", event.guild_id, from, to);
             },
     
         }
     }
 
-    async fn send_response(response: Message, producer: Arc<Mutex<FutureProducer>>) {
-        let mut producer_guard = producer.lock().await;
-        send_message(&response, &CONFIG.kafka.kafka_topic, &mut *producer_guard).await;
+    /// Answers a command that has no result beyond success or failure.
+    async fn send_outcome(command: &str, request: &Request, outcome: Result<()>, transport: Arc<dyn Transport>) {
+        let response_type = match outcome {
+            Ok(()) => ResponseType::Success,
+            Err(e) => {
+                error!("Failed to run {}: {:?}", command, e);
+                ResponseType::Failure { reason: e.to_string() }
+            }
+        };
+        Self::send_job_response(command, Message::Response(Response {
+            job_id: request.job_id.clone(),
+            guild_id: request.guild_id,
+            response_type,
+            timestamp: request.timestamp,
+        }), transport).await;
+    }
+
+    async fn send_job_response(command: &str, response: Message, transport: Arc<dyn Transport>) {
+        if let Message::Response(response) = &response {
+            metrics::record_job(command, &response.response_type, response.timestamp);
+        }
+        Self::send_response(response, transport).await;
+    }
+
+    async fn send_response(response: Message, transport: Arc<dyn Transport>) {
+        send_message(&response, &CONFIG.kafka.kafka_topic, transport.as_ref()).await;
+    }
+
+    /// Events are frequent and nobody waits on them, so they are not held
+    /// up waiting for the broker like responses are.
+    async fn send_event(event: Message, transport: Arc<dyn Transport>) {
+        send_message_detached(&event, &CONFIG.kafka.kafka_topic, transport.as_ref()).await;
     }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::memory::{MemoryHandle, MemoryTransport, Outbound};
+    use crate::transport::reply::ReplyTransport;
+    use ravalink_interconnect::protocol::Request;
+    use std::num::NonZero;
+    use std::sync::Once;
+    use std::time::Duration;
+    use tokio::time::timeout;
 
-    async fn send_event(event: Message, producer: Arc<Mutex<FutureProducer>>) {
-        let mut producer_guard = producer.lock().await;
-        send_message(&event, &CONFIG.kafka.kafka_topic, &mut *producer_guard).await;
+    const TOPIC: &str = "ravalink-test";
+
+    /// Provides the settings `CONFIG` requires before anything loads it.
+    fn configure() {
+        static ENV: Once = Once::new();
+        ENV.call_once(|| {
+            std::env::set_var("DISCORD_BOT_ID", "1");
+            std::env::set_var("DISCORD_BOT_TOKEN", "token");
+            std::env::set_var("KAFKA_URI", "localhost:9092");
+            std::env::set_var("KAFKA_TOPIC", TOPIC);
+        });
     }
-}
\ No newline at end of file
+
+    fn pool() -> WorkerPool {
+        configure();
+        WorkerPool::new(&mut ServerIPC { bus: EventBus::new(16) })
+    }
+
+    fn search(job_id: &str, guild_id: u64) -> Message {
+        Message::Request(Request {
+            job_id: job_id.to_string(),
+            guild_id: NonZero::new(guild_id).unwrap(),
+            voice_channel_id: None,
+            command: Command::Search { query: "never gonna give you up".to_string() },
+            timestamp: get_timestamp(),
+        })
+    }
+
+    /// Takes the next message off the transport and submits it, like the
+    /// consume loop does.
+    async fn submit(pool: &WorkerPool, transport: &Arc<MemoryTransport>) {
+        let delivery = transport.recv().await.unwrap().unwrap();
+        let message = serde_json::from_slice(delivery.payload.as_deref().unwrap()).unwrap();
+        pool.send_job(message, Some(delivery), transport.clone(), None).await.unwrap();
+    }
+
+    async fn next_outbound(handle: &MemoryHandle) -> Outbound {
+        timeout(Duration::from_secs(5), handle.next_outbound())
+            .await
+            .expect("the worker did nothing")
+            .expect("the transport closed")
+    }
+
+    async fn next_response(handle: &MemoryHandle) -> Response {
+        let sent = timeout(Duration::from_secs(5), handle.next_sent())
+            .await
+            .expect("the worker sent nothing")
+            .expect("the transport closed");
+        match sent {
+            (topic, Message::Response(response)) => {
+                assert_eq!(topic, TOPIC);
+                response
+            }
+            _ => panic!("expected a response"),
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_a_request_on_the_topic() {
+        let pool = pool();
+        let (transport, handle) = MemoryTransport::new(TOPIC);
+        let transport = Arc::new(transport);
+
+        handle.publish(&search("job", 1)).await.unwrap();
+        submit(&pool, &transport).await;
+
+        let response = next_response(&handle).await;
+        assert_eq!(response.job_id, "job");
+        assert!(matches!(response.response_type, ResponseType::SearchResults { .. }));
+    }
+
+    #[tokio::test]
+    async fn answers_every_guilds_requests() {
+        let pool = pool();
+        let (transport, handle) = MemoryTransport::new(TOPIC);
+        let transport = Arc::new(transport);
+
+        for guild_id in 1..=3 {
+            handle.publish(&search(&guild_id.to_string(), guild_id)).await.unwrap();
+            submit(&pool, &transport).await;
+        }
+
+        let mut job_ids = Vec::new();
+        for _ in 0..3 {
+            job_ids.push(next_response(&handle).await.job_id);
+        }
+        job_ids.sort();
+        assert_eq!(job_ids, vec!["1", "2", "3"]);
+    }
+
+    #[tokio::test]
+    async fn runs_a_guilds_jobs_in_order() {
+        let pool = pool();
+        let (transport, handle) = MemoryTransport::new(TOPIC);
+        let transport = Arc::new(transport);
+
+        for i in 0..20 {
+            handle.publish(&search(&i.to_string(), 1)).await.unwrap();
+            submit(&pool, &transport).await;
+        }
+
+        let mut job_ids = Vec::new();
+        for _ in 0..20 {
+            job_ids.push(next_response(&handle).await.job_id);
+        }
+        assert_eq!(job_ids, (0..20).map(|i| i.to_string()).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn replies_on_the_transport_the_job_came_from() {
+        let pool = pool();
+        let (transport, handle) = MemoryTransport::new(TOPIC);
+        let transport = Arc::new(transport);
+        let replies = Arc::new(ReplyTransport::new());
+        let waiter = replies.register("in-process").await;
+
+        pool.send_job(search("in-process", 3), None, replies.clone(), None).await.unwrap();
+        handle.publish(&search("from-broker", 3)).await.unwrap();
+        submit(&pool, &transport).await;
+
+        let reply = timeout(Duration::from_secs(5), waiter).await.unwrap().unwrap();
+        assert_eq!(reply.job_id, "in-process");
+        assert_eq!(next_response(&handle).await.job_id, "from-broker");
+        assert!(matches!(next_outbound(&handle).await, Outbound::Acked { .. }));
+    }
+
+    #[tokio::test]
+    async fn acknowledges_once_the_job_has_responded() {
+        let pool = pool();
+        let (transport, handle) = MemoryTransport::new(TOPIC);
+        let transport = Arc::new(transport);
+
+        let offset = handle.publish(&search("job", 2)).await.unwrap();
+        submit(&pool, &transport).await;
+
+        match next_outbound(&handle).await {
+            Outbound::Sent { .. } => {}
+            Outbound::Acked { .. } => panic!("acknowledged before responding"),
+        }
+        match next_outbound(&handle).await {
+            Outbound::Acked { offset: acked } => assert_eq!(acked, offset),
+            Outbound::Sent { .. } => panic!("expected the acknowledgement"),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_requests_after_the_guilds_earlier_jobs() {
+        let pool = pool();
+        let (transport, handle) = MemoryTransport::new(TOPIC);
+        let transport = Arc::new(transport);
+
+        handle.publish(&search("fresh", 4)).await.unwrap();
+        submit(&pool, &transport).await;
+        let mut stale = search("stale", 4);
+        if let Message::Request(request) = &mut stale {
+            request.timestamp = 0;
+        }
+        handle.publish(&stale).await.unwrap();
+        submit(&pool, &transport).await;
+
+        assert_eq!(next_response(&handle).await.job_id, "fresh");
+        let rejected = next_response(&handle).await;
+        assert_eq!(rejected.job_id, "stale");
+        assert!(matches!(
+            rejected.response_type,
+            ResponseType::Failure { reason } if reason.starts_with("expired")
+        ));
+    }
+}
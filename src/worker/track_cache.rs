@@ -0,0 +1,410 @@
+use anyhow::{anyhow, Context, Result};
+use log::{debug, error, warn};
+use once_cell::sync::Lazy;
+use opentelemetry::KeyValue;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Url};
+use serde_derive::{Deserialize, Serialize};
+use songbird::input::{HlsRequest, HttpRequest, Input};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+use crate::monitoring::{metrics, telemetry};
+use crate::state::initializer::StateClient;
+use crate::state::manager::State;
+use crate::utils::config::CONFIG;
+use crate::utils::constants::{STREAM_URL_EXPIRY_MARGIN_SECONDS, TRACK_CACHE_KEY_PREFIX};
+use crate::utils::helpers::get_timestamp;
+
+/// The subset of yt-dlp's `-j` output needed to play a track without
+/// invoking yt-dlp again.
+#[derive(Deserialize)]
+struct YtDlpOutput {
+    id: Option<String>,
+    extractor_key: Option<String>,
+    ext: Option<String>,
+    url: String,
+    #[serde(default)]
+    http_headers: HashMap<String, String>,
+    filesize: Option<u64>,
+    protocol: Option<String>,
+    title: Option<String>,
+    artist: Option<String>,
+    uploader: Option<String>,
+    duration: Option<f64>,
+    thumbnail: Option<String>,
+    webpage_url: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResolvedTrack {
+    pub track_id: Option<String>,
+    pub ext: Option<String>,
+    pub stream_url: String,
+    pub http_headers: HashMap<String, String>,
+    pub filesize: Option<u64>,
+    pub protocol: Option<String>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub duration: Option<f64>,
+    pub thumbnail: Option<String>,
+    pub source_url: Option<String>,
+    pub expires_at: u64,
+}
+
+impl ResolvedTrack {
+    pub fn headers(&self) -> HeaderMap {
+        self.http_headers
+            .iter()
+            .filter_map(|(k, v)| {
+                Some((
+                    HeaderName::from_bytes(k.as_bytes()).ok()?,
+                    HeaderValue::from_str(v).ok()?,
+                ))
+            })
+            .collect()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        get_timestamp() >= self.expires_at
+    }
+
+    pub fn http_request(&self, client: Client) -> HttpRequest {
+        HttpRequest {
+            client,
+            request: self.stream_url.clone(),
+            headers: self.headers(),
+            content_length: self.filesize,
+        }
+    }
+
+    /// Builds a Songbird input that streams straight from the resolved URL.
+    pub fn to_input(&self, client: Client) -> Input {
+        match self.protocol.as_deref() {
+            Some("m3u8_native") => {
+                HlsRequest::new_with_headers(client, self.stream_url.clone(), self.headers()).into()
+            }
+            _ => self.http_request(client).into(),
+        }
+    }
+}
+
+/// Signed stream URLs (e.g. googlevideo) carry their expiry as a unix
+/// timestamp in the `expire` query parameter. The track must be startable and
+/// finishable before that, so its duration is taken off the lifetime.
+fn expiry_for(stream_url: &str, duration: Option<f64>) -> u64 {
+    let now = get_timestamp();
+    let default_expiry = now + CONFIG.cache.track_cache_ttl_seconds;
+
+    let signed_expiry = Url::parse(stream_url).ok().and_then(|url| {
+        url.query_pairs()
+            .find(|(key, _)| key == "expire")
+            .and_then(|(_, value)| value.parse::<u64>().ok())
+    });
+
+    match signed_expiry {
+        Some(expire) => {
+            let margin = STREAM_URL_EXPIRY_MARGIN_SECONDS + duration.unwrap_or(0.0) as u64;
+            expire.saturating_sub(margin).min(default_expiry)
+        }
+        None => default_expiry,
+    }
+}
+
+/// The track id yt-dlp would report for a YouTube video link, read straight
+/// from the URL. Returns `None` for anything that needs yt-dlp to identify.
+pub fn track_id_for_url(link: &str) -> Option<String> {
+    let url = Url::parse(link).ok()?;
+    let id = match url.host_str()? {
+        "www.youtube.com" | "youtube.com" | "m.youtube.com" | "music.youtube.com" => {
+            match url.path() {
+                "/watch" => url
+                    .query_pairs()
+                    .find(|(key, _)| key == "v")
+                    .map(|(_, value)| value.into_owned())?,
+                path => path
+                    .strip_prefix("/shorts/")
+                    .or_else(|| path.strip_prefix("/live/"))?
+                    .to_string(),
+            }
+        }
+        "youtu.be" => url.path_segments()?.next()?.to_string(),
+        _ => return None,
+    };
+
+    let valid = id.len() == 11
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| format!("Youtube-{}", id))
+}
+
+async fn run_ytdlp(query: &str) -> Result<ResolvedTrack> {
+    // Queries come from users; never let one be read as a yt-dlp option.
+    if query.starts_with('-') {
+        return Err(anyhow!("Refusing to resolve a query starting with '-'"));
+    }
+
+    let started = Instant::now();
+    let output = Command::new("yt-dlp")
+        .args(["-j", "-f", "ba[abr>0][vcodec=none]/best", "--no-playlist", "--", query])
+        .output()
+        .await;
+    metrics::record_ytdlp(
+        "resolve",
+        started,
+        output.as_ref().is_ok_and(|output| output.status.success()),
+    );
+    let output = output.context("Failed to run yt-dlp")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "yt-dlp failed to resolve track: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let first_line = output
+        .stdout
+        .split(|&b| b == b'\n')
+        .find(|line| !line.is_empty())
+        .context("yt-dlp returned no results")?;
+    let parsed: YtDlpOutput = serde_json::from_slice(first_line)
+        .context("Failed to parse yt-dlp output")?;
+
+    let track_id = match (&parsed.extractor_key, &parsed.id) {
+        (Some(extractor), Some(id)) => Some(format!("{}-{}", extractor, id)),
+        _ => None,
+    };
+
+    Ok(ResolvedTrack {
+        track_id,
+        ext: parsed.ext,
+        expires_at: expiry_for(&parsed.url, parsed.duration),
+        stream_url: parsed.url,
+        http_headers: parsed.http_headers,
+        filesize: parsed.filesize,
+        protocol: parsed.protocol,
+        title: parsed.title,
+        artist: parsed.artist.or(parsed.uploader),
+        duration: parsed.duration,
+        thumbnail: parsed.thumbnail,
+        source_url: parsed.webpage_url,
+    })
+}
+
+/// Resolved-track cache keyed by the yt-dlp query (URL or `ytsearch1:` term),
+/// held in memory and mirrored to Redis when `REDIS_URL` is configured.
+pub struct TrackCache {
+    entries: Mutex<HashMap<String, ResolvedTrack>>,
+    in_flight: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    state: Option<State>,
+    max_entries: usize,
+}
+
+impl TrackCache {
+    pub fn new(state: Option<State>, max_entries: usize) -> Self {
+        TrackCache {
+            entries: Mutex::new(HashMap::new()),
+            in_flight: Mutex::new(HashMap::new()),
+            state,
+            max_entries: max_entries.max(1),
+        }
+    }
+
+    /// Keeps an entry in memory, dropping expired entries first and then the
+    /// ones closest to expiry so the map stays within `max_entries`.
+    async fn remember(&self, query: &str, track: &ResolvedTrack) {
+        let mut entries = self.entries.lock().await;
+        if !entries.contains_key(query) && entries.len() >= self.max_entries {
+            entries.retain(|_, track| !track.is_expired());
+            while entries.len() >= self.max_entries {
+                let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, track)| track.expires_at)
+                    .map(|(key, _)| key.clone())
+                else {
+                    break;
+                };
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(query.to_string(), track.clone());
+    }
+
+    async fn lookup(&self, query: &str) -> Option<ResolvedTrack> {
+        {
+            let mut entries = self.entries.lock().await;
+            match entries.get(query) {
+                Some(track) if !track.is_expired() => return Some(track.clone()),
+                Some(_) => {
+                    entries.remove(query);
+                }
+                None => {}
+            }
+        }
+
+        let state = self.state.as_ref()?;
+        let key = format!("{}{}", TRACK_CACHE_KEY_PREFIX, query);
+        let cached = match state.get(&key).await {
+            Ok(cached) => cached?,
+            Err(e) => {
+                warn!("Failed to read track cache from Redis: {}", e);
+                return None;
+            }
+        };
+
+        let track: ResolvedTrack = serde_json::from_str(&cached).ok()?;
+        if track.is_expired() {
+            return None;
+        }
+
+        self.remember(query, &track).await;
+        Some(track)
+    }
+
+    async fn store(&self, query: &str, track: &ResolvedTrack) {
+        self.remember(query, track).await;
+
+        let Some(state) = self.state.as_ref() else {
+            return;
+        };
+        let ttl = track.expires_at.saturating_sub(get_timestamp());
+        if ttl == 0 {
+            return;
+        }
+        match serde_json::to_string(track) {
+            Ok(value) => {
+                let key = format!("{}{}", TRACK_CACHE_KEY_PREFIX, query);
+                if let Err(e) = state.set_with_expiry(&key, &value, ttl).await {
+                    warn!("Failed to write track cache to Redis: {}", e);
+                }
+            }
+            Err(e) => error!("Failed to serialize resolved track: {}", e),
+        }
+    }
+
+    /// Returns the cached resolution for `query`, running yt-dlp once if it
+    /// is missing or expired. Concurrent callers for the same query share
+    /// the single yt-dlp invocation.
+    pub async fn resolve(&self, query: &str) -> Result<ResolvedTrack> {
+        if let Some(track) = self.lookup(query).await {
+            debug!("Track cache hit: {}", query);
+            return Ok(track);
+        }
+
+        let lock = self
+            .in_flight
+            .lock()
+            .await
+            .entry(query.to_string())
+            .or_default()
+            .clone();
+        let _guard = lock.lock().await;
+
+        let result = match self.lookup(query).await {
+            Some(track) => Ok(track),
+            None => {
+                debug!("Track cache miss: {}", query);
+                let resolved = telemetry::in_span(
+                    "yt-dlp resolve",
+                    vec![KeyValue::new("ravalink.query", query.to_string())],
+                    run_ytdlp(query),
+                )
+                .await;
+                if let Ok(track) = &resolved {
+                    self.store(query, track).await;
+                }
+                resolved
+            }
+        };
+
+        self.in_flight.lock().await.remove(query);
+        result
+    }
+
+    /// Resolves upcoming queue entries in the background so they start
+    /// without waiting on yt-dlp.
+    pub fn preresolve(&'static self, queries: Vec<String>) {
+        for query in queries {
+            tokio::spawn(async move {
+                if let Err(e) = self.resolve(&query).await {
+                    warn!("Failed to pre-resolve {}: {}", query, e);
+                }
+            });
+        }
+    }
+}
+
+pub static TRACK_CACHE: Lazy<TrackCache> = Lazy::new(|| {
+    let state = CONFIG.redis_url.as_deref().and_then(|url| match StateClient::new(url) {
+        Ok(client) => Some(State::new(client)),
+        Err(e) => {
+            error!("Failed to initialize Redis track cache, using memory only: {}", e);
+            None
+        }
+    });
+    TrackCache::new(state, CONFIG.cache.track_cache_max_entries)
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(expires_at: u64) -> ResolvedTrack {
+        ResolvedTrack {
+            track_id: None,
+            ext: None,
+            stream_url: "https://example.com/audio".to_string(),
+            http_headers: HashMap::new(),
+            filesize: None,
+            protocol: None,
+            title: None,
+            artist: None,
+            duration: None,
+            thumbnail: None,
+            source_url: None,
+            expires_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn evicts_expired_then_soonest_to_expire() {
+        let cache = TrackCache::new(None, 2);
+        let now = get_timestamp();
+
+        cache.remember("expired", &track(now - 1)).await;
+        cache.remember("late", &track(now + 600)).await;
+        cache.remember("soon", &track(now + 60)).await;
+        assert_eq!(cache.entries.lock().await.len(), 2);
+        assert!(!cache.entries.lock().await.contains_key("expired"));
+
+        cache.remember("new", &track(now + 300)).await;
+        let entries = cache.entries.lock().await;
+        assert!(entries.contains_key("late"));
+        assert!(entries.contains_key("new"));
+        assert!(!entries.contains_key("soon"));
+    }
+
+    #[test]
+    fn reads_youtube_track_ids_from_urls() {
+        for url in [
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://music.youtube.com/watch?v=dQw4w9WgXcQ&feature=share",
+            "https://youtu.be/dQw4w9WgXcQ?t=42",
+            "https://www.youtube.com/shorts/dQw4w9WgXcQ",
+        ] {
+            assert_eq!(track_id_for_url(url).as_deref(), Some("Youtube-dQw4w9WgXcQ"), "{}", url);
+        }
+        assert_eq!(track_id_for_url("https://www.youtube.com/watch?v=short"), None);
+        assert_eq!(track_id_for_url("https://soundcloud.com/artist/track"), None);
+        assert_eq!(track_id_for_url("ytsearch1:Fleetwood Mac - Dreams"), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_queries_that_look_like_options() {
+        let error = run_ytdlp("--exec=touch /tmp/pwned").await.unwrap_err();
+        assert!(error.to_string().contains("starting with '-'"));
+    }
+}
@@ -1,14 +1,19 @@
-use std::{collections::VecDeque, fmt};
-use rdkafka::producer::FutureProducer;
-use songbird::tracks::TrackHandle;
+use std::collections::VecDeque;
 use std::sync::Arc;
-use tokio::sync::{broadcast::{self}, Mutex};
+use std::time::Duration;
+use serde_derive::Serialize;
 use std::num::NonZero;
+use crate::resolver::ResolvedEntry;
+use crate::worker::events::EventBus;
+use crate::worker::track_cache::ResolvedTrack;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum ServerEventType {
+    TrackStarted { track: ResolvedTrack },
     TrackError { error: String },
     TrackEnded,
+    ChannelMoved { from: NonZero<u64>, to: NonZero<u64> },
 }
 
 #[derive(Clone, Debug)]
@@ -16,50 +21,120 @@ pub enum ServerMessage{
     Event(ServerEventType),
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct ServerIPCData{
     pub message: ServerMessage,
     pub guild_id: NonZero<u64>,
     pub job_id: String,
-    pub producer: Option<Arc<Mutex<FutureProducer>>>,
+    /// Whether the event is published on the transport. Off for jobs from
+    /// the in-process front ends, which read events from the bus instead.
+    pub publish: bool,
 }
 
-// Manual Debug implementation
-impl fmt::Debug for ServerIPCData {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("ServerIPCData")
-            .field("message", &self.message)
-            .field("guild_id", &self.guild_id)
-            .field("job_id", &self.job_id)
-            // You can either omit the producer or print a custom message instead
-            .field("producer", &"FutureProducer omitted")
-            .finish()
+impl ServerIPCData {
+    pub fn name(&self) -> &'static str {
+        let ServerMessage::Event(event_type) = &self.message;
+        match event_type {
+            ServerEventType::TrackStarted { .. } => "track_started",
+            ServerEventType::TrackError { .. } => "track_error",
+            ServerEventType::TrackEnded => "track_ended",
+            ServerEventType::ChannelMoved { .. } => "channel_moved",
+        }
     }
 }
 
 pub struct ServerIPC {
-    pub sender: Arc<broadcast::Sender<ServerIPCData>>,
-    pub receiver: broadcast::Receiver<ServerIPCData>,
+    pub bus: Arc<EventBus>,
+}
+
+#[derive(Clone, Debug)]
+pub enum TrackSource {
+    Url(String),
+    Search(String),
+}
+
+impl TrackSource {
+    /// The argument handed to yt-dlp for this source.
+    pub fn query(&self) -> &str {
+        match self {
+            TrackSource::Url(url) => url,
+            TrackSource::Search(query) => query,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct QueuedTrack {
+    pub source: TrackSource,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub isrc: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+impl QueuedTrack {
+    pub fn from_url(url: String) -> Self {
+        QueuedTrack {
+            source: TrackSource::Url(url),
+            title: None,
+            artist: None,
+            isrc: None,
+            duration: None,
+        }
+    }
+}
+
+impl From<ResolvedEntry> for QueuedTrack {
+    fn from(entry: ResolvedEntry) -> Self {
+        QueuedTrack {
+            source: TrackSource::Search(format!("ytsearch1:{}", entry.search_query())),
+            title: Some(entry.title),
+            artist: Some(entry.artist),
+            isrc: entry.isrc,
+            duration: entry.duration,
+        }
+    }
 }
 
-struct GuildQueue {
-    track_queue: VecDeque<TrackHandle>,
-    is_playing: bool,
+/// Outcome of a Play request that expanded into several queued tracks.
+#[derive(Clone, Debug)]
+pub struct QueueSummary {
+    pub count: usize,
+    pub duration: Duration,
+}
+
+impl QueueSummary {
+    pub fn from_tracks(tracks: &[QueuedTrack]) -> Self {
+        QueueSummary {
+            count: tracks.len(),
+            duration: tracks.iter().filter_map(|t| t.duration).sum(),
+        }
+    }
+}
+
+pub struct GuildQueue {
+    pub track_queue: VecDeque<QueuedTrack>,
+    pub is_playing: bool,
 }
 
 impl GuildQueue {
-    fn new() -> Self {
+    pub fn new() -> Self {
         GuildQueue {
             track_queue: VecDeque::new(),
             is_playing: false,
         }
     }
 
-    fn add_track(&mut self, track: TrackHandle) {
+    pub fn add_track(&mut self, track: QueuedTrack) {
         self.track_queue.push_back(track);
     }
 
-    fn next_track(&mut self) -> Option<TrackHandle> {
+    pub fn next_track(&mut self) -> Option<QueuedTrack> {
         self.track_queue.pop_front()
     }
+
+    pub fn clear(&mut self) {
+        self.track_queue.clear();
+        self.is_playing = false;
+    }
 }
\ No newline at end of file
@@ -0,0 +1,64 @@
+use log::{debug, warn};
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::time::timeout;
+
+use crate::utils::config::CONFIG;
+use crate::utils::constants::{WEBHOOK_MAX_IN_FLIGHT, WEBHOOK_TIMEOUT_SECONDS};
+use crate::utils::helpers::get_timestamp;
+use crate::worker::types::{ServerIPCData, ServerMessage};
+
+/// Posts every worker event to each of the `EVENT_WEBHOOK_URLS`. Deliveries
+/// run in their own tasks and are abandoned after the timeout, so a slow
+/// webhook never holds back the event bus. Up to `WEBHOOK_MAX_IN_FLIGHT`
+/// deliveries run at once; past that, events wait for one to finish.
+pub async fn run(mut events: mpsc::Receiver<ServerIPCData>) {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECONDS))
+        .build()
+        .unwrap_or_default();
+    let in_flight = Arc::new(Semaphore::new(WEBHOOK_MAX_IN_FLIGHT));
+
+    while let Some(event) = events.recv().await {
+        let ServerMessage::Event(event_type) = &event.message;
+        let body = json!({
+            "job_id": event.job_id,
+            "guild_id": event.guild_id.to_string(),
+            "timestamp": get_timestamp(),
+            "event": event_type,
+        });
+
+        for url in &CONFIG.events.webhook_urls {
+            let Ok(permit) = in_flight.clone().acquire_owned().await else {
+                return;
+            };
+            let client = client.clone();
+            let url = url.clone();
+            let body = body.clone();
+            let name = event.name();
+            tokio::spawn(async move {
+                deliver(&client, &url, &body, name).await;
+                drop(permit);
+            });
+        }
+    }
+}
+
+async fn deliver(client: &Client, url: &str, body: &Value, name: &str) {
+    let posted = timeout(
+        Duration::from_secs(WEBHOOK_TIMEOUT_SECONDS),
+        client.post(url).json(body).send(),
+    )
+    .await;
+    match posted {
+        Ok(Ok(response)) => match response.error_for_status() {
+            Ok(_) => debug!("Posted {} event to webhook {}", name, url),
+            Err(e) => warn!("Failed to post {} event to webhook {}: {}", name, url, e),
+        },
+        Ok(Err(e)) => warn!("Failed to post {} event to webhook {}: {}", name, url, e),
+        Err(_) => warn!("Timed out posting {} event to webhook {}", name, url),
+    }
+}